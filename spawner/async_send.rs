@@ -0,0 +1,99 @@
+//! Runtime-agnostic futures for controlling programs from asynchronous code.
+//!
+//! The futures don't depend on any particular executor, so they can be awaited from tokio,
+//! async-std or a hand-written `block_on`.
+
+use crate::pipe::WritePipe;
+use crate::{Error, ProgramMessage, Result};
+
+use std::future::Future;
+use std::io::Write;
+use std::pin::Pin;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+/// An asynchronous wrapper around the `Sender<ProgramMessage>` passed to `Program::msg_receiver`.
+#[derive(Clone)]
+pub struct AsyncSender(Sender<ProgramMessage>);
+
+/// The future returned by [`AsyncSender::send`].
+///
+/// [`AsyncSender::send`]: struct.AsyncSender.html#method.send
+pub struct SendFuture(Option<Result<()>>);
+
+/// The future returned by [`write`]. Resolves to the pipe once all data is written.
+///
+/// [`write`]: fn.write.html
+pub struct WriteFuture(Arc<Mutex<WriteState>>);
+
+struct WriteState {
+    result: Option<Result<WritePipe>>,
+    waker: Option<Waker>,
+}
+
+impl AsyncSender {
+    pub fn new(sender: Sender<ProgramMessage>) -> Self {
+        Self(sender)
+    }
+
+    /// Sends the message to the program's supervisor. The channel is unbounded, so the returned
+    /// future is ready immediately and fails only if the supervisor has already finished.
+    pub fn send(&self, msg: ProgramMessage) -> SendFuture {
+        SendFuture(Some(
+            self.0
+                .send(msg)
+                .map_err(|_| Error::from("Program has already finished")),
+        ))
+    }
+}
+
+impl Future for SendFuture {
+    type Output = Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, _: &mut Context) -> Poll<Self::Output> {
+        Poll::Ready(self.0.take().expect("SendFuture polled after completion"))
+    }
+}
+
+/// Writes `data` to the pipe, e.g. to the stdin of a program.
+///
+/// Writing to a pipe blocks until the reader consumes the data, so the write is performed on a
+/// separate thread which wakes the future once it's done. The write starts immediately, even if
+/// the future is never polled.
+pub fn write(mut pipe: WritePipe, data: Vec<u8>) -> WriteFuture {
+    let state = Arc::new(Mutex::new(WriteState {
+        result: None,
+        waker: None,
+    }));
+    let thread_state = state.clone();
+    thread::spawn(move || {
+        let result = pipe
+            .write_all(&data)
+            .and_then(|_| pipe.flush())
+            .map(|_| pipe)
+            .map_err(Error::from);
+        let mut state = thread_state.lock().unwrap();
+        state.result = Some(result);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    });
+    WriteFuture(state)
+}
+
+impl Future for WriteFuture {
+    type Output = Result<WritePipe>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mut state = self.0.lock().unwrap();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
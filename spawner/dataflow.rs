@@ -1,9 +1,10 @@
-use crate::pipe::{ReadPipe, WritePipe};
+use crate::pipe::{ReadPipe, StallWatch, WritePipe};
 use crate::{Error, Result};
 
 use std::collections::HashMap;
+use std::fmt;
 use std::io::{BufRead, BufReader, BufWriter, Write};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
 use std::thread::{self, JoinHandle};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -12,6 +13,18 @@ pub struct SourceId(usize);
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct DestinationId(usize);
 
+impl fmt::Display for SourceId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "src{}", self.0)
+    }
+}
+
+impl fmt::Display for DestinationId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "dst{}", self.0)
+    }
+}
+
 pub trait SourceReader: Send {
     fn read(&mut self, src: &mut ReadPipe, connections: &mut [Connection]) -> Result<()>;
 }
@@ -33,6 +46,20 @@ pub struct Connection {
     dst_id: DestinationId,
 }
 
+/// A handle for writing to a destination from outside of the dataflow, e.g. to send messages
+/// to a program's stdin. Clones share the same destination.
+#[derive(Clone)]
+pub struct DestinationWriter(Arc<Mutex<ConnectionKind>>);
+
+/// A `DestinationWriter` that doesn't keep the destination open.
+#[derive(Clone)]
+pub struct WeakDestinationWriter(Weak<Mutex<ConnectionKind>>);
+
+/// Exclusive access to a destination, see [`DestinationWriter::lock`].
+///
+/// [`DestinationWriter::lock`]: struct.DestinationWriter.html#method.lock
+pub struct DestinationGuard<'a>(MutexGuard<'a, ConnectionKind>);
+
 pub struct Destination {
     connection_kind: Arc<Mutex<ConnectionKind>>,
     edges: Vec<SourceId>,
@@ -96,6 +123,52 @@ impl Connection {
     }
 }
 
+impl DestinationWriter {
+    /// Writes the whole message under a single lock acquisition, so messages written
+    /// concurrently through other handles or connections never interleave with it.
+    pub fn write_message(&self, msg: &[u8]) -> Result<()> {
+        let mut guard = self.lock();
+        guard.write_all(msg)?;
+        guard.flush().map_err(Error::from)
+    }
+
+    /// Locks the destination for raw writes. Other writers are blocked only while the guard is
+    /// alive, so a message written in parts through separate guards may be interleaved with
+    /// messages from other threads. Prefer `write_message` unless this is intended.
+    pub fn lock(&self) -> DestinationGuard<'_> {
+        DestinationGuard(self.0.lock().unwrap())
+    }
+
+    /// Returns a handle that can be used while the destination is open, the destination is
+    /// closed once every connection to it and every `DestinationWriter` is gone.
+    pub fn downgrade(&self) -> WeakDestinationWriter {
+        WeakDestinationWriter(Arc::downgrade(&self.0))
+    }
+}
+
+impl WeakDestinationWriter {
+    /// Returns `None` if the destination has been closed.
+    pub fn upgrade(&self) -> Option<DestinationWriter> {
+        self.0.upgrade().map(DestinationWriter)
+    }
+}
+
+impl<'a> Write for DestinationGuard<'a> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match *self.0 {
+            ConnectionKind::Pipe(ref mut p) => p.write(buf),
+            ConnectionKind::File(ref mut f) => f.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match *self.0 {
+            ConnectionKind::Pipe(ref mut p) => p.flush(),
+            ConnectionKind::File(ref mut f) => f.flush(),
+        }
+    }
+}
+
 impl Source {
     pub fn edges(&self) -> &[DestinationId] {
         &self.edges
@@ -121,6 +194,14 @@ impl Destination {
     pub fn edges(&self) -> &[SourceId] {
         &self.edges
     }
+
+    /// Reports the writes to the destination pipe that are blocked for too long, see
+    /// `WritePipe::set_stall_watch`. Files are not watched.
+    pub fn set_stall_watch(&self, watch: StallWatch) {
+        if let ConnectionKind::Pipe(ref mut p) = *self.connection_kind.lock().unwrap() {
+            p.set_stall_watch(watch);
+        }
+    }
 }
 
 impl Graph {
@@ -185,6 +266,14 @@ impl Graph {
         self.dsts.get(&id)
     }
 
+    /// The destination must not be removed while the writer is alive, optimizations of such
+    /// destinations have to be disabled.
+    pub(crate) fn destination_writer(&self, id: DestinationId) -> Option<DestinationWriter> {
+        self.dsts
+            .get(&id)
+            .map(|dst| DestinationWriter(dst.connection_kind.clone()))
+    }
+
     pub fn remove_destination(&mut self, id: DestinationId) -> Option<WritePipe> {
         self.dsts.remove(&id).map(|dst| {
             for edge in dst.edges.iter() {
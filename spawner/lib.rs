@@ -22,6 +22,8 @@ cfg_if! {
     }
 }
 
+#[cfg(feature = "async")]
+pub mod async_send;
 pub mod dataflow;
 pub mod pipe;
 pub mod process;
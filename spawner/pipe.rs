@@ -1,23 +1,98 @@
 use crate::sys::pipe as imp;
 use crate::sys::{FromInner, IntoInner};
-use crate::Result;
+use crate::{Error, Result};
 
-use std::io::{self, Read, Write};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 /// A reference to the reading end of a pipe or to the file opened in read mode.
 ///
 /// [`create`]: fn.create.html
 /// [`ReadPipe::open`]: struct.ReadPipe.html#method.open
 #[derive(Debug)]
-pub struct ReadPipe(imp::ReadPipe);
+pub struct ReadPipe {
+    inner: imp::ReadPipe,
+    metrics: Option<Arc<PipeMetrics>>,
+    /// Data read past the line by `read_until_deadline`, returned first by the next reads.
+    unread: Vec<u8>,
+}
 
 /// A reference to the writing end of a pipe or to the file opened in write mode.
 ///
 /// [`create`]: fn.create.html
 /// [`WritePipe::open`]: struct.WritePipe.html#method.open
 #[derive(Debug)]
-pub struct WritePipe(imp::WritePipe);
+pub struct WritePipe {
+    inner: imp::WritePipe,
+    metrics: Option<Arc<PipeMetrics>>,
+    stall_watch: Option<StallWatch>,
+}
+
+/// Reports writes to a pipe that can't proceed because the reader doesn't consume the data,
+/// e.g. a stuck agent, see [`WritePipe::set_stall_watch`].
+///
+/// [`WritePipe::set_stall_watch`]: struct.WritePipe.html#method.set_stall_watch
+#[derive(Clone)]
+pub struct StallWatch {
+    threshold: Duration,
+    on_stall: Arc<dyn Fn(Duration) + Send + Sync>,
+}
+
+/// A buffered [`ReadPipe`] for line-oriented reads, e.g. `lines()` or `read_line`.
+///
+/// The buffer holds [`BufReadPipe::DEFAULT_CAPACITY`] bytes unless given otherwise, lines
+/// longer than that are still read whole. Once the writing end is closed and the buffer is
+/// drained, reads return zero bytes and `lines()` ends, yielding the last line even if it is
+/// not terminated by `\n`.
+///
+/// [`ReadPipe`]: struct.ReadPipe.html
+/// [`BufReadPipe::DEFAULT_CAPACITY`]: struct.BufReadPipe.html#associatedconstant.DEFAULT_CAPACITY
+#[derive(Debug)]
+pub struct BufReadPipe(BufReader<ReadPipe>);
+
+/// Counters of the I/O performed through pipes, which a monitoring thread can sample at any
+/// time. The same counters can be shared by several pipes.
+///
+/// Only reads and writes done through [`ReadPipe`] and [`WritePipe`] are counted, the I/O of
+/// a child process on its own end of the pipe is not.
+///
+/// [`ReadPipe`]: struct.ReadPipe.html
+/// [`WritePipe`]: struct.WritePipe.html
+#[derive(Debug, Default)]
+pub struct PipeMetrics {
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    write_calls: AtomicU64,
+}
+
+/// The largest write that a pipe accepts at once as soon as it is writable.
+const ATOMIC_WRITE_SIZE: usize = 4096;
+
+/// The outcome of [`ReadPipe::read_to_buf`].
+///
+/// [`ReadPipe::read_to_buf`]: struct.ReadPipe.html#method.read_to_buf
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReadResult {
+    /// The given number of bytes has been read. It is zero only if the buffer is empty.
+    Data(usize),
+    /// The writing end of the pipe is closed and there is nothing left to read.
+    Eof,
+}
+
+/// A line read by [`ReadPipe::read_until_deadline`].
+///
+/// [`ReadPipe::read_until_deadline`]: struct.ReadPipe.html#method.read_until_deadline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadUntil {
+    /// The bytes read, including the trailing `\n` if it has arrived.
+    pub data: Vec<u8>,
+    /// Whether the timeout has expired before the line was complete.
+    pub timed_out: bool,
+}
 
 /// Creates a new pipe returning the [`ReadPipe`] and [`WritePipe`] pair.
 ///
@@ -25,65 +100,265 @@ pub struct WritePipe(imp::WritePipe);
 /// [`WritePipe`]: struct.WritePipe.html
 pub fn create() -> Result<(ReadPipe, WritePipe)> {
     let (r, w) = imp::create()?;
-    Ok((ReadPipe(r), WritePipe(w)))
+    Ok((ReadPipe::from_inner(r), WritePipe::from_inner(w)))
+}
+
+impl PipeMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written.load(Ordering::Relaxed)
+    }
+
+    pub fn write_calls(&self) -> u64 {
+        self.write_calls.load(Ordering::Relaxed)
+    }
+}
+
+impl StallWatch {
+    /// Calls `on_stall` every `threshold` while a write is blocked, with the time the write
+    /// has been blocked so far.
+    pub fn new<F>(threshold: Duration, on_stall: F) -> Self
+    where
+        F: Fn(Duration) + Send + Sync + 'static,
+    {
+        Self {
+            threshold,
+            on_stall: Arc::new(on_stall),
+        }
+    }
+
+    fn wait(&self, pipe: &imp::WritePipe) -> io::Result<()> {
+        let mut stalled_for = Duration::from_secs(0);
+        while !pipe.wait_writable(self.threshold)? {
+            stalled_for += self.threshold;
+            (self.on_stall)(stalled_for);
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for StallWatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StallWatch")
+            .field("threshold", &self.threshold)
+            .finish()
+    }
 }
 
 impl ReadPipe {
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
-        imp::ReadPipe::open(path).map(Self)
+        imp::ReadPipe::open(path).map(Self::from_inner)
     }
 
     pub fn null() -> Result<Self> {
-        imp::ReadPipe::null().map(Self)
+        imp::ReadPipe::null().map(Self::from_inner)
+    }
+
+    /// Counts every byte read from this pipe in `metrics`.
+    pub fn with_metrics(mut self, metrics: Arc<PipeMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Reads data into `buf`, blocking until at least one byte is available or the writing
+    /// end is closed. Unlike `Read::read`, end of file is reported explicitly as
+    /// [`ReadResult::Eof`]. Interrupted reads are retried.
+    ///
+    /// [`ReadResult::Eof`]: enum.ReadResult.html#variant.Eof
+    pub fn read_to_buf(&mut self, buf: &mut [u8]) -> Result<ReadResult> {
+        if buf.is_empty() {
+            return Ok(ReadResult::Data(0));
+        }
+        loop {
+            match self.read(buf) {
+                Ok(0) => return Ok(ReadResult::Eof),
+                Ok(n) => return Ok(ReadResult::Data(n)),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Reads a line, e.g. an agent's move, waiting for it at most `timeout`. The line is
+    /// returned whole if its `\n` arrives in time, otherwise the part read so far is returned
+    /// with `timed_out` set. If the writing end is closed first, the partial line is returned
+    /// without a timeout. Data past the `\n` is kept for the next reads.
+    pub fn read_until_deadline(&mut self, timeout: Duration) -> Result<ReadUntil> {
+        let deadline = Instant::now() + timeout;
+        let mut data = Vec::new();
+        let mut buf = [0u8; BufReadPipe::DEFAULT_CAPACITY];
+        loop {
+            if let Some(pos) = self.unread.iter().position(|&b| b == b'\n') {
+                data.extend(self.unread.drain(..=pos));
+                return Ok(ReadUntil {
+                    data,
+                    timed_out: false,
+                });
+            }
+            data.append(&mut self.unread);
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::from_secs(0) || !self.inner.wait_readable(remaining)? {
+                return Ok(ReadUntil {
+                    data,
+                    timed_out: true,
+                });
+            }
+            match self.read_to_buf(&mut buf)? {
+                ReadResult::Eof => {
+                    return Ok(ReadUntil {
+                        data,
+                        timed_out: false,
+                    })
+                }
+                ReadResult::Data(n) => self.unread.extend_from_slice(&buf[..n]),
+            }
+        }
     }
 }
 
 impl IntoInner<imp::ReadPipe> for ReadPipe {
     fn into_inner(self) -> imp::ReadPipe {
-        self.0
+        self.inner
     }
 }
 
 impl FromInner<imp::ReadPipe> for ReadPipe {
     fn from_inner(inner: imp::ReadPipe) -> Self {
-        Self(inner)
+        Self {
+            inner,
+            metrics: None,
+            unread: Vec::new(),
+        }
     }
 }
 
 impl Read for ReadPipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.unread.is_empty() {
+            let n = (&self.unread[..]).read(buf)?;
+            self.unread.drain(..n);
+            return Ok(n);
+        }
+        let n = self.inner.read(buf)?;
+        if let Some(ref m) = self.metrics {
+            m.bytes_read.fetch_add(n as u64, Ordering::Relaxed);
+        }
+        Ok(n)
+    }
+}
+
+impl BufReadPipe {
+    pub const DEFAULT_CAPACITY: usize = 8192;
+
+    pub fn new(pipe: ReadPipe) -> Self {
+        Self::with_capacity(Self::DEFAULT_CAPACITY, pipe)
+    }
+
+    pub fn with_capacity(capacity: usize, pipe: ReadPipe) -> Self {
+        BufReadPipe(BufReader::with_capacity(capacity, pipe))
+    }
+
+    /// Returns the underlying pipe, any buffered data is lost.
+    pub fn into_inner(self) -> ReadPipe {
+        self.0.into_inner()
+    }
+}
+
+impl Read for BufReadPipe {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         self.0.read(buf)
     }
 }
 
+impl BufRead for BufReadPipe {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.0.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.0.consume(amt)
+    }
+}
+
 impl WritePipe {
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
-        imp::WritePipe::open(path).map(Self)
+        imp::WritePipe::open(path).map(Self::from_inner)
     }
 
     pub fn null() -> Result<Self> {
-        imp::WritePipe::null().map(Self)
+        imp::WritePipe::null().map(Self::from_inner)
+    }
+
+    /// Counts every write call and byte written to this pipe in `metrics`.
+    pub fn with_metrics(mut self, metrics: Arc<PipeMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Reports writes that are blocked for longer than the threshold of `watch`. Data is then
+    /// written in chunks of at most 4096 bytes, so that no single write blocks.
+    pub fn set_stall_watch(&mut self, watch: StallWatch) {
+        self.stall_watch = Some(watch);
+    }
+
+    /// Writes the whole message, repeating short and interrupted writes, so that the reader
+    /// never gets a part of it unless an error occurs.
+    pub fn write_all_msg(&mut self, msg: &[u8]) -> Result<()> {
+        let mut rest = msg;
+        while !rest.is_empty() {
+            match self.write(rest) {
+                Ok(0) => return Err(Error::from(io::Error::from(io::ErrorKind::WriteZero))),
+                Ok(n) => rest = &rest[n..],
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(Error::from(e)),
+            }
+        }
+        Ok(())
     }
 }
 
 impl IntoInner<imp::WritePipe> for WritePipe {
     fn into_inner(self) -> imp::WritePipe {
-        self.0
+        self.inner
     }
 }
 
 impl FromInner<imp::WritePipe> for WritePipe {
     fn from_inner(inner: imp::WritePipe) -> Self {
-        Self(inner)
+        Self {
+            inner,
+            metrics: None,
+            stall_watch: None,
+        }
     }
 }
 
 impl Write for WritePipe {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.0.write(buf)
+        let buf = match self.stall_watch {
+            Some(ref watch) => {
+                watch.wait(&self.inner)?;
+                &buf[..buf.len().min(ATOMIC_WRITE_SIZE)]
+            }
+            None => buf,
+        };
+        let n = self.inner.write(buf)?;
+        if let Some(ref m) = self.metrics {
+            m.write_calls.fetch_add(1, Ordering::Relaxed);
+            m.bytes_written.fetch_add(n as u64, Ordering::Relaxed);
+        }
+        Ok(n)
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.0.flush()
+        self.inner.flush()
     }
 }
@@ -52,6 +52,14 @@ pub struct GroupNetwork {
     pub active_connections: usize,
 }
 
+/// A process that has been seen in the group, see `ResourceUsage::processes`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GroupProcess {
+    pub pid: u32,
+    /// Peak memory usage in bytes, `None` if it is not tracked per process.
+    pub peak_memory: Option<u64>,
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum OsLimit {
     Memory,
@@ -123,6 +131,10 @@ impl ProcessInfo {
 }
 
 impl Process {
+    pub fn pid(&self) -> u32 {
+        self.0.pid()
+    }
+
     pub fn exit_status(&mut self) -> Result<Option<ExitStatus>> {
         self.0.exit_status()
     }
@@ -191,6 +203,13 @@ impl<'a> ResourceUsage<'a> {
     pub fn network(&self) -> Result<Option<GroupNetwork>> {
         self.0.network()
     }
+
+    /// Every process seen in the group by `update` in the order of appearance, including the
+    /// ones that have exited. A process that lives shorter than the interval between updates
+    /// may be missed.
+    pub fn processes(&self) -> Result<Option<Vec<GroupProcess>>> {
+        self.0.processes()
+    }
 }
 
 impl Group {
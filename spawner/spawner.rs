@@ -1,17 +1,20 @@
-use crate::dataflow::{DestinationId, Graph, SourceId, Transmitter, TransmitterResults};
+use crate::dataflow::{
+    DestinationId, DestinationWriter, Graph, SourceId, Transmitter, TransmitterResults,
+};
 use crate::dataflow_analysis::DataflowOptimizer;
-use crate::pipe;
+use crate::pipe::{self, PipeMetrics, ReadPipe, WritePipe};
 use crate::process::{
-    ExitStatus, Group, GroupIo, GroupMemory, GroupNetwork, GroupPidCounters, GroupTimers,
-    ProcessInfo, Stdio,
+    ExitStatus, Group, GroupIo, GroupMemory, GroupNetwork, GroupPidCounters, GroupProcess,
+    GroupTimers, ProcessInfo, Stdio,
 };
 use crate::supervisor::Supervisor;
 use crate::{Error, Result};
 
 use std::collections::HashSet;
 use std::fmt;
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::Receiver;
+use std::sync::mpsc::{Receiver, Sender};
 use std::sync::Arc;
 use std::thread::{self, JoinHandle};
 use std::time::Duration;
@@ -63,6 +66,17 @@ pub enum ProgramMessage {
     StopTimeAccounting,
     ResumeTimeAccounting,
     ResetTime,
+    /// Asks for the current `ProgramStatus`. Nothing is sent back if the program has already
+    /// finished.
+    QueryStatus(Sender<ProgramStatus>),
+}
+
+/// A snapshot of a running program's resource usage.
+#[derive(Copy, Clone, Debug)]
+pub struct ProgramStatus {
+    pub wall_clock_time: Duration,
+    /// Peak memory usage in bytes, if available.
+    pub peak_memory: Option<u64>,
 }
 
 /// Summary information about process's execution.
@@ -74,8 +88,17 @@ pub struct Report {
     pub timers: Option<GroupTimers>,
     pub pid_counters: Option<GroupPidCounters>,
     pub network: Option<GroupNetwork>,
+    /// The processes spawned by the program, see `ResourceUsage::processes`. Empty if the
+    /// platform doesn't track the processes of a group.
+    pub children: Vec<GroupProcess>,
     pub exit_status: ExitStatus,
     pub termination_reason: Option<TerminationReason>,
+    /// The number of bytes the program wrote to stdout, counted as spawner reads them. `None`
+    /// if the stream was handed to the program directly, e.g. when it is discarded or goes
+    /// to a single file, so spawner never reads it.
+    pub stdout_bytes: Option<u64>,
+    /// The same as `stdout_bytes` for stderr. Merged streams are still counted separately.
+    pub stderr_bytes: Option<u64>,
 }
 
 #[derive(Debug)]
@@ -94,16 +117,56 @@ pub struct Program {
     wait_for_children: bool,
 }
 
-#[derive(Copy, Clone)]
+/// Ids of the program's stdio pipes in the session graph. Ids are unique within a session,
+/// so the `Display` form identifies the mapping in logs, e.g. `stdin=dst0 stdout=src0 stderr=src1`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct StdioMapping {
     pub stdin: DestinationId,
     pub stdout: SourceId,
     pub stderr: SourceId,
 }
 
+/// Where a program reads its stdin from, see `StdioMappingBuilder`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputRedirect {
+    /// A source that is already in the session graph, e.g. another program's stdout.
+    Source(SourceId),
+    File(PathBuf),
+    /// Reads nothing, the program sees end of file.
+    Null,
+}
+
+/// Where a program writes its stdout or stderr to, see `StdioMappingBuilder`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputRedirect {
+    /// A destination that is already in the session graph, e.g. another program's stdin.
+    Destination(DestinationId),
+    File(PathBuf),
+    /// Discards the output.
+    Null,
+}
+
+/// Adds a program to a `Session` with its stdio redirected. Each stream may be redirected
+/// to several places, streams that are not redirected are left unconnected.
+#[derive(Debug, Clone, Default)]
+pub struct StdioMappingBuilder {
+    stdin: Vec<InputRedirect>,
+    stdout: Vec<OutputRedirect>,
+    stderr: Vec<OutputRedirect>,
+    merge_stderr: bool,
+}
+
 struct ProgramExt {
     prog: Program,
     stdio: Stdio,
+    stdout_metrics: Arc<PipeMetrics>,
+    stderr_metrics: Arc<PipeMetrics>,
+}
+
+/// The counters of the stdout and stderr pipes that spawner reads.
+struct OutputMetrics {
+    stdout: Option<Arc<PipeMetrics>>,
+    stderr: Option<Arc<PipeMetrics>>,
 }
 
 #[derive(Default)]
@@ -125,6 +188,7 @@ struct SupervisorThread {
 pub struct Run {
     supervisors: Vec<SupervisorThread>,
     mappings: Vec<StdioMapping>,
+    metrics: Vec<OutputMetrics>,
     transmitter: Transmitter,
 }
 
@@ -145,6 +209,16 @@ impl Default for ResourceLimits {
 
 impl std::error::Error for ProgramErrors {}
 
+impl fmt::Display for StdioMapping {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "stdin={} stdout={} stderr={}",
+            self.stdin, self.stdout, self.stderr
+        )
+    }
+}
+
 impl fmt::Display for ProgramErrors {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         for e in self.errors.iter() {
@@ -214,10 +288,16 @@ impl Session {
         let (stdin_r, stdin_w) = pipe::create()?;
         let (stdout_r, stdout_w) = pipe::create()?;
         let (stderr_r, stderr_w) = pipe::create()?;
+        let stdout_metrics = Arc::new(PipeMetrics::new());
+        let stderr_metrics = Arc::new(PipeMetrics::new());
         let mapping = StdioMapping {
             stdin: self.graph.add_destination(stdin_w),
-            stdout: self.graph.add_source(stdout_r),
-            stderr: self.graph.add_source(stderr_r),
+            stdout: self
+                .graph
+                .add_source(stdout_r.with_metrics(stdout_metrics.clone())),
+            stderr: self
+                .graph
+                .add_source(stderr_r.with_metrics(stderr_metrics.clone())),
         };
         self.progs.push(ProgramExt {
             prog: p.into(),
@@ -226,6 +306,8 @@ impl Session {
                 stdout: stdout_w,
                 stderr: stderr_w,
             },
+            stdout_metrics,
+            stderr_metrics,
         });
         self.mappings.push(mapping);
         Ok(mapping)
@@ -239,6 +321,13 @@ impl Session {
         self.ignored_dsts.insert(dst);
     }
 
+    /// Returns a handle for writing directly to the destination. The destination is kept
+    /// as is, its optimization is disabled.
+    pub fn destination_writer(&mut self, dst: DestinationId) -> Option<DestinationWriter> {
+        self.disable_destination_optimization(dst);
+        self.graph.destination_writer(dst)
+    }
+
     pub fn graph_mut(&mut self) -> &mut Graph {
         &mut self.graph
     }
@@ -248,7 +337,23 @@ impl Session {
     }
 
     pub fn run(mut self) -> Result<Run> {
-        self.optimize_io().map(|_| Run {
+        self.optimize_io()?;
+        // Optimized sources are removed from the graph, their pipes are never read.
+        let graph = &self.graph;
+        let metrics = self
+            .progs
+            .iter()
+            .zip(self.mappings.iter())
+            .map(|(p, mapping)| OutputMetrics {
+                stdout: graph
+                    .source(mapping.stdout)
+                    .map(|_| p.stdout_metrics.clone()),
+                stderr: graph
+                    .source(mapping.stderr)
+                    .map(|_| p.stderr_metrics.clone()),
+            })
+            .collect();
+        Ok(Run {
             supervisors: self
                 .progs
                 .into_iter()
@@ -256,6 +361,7 @@ impl Session {
                 .collect(),
             transmitter: self.graph.transmit_data(),
             mappings: self.mappings,
+            metrics,
         })
     }
 
@@ -272,6 +378,111 @@ impl Session {
     }
 }
 
+impl StdioMappingBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn stdin_from(&mut self, redirect: InputRedirect) -> &mut Self {
+        self.stdin.push(redirect);
+        self
+    }
+
+    pub fn stdout_to(&mut self, redirect: OutputRedirect) -> &mut Self {
+        self.stdout.push(redirect);
+        self
+    }
+
+    pub fn stderr_to(&mut self, redirect: OutputRedirect) -> &mut Self {
+        self.stderr.push(redirect);
+        self
+    }
+
+    /// Sends stderr wherever stdout goes. Stdout must be redirected, and stderr must not be
+    /// redirected on its own.
+    pub fn merge_stderr_into_stdout(&mut self) -> &mut Self {
+        self.merge_stderr = true;
+        self
+    }
+
+    /// Adds the program to the session and connects its stdio. Nothing is added if the
+    /// redirections conflict or refer to unknown ids.
+    pub fn build<P>(&self, sess: &mut Session, program: P) -> Result<StdioMapping>
+    where
+        P: Into<Program>,
+    {
+        if self.merge_stderr && !self.stderr.is_empty() {
+            return Err(Error::from(
+                "Stderr cannot be both redirected and merged into stdout",
+            ));
+        }
+        if self.merge_stderr && self.stdout.is_empty() {
+            return Err(Error::from(
+                "Stderr cannot be merged into stdout that is not redirected",
+            ));
+        }
+        for redirect in self.stdin.iter() {
+            if let InputRedirect::Source(id) = *redirect {
+                if sess.graph().source(id).is_none() {
+                    return Err(Error::from(format!("Unknown source '{}'", id)));
+                }
+            }
+        }
+        for redirect in self.stdout.iter().chain(self.stderr.iter()) {
+            if let OutputRedirect::Destination(id) = *redirect {
+                if sess.graph().destination(id).is_none() {
+                    return Err(Error::from(format!("Unknown destination '{}'", id)));
+                }
+            }
+        }
+
+        let mut srcs = Vec::new();
+        for redirect in self.stdin.iter() {
+            srcs.push(match redirect {
+                InputRedirect::Source(id) => *id,
+                InputRedirect::File(path) => sess.graph_mut().add_source(ReadPipe::open(path)?),
+                InputRedirect::Null => sess.graph_mut().add_source(ReadPipe::null()?),
+            });
+        }
+        let stdout_dsts = StdioMappingBuilder::output_destinations(sess, &self.stdout)?;
+        let stderr_dsts = if self.merge_stderr {
+            stdout_dsts.clone()
+        } else {
+            StdioMappingBuilder::output_destinations(sess, &self.stderr)?
+        };
+
+        let mapping = sess.add_program(program)?;
+        let graph = sess.graph_mut();
+        for src in srcs {
+            graph.connect(src, mapping.stdin);
+        }
+        for dst in stdout_dsts {
+            graph.connect(mapping.stdout, dst);
+        }
+        for dst in stderr_dsts {
+            graph.connect(mapping.stderr, dst);
+        }
+        Ok(mapping)
+    }
+
+    fn output_destinations(
+        sess: &mut Session,
+        redirects: &[OutputRedirect],
+    ) -> Result<Vec<DestinationId>> {
+        let mut dsts = Vec::new();
+        for redirect in redirects {
+            dsts.push(match redirect {
+                OutputRedirect::Destination(id) => *id,
+                OutputRedirect::File(path) => sess
+                    .graph_mut()
+                    .add_file_destination(WritePipe::open(path)?),
+                OutputRedirect::Null => sess.graph_mut().add_destination(WritePipe::null()?),
+            });
+        }
+        Ok(dsts)
+    }
+}
+
 impl Drop for FlagGuard {
     fn drop(&mut self) {
         self.0.store(true, Ordering::Release);
@@ -301,7 +512,12 @@ impl SupervisorThread {
         }
     }
 
-    fn wait(self, mapping: StdioMapping, results: &mut TransmitterResults) -> ProgramResult {
+    fn wait(
+        self,
+        mapping: StdioMapping,
+        metrics: OutputMetrics,
+        results: &mut TransmitterResults,
+    ) -> ProgramResult {
         // Collect io errors for this program.
         let mut errs = [mapping.stdout, mapping.stderr]
             .iter()
@@ -323,7 +539,11 @@ impl SupervisorThread {
             })
             .ok();
         if errs.is_empty() {
-            Ok(result.unwrap())
+            // The transmitter has finished, so every byte written has been read.
+            let mut report = result.unwrap();
+            report.stdout_bytes = metrics.stdout.map(|m| m.bytes_read());
+            report.stderr_bytes = metrics.stderr.map(|m| m.bytes_read());
+            Ok(report)
         } else {
             Err(ProgramErrors { errors: errs })
         }
@@ -340,7 +560,10 @@ impl Run {
         self.supervisors
             .into_iter()
             .zip(self.mappings.into_iter())
-            .map(|(supervisor, mapping)| supervisor.wait(mapping, &mut transmitter_results))
+            .zip(self.metrics)
+            .map(|((supervisor, mapping), metrics)| {
+                supervisor.wait(mapping, metrics, &mut transmitter_results)
+            })
             .collect::<Vec<_>>()
     }
 
@@ -1,6 +1,6 @@
 use crate::limit_checker::LimitChecker;
 use crate::process::{Group, OsLimit, Process, ProcessInfo, ResourceUsage, Stdio};
-use crate::{ProgramMessage, Report, ResourceLimits, Result, TerminationReason};
+use crate::{ProgramMessage, ProgramStatus, Report, ResourceLimits, Result, TerminationReason};
 
 use std::sync::mpsc::Receiver;
 use std::thread;
@@ -55,7 +55,9 @@ impl Supervisor {
                 return Ok(report);
             }
 
-            if last_check_time.elapsed() > self.monitor_interval {
+            // Once the group is terminated its reason is final, e.g. a program killed by the
+            // runner must not be reported as exceeding the wall clock time limit while exiting.
+            if self.term_reason.is_none() && last_check_time.elapsed() > self.monitor_interval {
                 last_check_time = Instant::now();
                 if let Some(tr) = self.check_limits(&group, &usage)? {
                     group.terminate()?;
@@ -63,7 +65,7 @@ impl Supervisor {
                 }
             }
 
-            self.handle_messages(&group)?;
+            self.handle_messages(&group, &usage)?;
             thread::sleep(Duration::from_millis(1));
         }
     }
@@ -107,12 +109,20 @@ impl Supervisor {
             timers: usage.timers()?,
             pid_counters,
             network: usage.network()?,
+            children: usage
+                .processes()?
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|p| p.pid != self.process.pid())
+                .collect(),
             exit_status,
             termination_reason: self.term_reason,
+            stdout_bytes: None,
+            stderr_bytes: None,
         }))
     }
 
-    fn handle_messages(&mut self, group: &Group) -> Result<()> {
+    fn handle_messages(&mut self, group: &Group, usage: &ResourceUsage) -> Result<()> {
         let receiver = match &mut self.msg_receiver {
             Some(r) => r,
             None => return Ok(()),
@@ -121,7 +131,8 @@ impl Supervisor {
             match msg {
                 ProgramMessage::Terminate => {
                     group.terminate()?;
-                    self.term_reason = Some(TerminationReason::TerminatedByRunner);
+                    self.term_reason
+                        .get_or_insert(TerminationReason::TerminatedByRunner);
                 }
                 ProgramMessage::Suspend => {
                     if self.process.exit_status()?.is_none() {
@@ -136,6 +147,13 @@ impl Supervisor {
                 ProgramMessage::ResetTime => self.limit_checker.reset_time(),
                 ProgramMessage::StopTimeAccounting => self.limit_checker.stop_time_accounting(),
                 ProgramMessage::ResumeTimeAccounting => self.limit_checker.resume_time_accounting(),
+                ProgramMessage::QueryStatus(sender) => {
+                    // A failed query must not stop the monitoring, the memory is unknown then.
+                    let _ = sender.send(ProgramStatus {
+                        wall_clock_time: self.creation_time.elapsed(),
+                        peak_memory: usage.memory().ok().flatten().map(|m| m.max_usage),
+                    });
+                }
             }
         }
         Ok(())
@@ -1,13 +1,17 @@
 use crate::sys::IntoInner;
 use crate::{Error, Result};
 
+use nix::errno::Errno;
 use nix::fcntl::{fcntl, open, FcntlArg, FdFlag, OFlag};
+use nix::poll::{poll, PollFd, PollFlags};
 use nix::sys::stat::Mode;
 use nix::unistd::{close, pipe, read, write};
 
 use std::io::{self, Read, Write};
+use std::os::raw::c_int;
 use std::os::unix::io::RawFd;
 use std::path::Path;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub struct PipeFd(RawFd);
@@ -62,6 +66,19 @@ impl ReadPipe {
     fn raw(&self) -> RawFd {
         (self.0).0
     }
+
+    /// Returns `true` once a read wouldn't block, which includes a closed writing end.
+    pub fn wait_readable(&self, timeout: Duration) -> io::Result<bool> {
+        let mut fds = [PollFd::new(self.raw(), PollFlags::POLLIN)];
+        let millis = timeout.as_micros().div_ceil(1000).min(c_int::MAX as u128) as c_int;
+        loop {
+            match poll(&mut fds, millis) {
+                Ok(n) => return Ok(n > 0),
+                Err(Errno::EINTR) => continue,
+                Err(e) => return Err(io::Error::from_raw_os_error(e as i32)),
+            }
+        }
+    }
 }
 
 impl Read for ReadPipe {
@@ -95,6 +112,20 @@ impl WritePipe {
     fn raw(&self) -> RawFd {
         (self.0).0
     }
+
+    /// Returns `true` once a write of up to `PIPE_BUF` bytes wouldn't block, which includes a
+    /// closed reading end.
+    pub fn wait_writable(&self, timeout: Duration) -> io::Result<bool> {
+        let mut fds = [PollFd::new(self.raw(), PollFlags::POLLOUT)];
+        let millis = timeout.as_micros().div_ceil(1000).min(c_int::MAX as u128) as c_int;
+        loop {
+            match poll(&mut fds, millis) {
+                Ok(n) => return Ok(n > 0),
+                Err(Errno::EINTR) => continue,
+                Err(e) => return Err(io::Error::from_raw_os_error(e as i32)),
+            }
+        }
+    }
 }
 
 impl Write for WritePipe {
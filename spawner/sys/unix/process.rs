@@ -1,5 +1,6 @@
 use crate::process::{
-    ExitStatus, GroupIo, GroupMemory, GroupNetwork, GroupPidCounters, GroupTimers, OsLimit,
+    ExitStatus, GroupIo, GroupMemory, GroupNetwork, GroupPidCounters, GroupProcess, GroupTimers,
+    OsLimit,
 };
 use crate::sys::unix::missing_decls::{sock_fprog, SECCOMP_MODE_FILTER};
 use crate::sys::unix::pipe::{PipeFd, ReadPipe, WritePipe};
@@ -88,6 +89,8 @@ pub struct ResourceUsage<'a> {
     // Since we have information only about active tasks we need to memorize amount
     // of dead tasks and amount of bytes written by them.
     dead_tasks_info: DeadTasksInfo,
+    // Every task seen so far, dead ones included.
+    processes: Vec<GroupProcess>,
 }
 
 pub struct Group {
@@ -203,6 +206,10 @@ impl ProcessInfo {
 }
 
 impl Process {
+    pub fn pid(&self) -> u32 {
+        self.pid.as_raw() as u32
+    }
+
     pub fn exit_status(&mut self) -> Result<Option<ExitStatus>> {
         if let ProcessStatus::Exited(ref status) = self.status {
             return Ok(Some(status.clone()));
@@ -282,11 +289,14 @@ impl<'a> ResourceUsage<'a> {
             group,
             active_tasks: ActiveTasks::new(),
             dead_tasks_info: DeadTasksInfo::new(),
+            processes: Vec::new(),
         }
     }
 
     pub fn update(&mut self) -> Result<()> {
-        let dead_tasks_info = self.active_tasks.update(&self.group.freezer)?;
+        let dead_tasks_info = self
+            .active_tasks
+            .update(&self.group.freezer, &mut self.processes)?;
         self.dead_tasks_info.num_dead_tasks += dead_tasks_info.num_dead_tasks;
         self.dead_tasks_info.total_bytes_written += dead_tasks_info.total_bytes_written;
         Ok(())
@@ -324,6 +334,10 @@ impl<'a> ResourceUsage<'a> {
         }))
     }
 
+    pub fn processes(&self) -> Result<Option<Vec<GroupProcess>>> {
+        Ok(Some(self.processes.clone()))
+    }
+
     pub fn timers(&self) -> Result<Option<GroupTimers>> {
         let cpuacct = &self.group.cpuacct;
         Ok(Some(GroupTimers {
@@ -436,7 +450,12 @@ impl ActiveTasks {
             .count())
     }
 
-    fn update(&mut self, freezer: &Cgroup) -> Result<DeadTasksInfo> {
+    /// Also records the new tasks and the peak memory usage of every task in `processes`.
+    fn update(
+        &mut self,
+        freezer: &Cgroup,
+        processes: &mut Vec<GroupProcess>,
+    ) -> Result<DeadTasksInfo> {
         self.pid_by_inode.clear();
         let new_wchar_by_pid = freezer
             .get_tasks()?
@@ -444,6 +463,15 @@ impl ActiveTasks {
             .filter_map(|pid| procfs::process::Process::new(pid.as_raw()).ok())
             .map(|ps| {
                 let pid = Pid::from_raw(ps.pid());
+                // VmHWM is the peak resident set size in kB.
+                let peak_memory = ps.status().ok().and_then(|s| s.vmhwm).map(|kb| kb * 1024);
+                match processes.iter_mut().find(|p| p.pid == ps.pid() as u32) {
+                    Some(p) => p.peak_memory = p.peak_memory.max(peak_memory),
+                    None => processes.push(GroupProcess {
+                        pid: ps.pid() as u32,
+                        peak_memory,
+                    }),
+                }
 
                 if let Ok(fds) = ps.fd() {
                     self.pid_by_inode
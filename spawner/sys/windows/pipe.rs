@@ -2,11 +2,12 @@ use crate::sys::windows::helpers::{cvt, to_utf16, Handle};
 use crate::sys::IntoInner;
 use crate::{Error, Result};
 
-use winapi::shared::minwindef::{DWORD, LPVOID, TRUE};
+use winapi::shared::minwindef::{DWORD, LPVOID, TRUE, ULONG};
+use winapi::shared::ntdef::{HANDLE, NTSTATUS, PVOID};
 use winapi::um::fileapi::{CreateFileW, ReadFile, WriteFile, CREATE_ALWAYS, OPEN_EXISTING};
 use winapi::um::handleapi::{SetHandleInformation, INVALID_HANDLE_VALUE};
 use winapi::um::minwinbase::SECURITY_ATTRIBUTES;
-use winapi::um::namedpipeapi::CreatePipe;
+use winapi::um::namedpipeapi::{CreatePipe, PeekNamedPipe};
 use winapi::um::winbase::HANDLE_FLAG_INHERIT;
 use winapi::um::winnt::{
     FILE_ATTRIBUTE_NORMAL, FILE_SHARE_READ, FILE_SHARE_WRITE, GENERIC_READ, GENERIC_WRITE,
@@ -16,10 +17,48 @@ use std::io::{self, Read, Write};
 use std::mem::size_of;
 use std::path::Path;
 use std::ptr;
+use std::thread;
+use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 pub struct ReadPipe(Handle);
 
+/// `FILE_INFORMATION_CLASS` of `FILE_PIPE_LOCAL_INFORMATION`.
+const FILE_PIPE_LOCAL_INFORMATION_CLASS: ULONG = 24;
+
+#[repr(C)]
+#[derive(Default)]
+struct FilePipeLocalInformation {
+    named_pipe_type: ULONG,
+    named_pipe_configuration: ULONG,
+    maximum_instances: ULONG,
+    current_instances: ULONG,
+    inbound_quota: ULONG,
+    read_data_available: ULONG,
+    outbound_quota: ULONG,
+    write_quota_available: ULONG,
+    named_pipe_state: ULONG,
+    named_pipe_end: ULONG,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct IoStatusBlock {
+    status: usize,
+    information: usize,
+}
+
+#[link(name = "ntdll")]
+extern "system" {
+    fn NtQueryInformationFile(
+        file: HANDLE,
+        io_status: *mut IoStatusBlock,
+        info: PVOID,
+        len: ULONG,
+        class: ULONG,
+    ) -> NTSTATUS;
+}
+
 #[derive(Debug)]
 pub struct WritePipe(Handle);
 
@@ -59,6 +98,33 @@ impl ReadPipe {
     pub fn null() -> Result<Self> {
         Self::open("nul")
     }
+
+    /// Returns `true` once a read wouldn't block, which includes a closed writing end.
+    pub fn wait_readable(&self, timeout: Duration) -> io::Result<bool> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let mut available: DWORD = 0;
+            let peeked = unsafe {
+                PeekNamedPipe(
+                    self.0.raw(),
+                    ptr::null_mut(),
+                    0,
+                    ptr::null_mut(),
+                    &mut available,
+                    ptr::null_mut(),
+                )
+            };
+            // Peeking fails for files and for pipes with the writing end closed, reading them
+            // doesn't block either.
+            if peeked == 0 || available > 0 {
+                return Ok(true);
+            }
+            if Instant::now() >= deadline {
+                return Ok(false);
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
 }
 
 impl IntoInner<Handle> for ReadPipe {
@@ -96,6 +162,33 @@ impl WritePipe {
     pub fn null() -> Result<Self> {
         open("nul", GENERIC_WRITE, OPEN_EXISTING, false).map(Self)
     }
+
+    /// Returns `true` once the pipe has room for more data. Anonymous pipes can't be polled
+    /// for writing, so the pipe's write quota is checked every millisecond instead. Files and
+    /// other handles that aren't pipes never block.
+    pub fn wait_writable(&self, timeout: Duration) -> io::Result<bool> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let mut io_status = IoStatusBlock::default();
+            let mut info = FilePipeLocalInformation::default();
+            let status = unsafe {
+                NtQueryInformationFile(
+                    self.0.raw(),
+                    &mut io_status,
+                    &mut info as *mut FilePipeLocalInformation as PVOID,
+                    size_of::<FilePipeLocalInformation>() as ULONG,
+                    FILE_PIPE_LOCAL_INFORMATION_CLASS,
+                )
+            };
+            if status < 0 || info.write_quota_available > 0 {
+                return Ok(true);
+            }
+            if Instant::now() >= deadline {
+                return Ok(false);
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
 }
 
 impl IntoInner<Handle> for WritePipe {
@@ -1,5 +1,6 @@
 use crate::process::{
-    ExitStatus, GroupIo, GroupMemory, GroupNetwork, GroupPidCounters, GroupTimers, OsLimit,
+    ExitStatus, GroupIo, GroupMemory, GroupNetwork, GroupPidCounters, GroupProcess, GroupTimers,
+    OsLimit,
 };
 use crate::sys::windows::helpers::{
     cvt, to_utf16, Endpoints, EnvBlock, Handle, JobNotifications, PidList, RawStdio, StartupInfo,
@@ -18,8 +19,8 @@ use winapi::um::jobapi2::{
 };
 use winapi::um::minwinbase::STILL_ACTIVE;
 use winapi::um::processthreadsapi::{
-    CreateProcessAsUserW, CreateProcessW, GetExitCodeProcess, ResumeThread, SuspendThread,
-    TerminateProcess, PROCESS_INFORMATION,
+    CreateProcessAsUserW, CreateProcessW, GetExitCodeProcess, GetProcessId, ResumeThread,
+    SuspendThread, TerminateProcess, PROCESS_INFORMATION,
 };
 use winapi::um::winbase::{
     CREATE_SUSPENDED, CREATE_UNICODE_ENVIRONMENT, EXTENDED_STARTUPINFO_PRESENT,
@@ -85,6 +86,7 @@ pub struct ResourceUsage<'a> {
     group: &'a Group,
     pid_list: RefCell<PidList>,
     endpoints: RefCell<Endpoints>,
+    processes: Vec<GroupProcess>,
 }
 
 pub struct Group {
@@ -185,6 +187,10 @@ impl AsRef<ProcessInfo> for ProcessInfo {
 }
 
 impl Process {
+    pub fn pid(&self) -> u32 {
+        unsafe { GetProcessId(self.handle.raw()) }
+    }
+
     pub fn exit_status(&self) -> Result<Option<ExitStatus>> {
         let mut exit_code: DWORD = 0;
         unsafe {
@@ -375,10 +381,22 @@ impl<'a> ResourceUsage<'a> {
             group,
             pid_list: RefCell::new(PidList::new()),
             endpoints: RefCell::new(Endpoints::new()),
+            processes: Vec::new(),
         }
     }
 
     pub fn update(&mut self) -> Result<()> {
+        let mut pid_list = self.pid_list.borrow_mut();
+        for &pid in pid_list.update(&self.group.job)? {
+            let pid = pid as u32;
+            if !self.processes.iter().any(|p| p.pid == pid) {
+                // The job doesn't account memory per process.
+                self.processes.push(GroupProcess {
+                    pid,
+                    peak_memory: None,
+                });
+            }
+        }
         Ok(())
     }
 
@@ -421,6 +439,10 @@ impl<'a> ResourceUsage<'a> {
         })
     }
 
+    pub fn processes(&self) -> Result<Option<Vec<GroupProcess>>> {
+        Ok(Some(self.processes.clone()))
+    }
+
     pub fn network(&self) -> Result<Option<GroupNetwork>> {
         let mut pid_list = self.pid_list.borrow_mut();
         let pids = pid_list.update(&self.group.job)?;
@@ -0,0 +1,102 @@
+use crate::protocol_entities::AgentIdx;
+use crate::value_parser::{DefaultValueParser, MemValueParser};
+
+use spawner::Error;
+
+use spawner_opts::OptionValueParser;
+
+use json::JsonValue;
+
+use std::io::BufRead;
+use std::time::Duration;
+
+/// Limits of a single agent, in the same units as the corresponding `Command` options.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct AgentLimits {
+    pub time_limit: Option<Duration>,
+    /// Megabytes.
+    pub memory_limit: Option<f64>,
+    /// Megabytes.
+    pub write_limit: Option<f64>,
+}
+
+/// Per-agent limits declared in one place, see `AgentLimitsConfig::read`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AgentLimitsConfig {
+    agents: Vec<(String, AgentLimits)>,
+}
+
+impl AgentLimitsConfig {
+    /// Reads one JSON object per line, e.g.
+    /// `{"Name": "solver", "TimeLimit": "1s", "MemoryLimit": "64M", "WriteLimit": "1M"}`.
+    /// Values are parsed like the `-tl`, `-ml` and `-wl` options, omitted limits are `None`.
+    /// The n-th agent in the config gets `AgentIdx(n)`, and agent names must be unique.
+    pub fn read<R: BufRead>(reader: R) -> Result<Self, Error> {
+        let mut agents: Vec<(String, AgentLimits)> = Vec::new();
+        for (idx, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (name, limits) = json::parse(&line)
+                .map_err(|e| Error::from(e.to_string()))
+                .and_then(|value| parse_agent(&value))
+                .map_err(|e| {
+                    Error::from(format!("Invalid agent limits on line {}: {}", idx + 1, e))
+                })?;
+            if agents.iter().any(|(n, _)| *n == name) {
+                return Err(Error::from(format!(
+                    "Duplicate agent name '{}' on line {}",
+                    name,
+                    idx + 1
+                )));
+            }
+            agents.push((name, limits));
+        }
+        Ok(Self { agents })
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&AgentLimits> {
+        self.agents
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, limits)| limits)
+    }
+
+    pub fn by_idx(&self, idx: AgentIdx) -> Option<&AgentLimits> {
+        self.agents.get(idx.0).map(|(_, limits)| limits)
+    }
+}
+
+fn parse_agent(value: &JsonValue) -> Result<(String, AgentLimits), Error> {
+    if !value.is_object() {
+        return Err(Error::from("Agent limits must be an object"));
+    }
+    let name = match value["Name"].as_str() {
+        Some(name) if !name.is_empty() => name.to_string(),
+        _ => return Err(Error::from("Missing field 'Name'")),
+    };
+    let mut limits = AgentLimits::default();
+    parse_limit::<_, DefaultValueParser>(value, "TimeLimit", &mut limits.time_limit)?;
+    parse_limit::<_, MemValueParser>(value, "MemoryLimit", &mut limits.memory_limit)?;
+    parse_limit::<_, MemValueParser>(value, "WriteLimit", &mut limits.write_limit)?;
+    Ok((name, limits))
+}
+
+/// Parses a string or a number field with `P`, leaving `limit` as is if the field is absent.
+fn parse_limit<T, P>(value: &JsonValue, name: &str, limit: &mut T) -> Result<(), Error>
+where
+    P: OptionValueParser<T>,
+{
+    let field = &value[name];
+    let v = if field.is_null() {
+        return Ok(());
+    } else if field.is_number() {
+        field.dump()
+    } else if let Some(s) = field.as_str() {
+        s.to_string()
+    } else {
+        return Err(Error::from(format!("Invalid field '{}'", name)));
+    };
+    P::parse(limit, &v).map_err(|e| Error::from(format!("Invalid field '{}': {}", name, e)))
+}
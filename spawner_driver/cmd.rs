@@ -300,6 +300,21 @@ pub struct Command {
     )]
     pub use_json: bool,
 
+    #[flag(
+        name = "--csv",
+        desc = "Use comma-separated format in report",
+        conflicts_with = "use_json"
+    )]
+    pub use_csv: bool,
+
+    #[flag(
+        name = "--tsv",
+        desc = "Use tab-separated format in report",
+        conflicts_with = "use_json",
+        conflicts_with = "use_csv"
+    )]
+    pub use_tsv: bool,
+
     #[flag(
         name = "--wait-for-children",
         desc = "Wait for all child processes to exit"
@@ -342,6 +357,8 @@ impl Default for Command {
             controller: false,
             shared_memory: None,
             use_json: false,
+            use_csv: false,
+            use_tsv: false,
             wait_for_children: false,
             argv: Vec::new(),
         }
@@ -359,9 +376,9 @@ impl Command {
         exclusive: false,
     };
 
-    pub fn from_env() -> Result<Self, String> {
+    pub fn from_env(warnings: &mut Vec<String>) -> Result<Self, String> {
         let mut opts = Self::default();
-        opts.parse_env()?;
+        opts.parse_env_with_warnings(warnings)?;
         Ok(opts)
     }
 
@@ -1,8 +1,8 @@
 use crate::cmd::{Command, Environment, RedirectFlags, RedirectKind, RedirectList};
 use crate::misc::mb2b;
-use crate::protocol_entities::{Agent, AgentIdx, Controller};
+use crate::protocol_entities::{Agent, AgentIdx, Controller, StalledStdin};
 use crate::protocol_handlers::{AgentStdout, ControllerStdout};
-use crate::report::Report;
+use crate::report::{Report, ReportKind, TableReport, DEFAULT_REPORT_COLUMNS};
 use crate::sys::{
     init_os_specific_process_extensions, open_input_file, open_output_file, ConsoleReader,
 };
@@ -30,6 +30,11 @@ use std::fs;
 use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
 use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+
+/// How long a write to an agent's stdin may be blocked before it is reported as a warning.
+const AGENT_STDIN_STALL_THRESHOLD: Duration = Duration::from_secs(5);
 
 pub struct Warnings(RefCell<HashSet<String>>);
 
@@ -38,6 +43,8 @@ pub struct Driver {
     cmds: Vec<Command>,
     warnings: Warnings,
     stdio: DriverStdio,
+    /// Writes to agents' stdin that were blocked for too long, reported as they occur.
+    stalls: Option<Receiver<StalledStdin>>,
 }
 
 /// All redirects to *std are redirected here.
@@ -45,6 +52,7 @@ pub struct Driver {
 struct DriverStdio {
     stdin_w: Option<WritePipe>,
     stdout_r: Option<ReadPipe>,
+    input_files: HashSet<SourceId>,
 }
 
 struct StdioLinker<'w, 's, 'm> {
@@ -52,9 +60,12 @@ struct StdioLinker<'w, 's, 'm> {
     sess: &'s mut Session,
     stdin: Option<(WritePipe, SourceId)>,
     stdout: Option<(ReadPipe, DestinationId)>,
+    null_input: Option<SourceId>,
+    null_output: Option<DestinationId>,
     warnings: &'w Warnings,
     output_files: HashMap<PathBuf, DestinationId>,
     exclusive_input_files: HashMap<PathBuf, SourceId>,
+    input_files: HashSet<SourceId>,
 }
 
 #[derive(Copy, Clone)]
@@ -72,6 +83,12 @@ impl Warnings {
     pub fn emit<T: ToString>(&self, msg: T) {
         self.0.borrow_mut().insert(msg.to_string());
     }
+
+    /// Prints a warning that occurs while the programs run, the ones emitted before are
+    /// printed when the run starts.
+    pub fn print_now<T: fmt::Display>(msg: T) {
+        eprintln!("warning: {}", msg);
+    }
 }
 
 impl fmt::Display for Warnings {
@@ -90,7 +107,7 @@ impl Driver {
         U: AsRef<str>,
     {
         let warnings = Warnings::new();
-        let cmds = parse_argv(argv)?;
+        let cmds = parse_argv(argv, &warnings)?;
         check_cmds(&cmds, &warnings)?;
 
         let mut sess = Session::new();
@@ -107,10 +124,16 @@ impl Driver {
             .collect::<Result<Vec<_>>>()?;
 
         let stdio = StdioLinker::new(&mut sess, &mappings, &warnings).link(&cmds)?;
+        let mut stalls = None;
 
         if let Some(controller) = cmds.iter().position(|cmd| cmd.controller) {
             // Initialize protocol entities.
-            let controller = Controller::new(senders[controller].clone(), mappings[controller]);
+            let mapping = mappings[controller];
+            let mut controller = Controller::new(senders[controller].clone(), mapping);
+            // Replies to status queries are written to the controller's stdin directly.
+            if let Some(writer) = sess.destination_writer(mapping.stdin) {
+                controller = controller.with_stdin_writer(writer);
+            }
             let agents = roles
                 .iter()
                 .zip(mappings.iter())
@@ -120,7 +143,13 @@ impl Driver {
                     _ => None,
                 })
                 .collect::<Vec<_>>();
-            check_protocol_entities(&controller, &agents, sess.graph(), &warnings);
+            check_protocol_entities(
+                &controller,
+                &agents,
+                sess.graph(),
+                &stdio.input_files,
+                &warnings,
+            );
 
             for entity in roles {
                 init_entity_handler(entity, sess.graph_mut(), &controller, &agents);
@@ -128,6 +157,16 @@ impl Driver {
             for agent in &agents {
                 agent.stop_time_accounting();
             }
+
+            let (stall_sender, stall_receiver) = channel();
+            for agent in &agents {
+                agent.watch_stdin(
+                    sess.graph(),
+                    AGENT_STDIN_STALL_THRESHOLD,
+                    stall_sender.clone(),
+                );
+            }
+            stalls = Some(stall_receiver);
         }
 
         Ok(Self {
@@ -135,6 +174,7 @@ impl Driver {
             cmds,
             warnings,
             stdio,
+            stalls,
         })
     }
 
@@ -151,6 +191,9 @@ impl Driver {
         if let Some(stdin) = self.stdio.stdin_w {
             ConsoleReader::spawn(stdin).join(&run);
         }
+        if let Some(stalls) = self.stalls {
+            spawn_stall_reporter(stalls);
+        }
 
         let reports = run
             .wait()
@@ -174,9 +217,12 @@ impl<'w, 's, 'm> StdioLinker<'w, 's, 'm> {
             mappings,
             stdin: None,
             stdout: None,
+            null_input: None,
+            null_output: None,
             warnings,
             output_files: HashMap::new(),
             exclusive_input_files: HashMap::new(),
+            input_files: HashSet::new(),
         }
     }
 
@@ -190,6 +236,7 @@ impl<'w, 's, 'm> StdioLinker<'w, 's, 'm> {
         Ok(DriverStdio {
             stdin_w: self.stdin.map(|s| s.0),
             stdout_r: self.stdout.map(|s| s.0),
+            input_files: self.input_files,
         })
     }
 
@@ -209,6 +256,7 @@ impl<'w, 's, 'm> StdioLinker<'w, 's, 'm> {
                     }
                     self.stdin.as_ref().unwrap().1
                 }
+                RedirectKind::Null => self.null_input()?,
                 _ => continue,
             };
             self.sess.graph_mut().connect(src, dst);
@@ -228,6 +276,7 @@ impl<'w, 's, 'm> StdioLinker<'w, 's, 'm> {
                     }
                     self.stdout.as_ref().unwrap().1
                 }
+                RedirectKind::Null => self.null_output()?,
                 _ => continue,
             };
             self.sess.graph_mut().connect(src, dst);
@@ -235,6 +284,24 @@ impl<'w, 's, 'm> StdioLinker<'w, 's, 'm> {
         Ok(())
     }
 
+    fn null_input(&mut self) -> Result<SourceId> {
+        if self.null_input.is_none() {
+            let pipe = ReadPipe::null()?;
+            self.null_input = Some(self.sess.graph_mut().add_source(pipe));
+        }
+        Ok(self.null_input.unwrap())
+    }
+
+    /// Everything written to *null is discarded by the null device, so the
+    /// program never blocks on a pipe no one reads.
+    fn null_output(&mut self) -> Result<DestinationId> {
+        if self.null_output.is_none() {
+            let pipe = WritePipe::null()?;
+            self.null_output = Some(self.sess.graph_mut().add_destination(pipe));
+        }
+        Ok(self.null_output.unwrap())
+    }
+
     fn open_input_file(&mut self, path: &str, flags: RedirectFlags) -> Result<SourceId> {
         let path = canonicalize(path)?;
         match self.exclusive_input_files.get(&path).copied() {
@@ -242,6 +309,7 @@ impl<'w, 's, 'm> StdioLinker<'w, 's, 'm> {
             None => {
                 let pipe = open_input_file(&path, flags, &self.warnings)?;
                 let id = self.sess.graph_mut().add_source(pipe);
+                self.input_files.insert(id);
                 if flags.exclusive {
                     self.exclusive_input_files.insert(path, id);
                     // Avoid inlining to keep pipe open as long as possible.
@@ -294,19 +362,22 @@ impl Role {
     }
 }
 
-fn parse_argv<T, U>(argv: T) -> Result<Vec<Command>>
+fn parse_argv<T, U>(argv: T, warnings: &Warnings) -> Result<Vec<Command>>
 where
     T: IntoIterator<Item = U>,
     U: AsRef<str>,
 {
     let argv: Vec<String> = argv.into_iter().map(|x| x.as_ref().to_string()).collect();
-    let mut default_cmd = Command::from_env()?;
+    let mut parser_warnings = Vec::new();
+    let mut default_cmd = Command::from_env(&mut parser_warnings)?;
     let mut pos = 0;
     let mut cmds: Vec<Command> = Vec::new();
 
     while pos < argv.len() {
         let mut cmd = default_cmd.clone();
-        pos += cmd.parse_argv(&argv[pos..]).map_err(Error::from)?;
+        pos += cmd
+            .parse_argv_with_warnings(&argv[pos..], &mut parser_warnings)
+            .map_err(|e| Error::from(e.to_string()))?;
 
         let mut sep_pos = argv.len();
         if let Some(sep) = &cmd.separator {
@@ -326,6 +397,9 @@ where
         }
     }
 
+    for w in parser_warnings {
+        warnings.emit(w);
+    }
     Ok(cmds)
 }
 
@@ -359,7 +433,17 @@ fn print_reports(cmds: &[Command], reports: &[Report]) -> std::io::Result<()> {
     let mut output_files: HashMap<&String, Vec<&Report>> = HashMap::new();
     for (i, cmd) in cmds.iter().enumerate() {
         if !cmd.hide_report && reports.len() == 1 {
-            println!("{}", reports[i]);
+            match reports[i].kind {
+                ReportKind::Table(separator) => println!(
+                    "{}",
+                    TableReport {
+                        reports: &[&reports[i]],
+                        columns: &DEFAULT_REPORT_COLUMNS,
+                        separator,
+                    }
+                ),
+                _ => println!("{}", reports[i]),
+            }
         }
         if let Some(filename) = &cmd.output_file {
             output_files
@@ -373,7 +457,15 @@ fn print_reports(cmds: &[Command], reports: &[Report]) -> std::io::Result<()> {
         let _ = fs::remove_file(filename);
         let mut file = fs::File::create(filename)?;
 
-        if file_reports.len() == 1 && !file_reports[0].kind.is_json() {
+        // Reports sharing a file have the same kind, see `check_cmds`.
+        if let ReportKind::Table(separator) = file_reports[0].kind {
+            let table = TableReport {
+                reports: &file_reports,
+                columns: &DEFAULT_REPORT_COLUMNS,
+                separator,
+            };
+            write!(&mut file, "{}", table)?;
+        } else if file_reports.len() == 1 && !file_reports[0].kind.is_json() {
             write!(&mut file, "{}", file_reports[0])?;
         } else if file_reports.iter().all(|r| r.kind.is_json()) {
             let json_reports =
@@ -385,10 +477,32 @@ fn print_reports(cmds: &[Command], reports: &[Report]) -> std::io::Result<()> {
     Ok(())
 }
 
+/// Prints every stall as a warning as soon as it is sent, i.e. every
+/// `AGENT_STDIN_STALL_THRESHOLD` while an agent doesn't read its stdin.
+fn spawn_stall_reporter(stalls: Receiver<StalledStdin>) {
+    thread::spawn(move || {
+        for stall in stalls {
+            Warnings::print_now(stall);
+        }
+    });
+}
+
 fn check_cmds(cmds: &[Command], warnings: &Warnings) -> Result<()> {
     if cmds.iter().filter(|cmd| cmd.controller).count() > 1 {
         return Err(Error::from("There can be at most one controller"));
     }
+    let mut output_formats = HashMap::new();
+    for cmd in cmds.iter() {
+        if let Some(filename) = &cmd.output_file {
+            let format = (cmd.use_json, cmd.use_csv, cmd.use_tsv);
+            if *output_formats.entry(filename).or_insert(format) != format {
+                return Err(Error::from(format!(
+                    "Reports written to the same file '{}' must have the same format",
+                    filename
+                )));
+            }
+        }
+    }
     for cmd in cmds.iter() {
         assert!(!cmd.argv.is_empty());
         if cmd.delegated {
@@ -405,10 +519,16 @@ fn check_protocol_entities(
     controller: &Controller,
     agents: &[Agent],
     graph: &Graph,
+    input_files: &HashSet<SourceId>,
     warnings: &Warnings,
 ) {
     for agent in agents {
-        if !graph.has_connection(controller.stdout(), agent.stdin()) {
+        // An agent may read a fixed input file instead, e.g. `--in=file`, controller messages
+        // to such an agent are dropped.
+        let reads_file = graph
+            .destination(agent.stdin())
+            .is_some_and(|dst| dst.edges().iter().any(|src| input_files.contains(src)));
+        if !graph.has_connection(controller.stdout(), agent.stdin()) && !reads_file {
             warnings.emit(format!(
                 "Controller is not connected to agent#{} via stdout",
                 agent.idx().0 + 1
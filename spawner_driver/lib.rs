@@ -10,6 +10,7 @@ extern crate winapi;
 #[cfg(unix)]
 extern crate libc;
 
+pub mod agent_limits;
 mod cmd;
 mod driver;
 mod misc;
@@ -22,6 +23,9 @@ mod value_parser;
 #[cfg(test)]
 mod tests;
 
+#[cfg(feature = "json-events")]
+pub use crate::protocol_entities::AgentEvent;
+pub use crate::protocol_entities::AgentIdx;
 pub use crate::report::*;
 
 use crate::driver::Driver;
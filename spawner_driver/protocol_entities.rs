@@ -1,41 +1,184 @@
-use spawner::dataflow::{DestinationId, SourceId};
-use spawner::{Error, ProgramMessage, Result, StdioMapping};
+use spawner::dataflow::{DestinationId, DestinationWriter, Graph, SourceId, WeakDestinationWriter};
+use spawner::pipe::StallWatch;
+use spawner::{Error, ProgramMessage, ProgramStatus, Result, StdioMapping};
 
+#[cfg(feature = "json-events")]
+use json::JsonValue;
+
+use std::borrow::Cow;
 use std::char;
+use std::fmt;
 use std::str;
-use std::sync::mpsc::Sender;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The protocol version assumed when the controller doesn't declare one.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Version 1 is the original protocol, version 2 adds the `E` command.
+pub const SUPPORTED_PROTOCOL_VERSIONS: [u32; 2] = [1, 2];
+
+/// How long `Agent::query_status` waits for the agent's supervisor to reply.
+pub const STATUS_QUERY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The 0-based index of an agent, agent `N` of the protocol has index `N - 1`. Indices parsed
+/// from controller messages are checked by the handler, other crates can create them with
+/// `AgentIdx::new` only.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AgentIdx(pub(crate) usize);
+
+impl AgentIdx {
+    /// Returns the index if it refers to one of `count` agents.
+    pub fn new(idx: usize, count: usize) -> Option<Self> {
+        if idx < count {
+            Some(AgentIdx(idx))
+        } else {
+            None
+        }
+    }
 
-#[derive(Copy, Clone, PartialEq)]
-pub struct AgentIdx(pub usize);
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
 
 #[derive(Clone)]
 pub struct Controller {
     sender: Sender<ProgramMessage>,
     mapping: StdioMapping,
+    stdin_writer: Option<WeakDestinationWriter>,
 }
 
+/// Clones of an agent share the channel, so a delivery tracked through one of them is resolved
+/// by the handler reading the agent's stdout.
 #[derive(Clone)]
 pub struct Agent {
     idx: AgentIdx,
-    sender: Sender<ProgramMessage>,
+    channel: Arc<Mutex<AgentChannel>>,
     mapping: StdioMapping,
+    encoding: Arc<Mutex<StreamEncoding>>,
+}
+
+struct AgentChannel {
+    sender: Sender<ProgramMessage>,
+    deliveries: Vec<Sender<()>>,
+    exited: bool,
+}
+
+/// Tells when the agent has consumed the data sent to it, see `Agent::track_delivery`.
+pub struct Delivery(Receiver<()>);
+
+/// The reply to a `NA#<data>` message, written to the controller's stdin as `NA=ok#\n` or
+/// `NA=exited#\n`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DeliveryStatus {
+    /// The agent has sent a message after the data was sent.
+    Acknowledged,
+    /// The agent has closed its stdout without sending a message.
+    AgentExited,
+}
+
+/// A write to an agent's stdin has been blocked for `stalled_for` because the agent doesn't
+/// read it, which may mean a deadlock, see `Agent::watch_stdin`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct StalledStdin {
+    pub agent: AgentIdx,
+    pub stalled_for: Duration,
+}
+
+/// The encoding an agent reads its stdin and writes its stdout in, see `MessageKind::Encoding`.
+/// Protocol messages are always UTF-8, so data is transcoded on the way to and from the agent.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum StreamEncoding {
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
 }
 
 pub enum MessageKind<'a> {
+    /// The payload without the line terminator. Agents get it as a line ending with '\n',
+    /// even if the controller has sent "\r\n".
     Data(&'a [u8]),
+    /// `NA#<data>` is the same as `N#<data>`, but the controller gets a `DeliveryStatus`
+    /// once agent N replies to the data or exits.
+    TrackedData(&'a [u8]),
     Terminate,
     Resume,
+    /// `0E#` from the controller means that the controller has finished the session.
+    /// `NE#` is sent to the controller when agent N writes the `E#` line to signal that
+    /// its turn is over.
+    Done,
+    /// `V=N#` declares the protocol version the controller speaks. It may only be sent
+    /// as the first controller message.
+    Version(u32),
+    /// `N?#` asks for the status of agent N, see `AgentStatus`.
+    Query,
+    /// `N=utf16#` sets the encoding of the data sent to and received from agent N from now on,
+    /// see `StreamEncoding::from_name`.
+    Encoding(StreamEncoding),
+}
+
+/// The reply to a `N?#` query, written to the controller's stdin as
+/// `N?=<wall clock time>s,<peak memory>M#\n`, e.g. `3?=1.230s,45M#\n`. The time has millisecond
+/// precision, the memory is rounded up to whole mebibytes. Unknown values are written as `-`,
+/// both are unknown if the agent has already finished.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct AgentStatus {
+    pub wall_clock_time: Option<Duration>,
+    pub peak_memory: Option<u64>,
+}
+
+/// An agent data line as written to the controller's stdin, `N#<data>\n`. Agents may write
+/// events such as `{"type":"move","data":[1,2]}`, any data that isn't valid JSON is kept as is.
+#[cfg(feature = "json-events")]
+#[derive(Debug, PartialEq)]
+pub enum AgentEvent<'a> {
+    Json(JsonValue),
+    Raw(&'a [u8]),
 }
 
 pub struct Message<'a> {
-    agent_idx: Option<AgentIdx>,
+    /// The first and the last addressed agents, `None` if the message is addressed to nobody.
+    agents: Option<(AgentIdx, AgentIdx)>,
     kind: MessageKind<'a>,
     raw: &'a [u8],
+    seq: usize,
 }
 
 impl Controller {
     pub fn new(sender: Sender<ProgramMessage>, mapping: StdioMapping) -> Self {
-        Self { sender, mapping }
+        Self {
+            sender,
+            mapping,
+            stdin_writer: None,
+        }
+    }
+
+    /// Allows writing to the controller's stdin directly, see `Session::destination_writer`.
+    /// Only a weak handle is kept, so the controller's stdin is still closed once the agents
+    /// are gone.
+    pub fn with_stdin_writer(mut self, writer: DestinationWriter) -> Self {
+        self.stdin_writer = Some(writer.downgrade());
+        self
+    }
+
+    /// Writes a complete framed message to the controller's stdin. Messages written
+    /// concurrently from `Controller` clones or by agents never interleave.
+    pub fn write_message(&self, msg: &[u8]) -> Result<()> {
+        match self.stdin_writer() {
+            Some(w) => w.write_message(msg),
+            None => Err(Error::from("Controller stdin is not available for writing")),
+        }
+    }
+
+    /// Returns a handle for raw writes to the controller's stdin, see `DestinationWriter::lock`.
+    /// Prefer `write_message`.
+    pub fn stdin_writer(&self) -> Option<DestinationWriter> {
+        self.stdin_writer
+            .as_ref()
+            .and_then(WeakDestinationWriter::upgrade)
     }
 
     fn send(&self, msg: ProgramMessage) -> &Self {
@@ -64,8 +207,13 @@ impl Agent {
     pub fn new(idx: AgentIdx, sender: Sender<ProgramMessage>, mapping: StdioMapping) -> Self {
         Self {
             idx,
-            sender,
+            channel: Arc::new(Mutex::new(AgentChannel {
+                sender,
+                deliveries: Vec::new(),
+                exited: false,
+            })),
             mapping,
+            encoding: Arc::new(Mutex::new(StreamEncoding::default())),
         }
     }
 
@@ -73,8 +221,37 @@ impl Agent {
         self.idx
     }
 
+    /// Returns a `Delivery` resolved by the next message of the agent. A successful write to
+    /// the agent's stdin only means the data is in the pipe buffer, whereas in the protocol the
+    /// agent replies once it has read its input, so call this right after sending the data to
+    /// wait for the reply. If the agent exits first, or has already exited, the delivery
+    /// resolves to `DeliveryStatus::AgentExited`. The controller asks for this with
+    /// `NA#<data>`, see `MessageKind::TrackedData`.
+    pub fn track_delivery(&self) -> Delivery {
+        let (sender, receiver) = channel();
+        let mut channel = self.channel.lock().unwrap();
+        if !channel.exited {
+            channel.deliveries.push(sender);
+        }
+        Delivery(receiver)
+    }
+
+    /// Resolves the pending deliveries, called when the agent sends a message.
+    pub fn acknowledge(&self) {
+        for delivery in self.channel.lock().unwrap().deliveries.drain(..) {
+            let _ = delivery.send(());
+        }
+    }
+
+    /// Fails the pending and future deliveries, called when the agent closes its stdout.
+    pub fn close_deliveries(&self) {
+        let mut channel = self.channel.lock().unwrap();
+        channel.deliveries.clear();
+        channel.exited = true;
+    }
+
     fn send(&self, msg: ProgramMessage) -> &Self {
-        let _ = self.sender.send(msg);
+        let _ = self.channel.lock().unwrap().sender.send(msg);
         self
     }
 
@@ -97,10 +274,47 @@ impl Agent {
             .send(ProgramMessage::ResumeTimeAccounting);
     }
 
+    /// Returns the current status of the agent, or `None` if it has already finished or hasn't
+    /// replied within `STATUS_QUERY_TIMEOUT`.
+    pub fn query_status(&self) -> Option<ProgramStatus> {
+        let (sender, receiver) = channel();
+        self.send(ProgramMessage::QueryStatus(sender));
+        receiver.recv_timeout(STATUS_QUERY_TIMEOUT).ok()
+    }
+
+    /// The encoding of the agent's stdin and stdout, shared between clones of the agent.
+    pub fn encoding(&self) -> StreamEncoding {
+        *self.encoding.lock().unwrap()
+    }
+
+    pub fn set_encoding(&self, encoding: StreamEncoding) {
+        *self.encoding.lock().unwrap() = encoding;
+    }
+
     pub fn stdio_mapping(&self) -> StdioMapping {
         self.mapping
     }
 
+    /// Sends a `StalledStdin` to `sender` every `threshold` while a write to the agent's stdin
+    /// is blocked. Returns `false` if the agent's stdin is not in `graph`.
+    pub fn watch_stdin(
+        &self,
+        graph: &Graph,
+        threshold: Duration,
+        sender: Sender<StalledStdin>,
+    ) -> bool {
+        let agent = self.idx;
+        match graph.destination(self.mapping.stdin) {
+            Some(dst) => {
+                dst.set_stall_watch(StallWatch::new(threshold, move |stalled_for| {
+                    let _ = sender.send(StalledStdin { agent, stalled_for });
+                }));
+                true
+            }
+            None => false,
+        }
+    }
+
     pub fn stdout(&self) -> SourceId {
         self.mapping.stdout
     }
@@ -110,75 +324,468 @@ impl Agent {
     }
 }
 
-impl<'a> Message<'a> {
-    fn parse_header(header: &'a [u8], msg: &'a [u8]) -> Result<(usize, MessageKind<'a>)> {
-        if header.is_empty() {
-            return Err(Error::from("Missing header in controller message"));
+impl Delivery {
+    /// Blocks until the agent replies or exits.
+    pub fn wait(&self) -> DeliveryStatus {
+        match self.0.recv() {
+            Ok(()) => DeliveryStatus::Acknowledged,
+            Err(_) => DeliveryStatus::AgentExited,
         }
+    }
 
-        let header_str = str::from_utf8(header)
-            .map_err(|_| Error::from("Invalid header in controller message"))?;
+    /// Same as `wait`, but returns `None` if the agent has done neither within `timeout`.
+    #[cfg(test)]
+    pub fn wait_timeout(&self, timeout: Duration) -> Option<DeliveryStatus> {
+        use std::sync::mpsc::RecvTimeoutError;
+
+        match self.0.recv_timeout(timeout) {
+            Ok(()) => Some(DeliveryStatus::Acknowledged),
+            Err(RecvTimeoutError::Disconnected) => Some(DeliveryStatus::AgentExited),
+            Err(RecvTimeoutError::Timeout) => None,
+        }
+    }
+}
 
-        let mut num_digits = 0;
-        for c in header_str.chars() {
-            if char::is_digit(c, 10) {
-                num_digits += 1;
-            } else {
-                break;
+impl DeliveryStatus {
+    /// Encodes the reply to a `NA#<data>` message sent to the given agent.
+    pub fn encode(self, agent_idx: AgentIdx) -> String {
+        let status = match self {
+            DeliveryStatus::Acknowledged => "ok",
+            DeliveryStatus::AgentExited => "exited",
+        };
+        format!("{}A={}#\n", agent_idx.0 + 1, status)
+    }
+}
+
+impl StreamEncoding {
+    /// Accepts `utf8`, `utf16le`, `utf16be` and `utf16`, which is the same as `utf16le`.
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "utf8" | "utf-8" => Ok(StreamEncoding::Utf8),
+            "utf16" | "utf-16" | "utf16le" | "utf-16le" => Ok(StreamEncoding::Utf16Le),
+            "utf16be" | "utf-16be" => Ok(StreamEncoding::Utf16Be),
+            _ => Err(Error::from(format!("Unknown encoding '{}'", name))),
+        }
+    }
+
+    /// Transcodes UTF-8 `data` into this encoding. Invalid UTF-8 sequences are replaced with
+    /// U+FFFD.
+    pub fn encode<'a>(&self, data: &'a [u8]) -> Cow<'a, [u8]> {
+        let units = || {
+            String::from_utf8_lossy(data)
+                .encode_utf16()
+                .collect::<Vec<_>>()
+        };
+        match self {
+            StreamEncoding::Utf8 => Cow::Borrowed(data),
+            StreamEncoding::Utf16Le => {
+                Cow::Owned(units().iter().flat_map(|u| u.to_le_bytes()).collect())
+            }
+            StreamEncoding::Utf16Be => {
+                Cow::Owned(units().iter().flat_map(|u| u.to_be_bytes()).collect())
+            }
+        }
+    }
+
+    /// Transcodes `data` in this encoding into UTF-8, returning the result and the number of
+    /// bytes consumed. Since a stream may be split anywhere, a trailing incomplete code unit or
+    /// surrogate pair is not consumed and should be passed again along with the following data.
+    /// Unpaired surrogates are replaced with U+FFFD.
+    pub fn decode<'a>(&self, data: &'a [u8]) -> (Cow<'a, [u8]>, usize) {
+        let unit = |i: usize| match self {
+            StreamEncoding::Utf16Be => u16::from_be_bytes([data[i], data[i + 1]]),
+            _ => u16::from_le_bytes([data[i], data[i + 1]]),
+        };
+        match self {
+            StreamEncoding::Utf8 => (Cow::Borrowed(data), data.len()),
+            StreamEncoding::Utf16Le | StreamEncoding::Utf16Be => {
+                let mut len = data.len() & !1;
+                if len >= 2 && (0xD800..0xDC00).contains(&unit(len - 2)) {
+                    len -= 2;
+                }
+                let units = (0..len).step_by(2).map(unit).collect::<Vec<_>>();
+                let utf8 = String::from_utf16_lossy(&units).into_bytes();
+                (Cow::Owned(utf8), len)
             }
         }
+    }
+}
+
+impl<'a> Message<'a> {
+    fn count_digits(s: &str) -> usize {
+        s.chars().take_while(|&c| char::is_digit(c, 10)).count()
+    }
 
-        let agent_idx = usize::from_str_radix(&header_str[..num_digits], 10).map_err(|_| {
+    /// Parses the agent index `N` or the agent range `N-M` at the beginning of the header.
+    /// Returns the 1-based bounds of the range and the rest of the header.
+    fn parse_agents(header_str: &str) -> Result<((usize, usize), &str)> {
+        let num_digits = Message::count_digits(header_str);
+        let first = header_str[..num_digits].parse::<usize>().map_err(|_| {
             Error::from(format!(
                 "Unable to parse agent index '{}'",
                 &header_str[..num_digits]
             ))
         })?;
 
-        match &header_str[num_digits..] {
-            "" => Ok((agent_idx, MessageKind::Data(msg))),
-            "W" => Ok((agent_idx, MessageKind::Resume)),
-            "S" => Ok((agent_idx, MessageKind::Terminate)),
+        let rest = &header_str[num_digits..];
+        let range_end = match rest.strip_prefix('-') {
+            Some(end) if Message::count_digits(end) > 0 => end,
+            _ => return Ok(((first, first), rest)),
+        };
+        let num_digits = Message::count_digits(range_end);
+        let range = &header_str[..header_str.len() - range_end.len() + num_digits];
+        let last = range_end[..num_digits]
+            .parse::<usize>()
+            .map_err(|_| Error::from(format!("Unable to parse agent range '{}'", range)))?;
+        if first == 0 || first > last {
+            return Err(Error::from(format!(
+                "Invalid agent range '{}' in '{}'",
+                range, header_str
+            )));
+        }
+        Ok(((first, last), &range_end[num_digits..]))
+    }
+
+    fn parse_header(header: &'a [u8], msg: &'a [u8]) -> Result<((usize, usize), MessageKind<'a>)> {
+        if header.is_empty() {
+            return Err(Error::from("Missing header in controller message"));
+        }
+
+        let header_str = str::from_utf8(header)
+            .map_err(|_| Error::from("Invalid header in controller message"))?;
+
+        if let Some(version) = header_str.strip_prefix("V=") {
+            return Message::parse_version(version, header_str, msg).map(|v| ((0, 0), v));
+        }
+
+        let (agents, cmd) = Message::parse_agents(header_str)?;
+        if let Some(name) = cmd.strip_prefix("N=") {
+            if !is_line_end(msg) {
+                return Err(Error::from(format!(
+                    "Controller command 'N' doesn't accept data in '{}'",
+                    header_str
+                )));
+            }
+            return StreamEncoding::from_name(name).map(|e| (agents, MessageKind::Encoding(e)));
+        }
+        match cmd {
+            "" => Ok((agents, MessageKind::Data(strip_line_end(msg)))),
+            "A" => Ok((agents, MessageKind::TrackedData(strip_line_end(msg)))),
+            "W" => Ok((agents, MessageKind::Resume)),
+            "S" => Ok((agents, MessageKind::Terminate)),
+            cmd @ "E" | cmd @ "?" => {
+                if !is_line_end(msg) {
+                    return Err(Error::from(format!(
+                        "Controller command '{}' doesn't accept data in '{}'",
+                        cmd, header_str
+                    )));
+                }
+                match cmd {
+                    "E" => Ok((agents, MessageKind::Done)),
+                    _ => Ok((agents, MessageKind::Query)),
+                }
+            }
             _ => Err(Error::from(format!(
                 "Invalid controller command '{}' in '{}'",
-                &header_str[num_digits..],
-                header_str
+                cmd, header_str
             ))),
         }
     }
 
+    fn parse_version(version: &str, header: &str, msg: &[u8]) -> Result<MessageKind<'a>> {
+        if !is_line_end(msg) {
+            return Err(Error::from(format!(
+                "Controller command 'V' doesn't accept data in '{}'",
+                header
+            )));
+        }
+        let version = version
+            .parse::<u32>()
+            .map_err(|_| Error::from(format!("Invalid protocol version '{}'", version)))?;
+        if !SUPPORTED_PROTOCOL_VERSIONS.contains(&version) {
+            return Err(Error::from(format!(
+                "Unsupported protocol version '{}', supported versions are {:?}",
+                version, SUPPORTED_PROTOCOL_VERSIONS
+            )));
+        }
+        Ok(MessageKind::Version(version))
+    }
+
     pub fn parse(data: &'a [u8]) -> Result<Self> {
         if data.is_empty() {
             return Err(Error::from("Empty controller message"));
         }
-        if !data.ends_with(&[b'\n']) {
-            return Err(Error::from("Controller message must end with '\n'"));
-        }
 
+        // Framing errors tell the byte offset where the missing character was expected.
         let (header, msg) = match data.iter().position(|&x| x == b'#') {
             Some(hash_pos) => (&data[..hash_pos], &data[hash_pos + 1..]),
-            None => return Err(Error::from("Missing '#' in controller message")),
+            None => {
+                let header_len = data
+                    .iter()
+                    .take_while(|&&b| b.is_ascii_alphanumeric() || b"-?=".contains(&b))
+                    .count();
+                return Err(Error::from(format!(
+                    "Missing '#' in controller message at byte {}",
+                    header_len
+                )));
+            }
         };
+        if !data.ends_with(b"\n") {
+            return Err(Error::from(format!(
+                "Missing trailing '\\n' in controller message at byte {}",
+                data.len()
+            )));
+        }
 
-        Message::parse_header(header, msg).map(|(agent_idx, kind)| Self {
-            agent_idx: match agent_idx {
-                0 => None,
-                x => Some(AgentIdx(x - 1)),
+        Message::parse_header(header, msg).map(|(agents, kind)| Self {
+            agents: match agents {
+                (0, _) => None,
+                (first, last) => Some((AgentIdx(first - 1), AgentIdx(last - 1))),
             },
             kind,
             raw: data,
+            seq: 0,
         })
     }
 
-    pub fn kind(&self) -> &MessageKind {
+    /// Sets the sequence number of the message within the stream it was read from.
+    pub fn with_seq(mut self, seq: usize) -> Self {
+        self.seq = seq;
+        self
+    }
+
+    /// The sequence number of the message, starting from 0. Used for diagnostics only.
+    pub fn seq(&self) -> usize {
+        self.seq
+    }
+
+    /// The message contents between '#' and the trailing '\n'. Messages may also end with
+    /// "\r\n", the '\r' is not a part of the payload then.
+    pub fn payload(&self) -> &'a [u8] {
+        let raw: &'a [u8] = self.raw;
+        strip_line_end(&raw[raw.iter().position(|&b| b == b'#').map_or(0, |pos| pos + 1)..])
+    }
+
+    /// CRC-32 of the payload. Used for diagnostics only.
+    pub fn crc32(&self) -> u32 {
+        let mut crc = !0u32;
+        for &b in self.payload() {
+            crc ^= u32::from(b);
+            for _ in 0..8 {
+                crc = if crc & 1 != 0 {
+                    (crc >> 1) ^ 0xEDB8_8320
+                } else {
+                    crc >> 1
+                };
+            }
+        }
+        !crc
+    }
+
+    pub fn kind(&self) -> &MessageKind<'a> {
         &self.kind
     }
 
+    /// The addressed agent, or the first one if the message is addressed to a range of agents
+    /// such as `2-4S#`.
     pub fn agent_idx(&self) -> Option<AgentIdx> {
-        self.agent_idx
+        self.agents.map(|(first, _)| first)
+    }
+
+    /// Every addressed agent in order. Empty if the message is not addressed to agents.
+    pub fn agents(&self) -> impl Iterator<Item = AgentIdx> {
+        let (first, last) = self
+            .agents
+            .map_or((1, 0), |(first, last)| (first.0, last.0));
+        (first..=last).map(AgentIdx)
     }
 
     pub fn as_raw(&self) -> &[u8] {
         self.raw
     }
 }
+
+/// `msg` without the trailing "\n" or "\r\n".
+fn strip_line_end(msg: &[u8]) -> &[u8] {
+    msg.strip_suffix(b"\r\n")
+        .or_else(|| msg.strip_suffix(b"\n"))
+        .unwrap_or(msg)
+}
+
+/// Whether `msg` consists of the line terminator only, either "\n" or "\r\n".
+fn is_line_end(msg: &[u8]) -> bool {
+    msg == b"\n" || msg == b"\r\n"
+}
+
+impl fmt::Debug for Controller {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Controller")
+            .field("mapping", &self.mapping)
+            .field("stdin_writer", &self.stdin_writer().is_some())
+            .finish()
+    }
+}
+
+impl fmt::Debug for Agent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Agent")
+            .field("idx", &self.idx.0)
+            .field("mapping", &self.mapping)
+            .field("encoding", &self.encoding())
+            .finish()
+    }
+}
+
+/// Data is shown as its length only, since messages may carry large payloads.
+impl<'a> fmt::Debug for MessageKind<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MessageKind::Data(data) => f
+                .debug_tuple("Data")
+                .field(&format_args!("{} bytes", data.len()))
+                .finish(),
+            MessageKind::TrackedData(data) => f
+                .debug_tuple("TrackedData")
+                .field(&format_args!("{} bytes", data.len()))
+                .finish(),
+            MessageKind::Terminate => f.write_str("Terminate"),
+            MessageKind::Resume => f.write_str("Resume"),
+            MessageKind::Done => f.write_str("Done"),
+            MessageKind::Version(version) => f.debug_tuple("Version").field(version).finish(),
+            MessageKind::Query => f.write_str("Query"),
+            MessageKind::Encoding(encoding) => f.debug_tuple("Encoding").field(encoding).finish(),
+        }
+    }
+}
+
+impl<'a> fmt::Debug for Message<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Message")
+            .field("agent_idx", &self.agent_idx().map(|idx| idx.0))
+            .field("kind", &self.kind)
+            .finish()
+    }
+}
+
+impl fmt::Display for StalledStdin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Agent#{} didn't read its stdin for {:.1}s",
+            self.agent.0 + 1,
+            self.stalled_for.as_secs_f64()
+        )
+    }
+}
+
+impl From<ProgramStatus> for AgentStatus {
+    fn from(status: ProgramStatus) -> Self {
+        Self {
+            wall_clock_time: Some(status.wall_clock_time),
+            peak_memory: status.peak_memory,
+        }
+    }
+}
+
+impl AgentStatus {
+    const MEGABYTE: u64 = 1 << 20;
+
+    /// Encodes the reply to a status query about the given agent.
+    pub fn encode(&self, agent_idx: AgentIdx) -> String {
+        let time = self
+            .wall_clock_time
+            .map_or("-".to_string(), |t| format!("{:.3}s", t.as_secs_f64()));
+        let memory = self.peak_memory.map_or("-".to_string(), |m| {
+            format!("{}M", m.div_ceil(Self::MEGABYTE))
+        });
+        format!("{}?={},{}#\n", agent_idx.0 + 1, time, memory)
+    }
+
+    /// Parses a reply produced by `encode`.
+    #[cfg(test)]
+    pub fn parse(data: &[u8]) -> Result<(AgentIdx, Self)> {
+        let invalid = || {
+            Error::from(format!(
+                "Invalid status reply '{}'",
+                String::from_utf8_lossy(data).trim_end()
+            ))
+        };
+        let reply = str::from_utf8(data)
+            .ok()
+            .and_then(|s| s.strip_suffix("#\n"))
+            .ok_or_else(invalid)?;
+        let (idx, values) = reply.split_at(reply.find("?=").ok_or_else(invalid)?);
+        let agent_idx = match idx.parse::<usize>() {
+            Ok(idx) if idx > 0 => AgentIdx(idx - 1),
+            _ => return Err(invalid()),
+        };
+        let mut values = values[2..].split(',');
+        let (time, memory) = match (values.next(), values.next(), values.next()) {
+            (Some(time), Some(memory), None) => (time, memory),
+            _ => return Err(invalid()),
+        };
+
+        let wall_clock_time = match time {
+            "-" => None,
+            _ => time
+                .strip_suffix('s')
+                .and_then(|t| t.parse::<f64>().ok())
+                .map(|t| Some(Duration::from_millis((t * 1000.0).round() as u64)))
+                .ok_or_else(invalid)?,
+        };
+        let peak_memory = match memory {
+            "-" => None,
+            _ => memory
+                .strip_suffix('M')
+                .and_then(|m| m.parse::<u64>().ok())
+                .map(|m| Some(m * Self::MEGABYTE))
+                .ok_or_else(invalid)?,
+        };
+        Ok((
+            agent_idx,
+            Self {
+                wall_clock_time,
+                peak_memory,
+            },
+        ))
+    }
+}
+
+#[cfg(feature = "json-events")]
+impl<'a> AgentEvent<'a> {
+    /// Parses a data line written by an agent, see `AgentStdout`.
+    pub fn parse(line: &'a [u8]) -> Result<(AgentIdx, Self)> {
+        let msg = Message::parse(line)?;
+        let agent_idx = match (msg.agents, msg.kind()) {
+            (Some((first, last)), MessageKind::Data(_)) if first == last => first,
+            _ => {
+                return Err(Error::from(format!(
+                    "Invalid agent data line '{}'",
+                    String::from_utf8_lossy(line).trim_end()
+                )))
+            }
+        };
+        let payload = msg.payload();
+        let event = str::from_utf8(payload)
+            .ok()
+            .and_then(|s| json::parse(s).ok())
+            .map_or(AgentEvent::Raw(payload), AgentEvent::Json);
+        Ok((agent_idx, event))
+    }
+
+    /// The `"type"` member of a JSON object event.
+    pub fn event_type(&self) -> Option<&str> {
+        match self {
+            AgentEvent::Json(value) => value["type"].as_str(),
+            AgentEvent::Raw(_) => None,
+        }
+    }
+
+    /// The `"data"` member of a JSON object event.
+    pub fn data(&self) -> Option<&JsonValue> {
+        match self {
+            AgentEvent::Json(value) if value.has_key("data") => Some(&value["data"]),
+            _ => None,
+        }
+    }
+}
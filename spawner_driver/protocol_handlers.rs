@@ -1,23 +1,33 @@
-use crate::protocol_entities::{Agent, AgentIdx, Controller, Message, MessageKind};
+use crate::protocol_entities::{
+    Agent, AgentIdx, AgentStatus, Controller, Message, MessageKind, StreamEncoding,
+    PROTOCOL_VERSION,
+};
 
 use spawner::dataflow::{Connection, DestinationId, SourceReader};
-use spawner::pipe::ReadPipe;
+use spawner::pipe::{ReadPipe, ReadResult};
 use spawner::{Error, Result};
 
 use std::collections::HashMap;
 use std::io::{BufRead, BufReader};
+use std::sync::mpsc::{channel, Sender};
+use std::thread;
 
 pub struct ControllerStdout {
     controller: Controller,
     agents: Vec<Agent>,
     agent_by_stdin_id: HashMap<DestinationId, AgentIdx>,
+    /// The protocol version declared by the controller, or `PROTOCOL_VERSION` if none was.
+    version: u32,
+    /// Status queries answered by a separate thread, see `send_query`.
+    queries: Option<Sender<Agent>>,
 }
 
 pub struct AgentStdout(Agent);
 
-struct MessageBuf {
+pub(crate) struct MessageBuf {
     buf: Vec<u8>,
     max_size: usize,
+    seq: usize,
 }
 
 impl ControllerStdout {
@@ -29,42 +39,138 @@ impl ControllerStdout {
             .collect();
         Self {
             controller,
-            agents,
             agent_by_stdin_id,
+            version: PROTOCOL_VERSION,
+            agents,
+            queries: None,
         }
     }
 
-    fn handle_msg(&self, msg: Message, connections: &mut [Connection]) -> Result<()> {
+    /// Returns `false` if the controller has finished the session.
+    fn handle_msg(&mut self, msg: Message, connections: &mut [Connection]) -> Result<bool> {
         self.controller.reset_time();
 
-        if let Some(agent_idx) = msg.agent_idx() {
-            if agent_idx.0 >= self.agents.len() {
+        if let MessageKind::Version(version) = *msg.kind() {
+            if msg.seq() != 0 {
+                return Err(Error::from(
+                    "Protocol version must be declared in the first controller message",
+                ));
+            }
+            self.version = version;
+            return Ok(true);
+        }
+        if self.version < 2 && matches!(msg.kind(), MessageKind::Done) {
+            return Err(Error::from(format!(
+                "Controller command 'E' is not supported by protocol version {}",
+                self.version
+            )));
+        }
+
+        for agent_idx in msg.agents() {
+            if AgentIdx::new(agent_idx.0, self.agents.len()).is_none() {
                 return Err(Error::from(format!(
                     "Agent index '{}' is out of range",
                     agent_idx.0 + 1,
                 )));
             }
+        }
 
+        for agent_idx in msg.agents() {
             let agent = &self.agents[agent_idx.0];
             match msg.kind() {
                 MessageKind::Terminate => agent.terminate(),
                 MessageKind::Resume => agent.resume(),
+                MessageKind::Done => {
+                    return Err(Error::from(format!(
+                        "Controller command 'E' cannot be sent to agent#{}",
+                        agent_idx.0 + 1
+                    )));
+                }
+                MessageKind::Query => {
+                    let agent = agent.clone();
+                    self.send_query(agent);
+                }
+                MessageKind::Encoding(encoding) => agent.set_encoding(*encoding),
+                // Tracked before the data is sent, so the agent can't reply first.
+                MessageKind::TrackedData(_) => self.send_delivery_status(agent),
+                _ => {}
+            }
+        }
+        if msg.agent_idx().is_none() {
+            match msg.kind() {
+                MessageKind::TrackedData(_) => {
+                    return Err(Error::from(
+                        "Controller command 'A' must be sent to an agent",
+                    ))
+                }
+                MessageKind::Query => {
+                    return Err(Error::from(
+                        "Controller command '?' must be sent to an agent",
+                    ))
+                }
+                MessageKind::Encoding(_) => {
+                    return Err(Error::from(
+                        "Controller command 'N' must be sent to an agent",
+                    ))
+                }
                 _ => {}
             }
         }
 
+        let done = matches!(msg.kind(), MessageKind::Done);
         self.transmit_msg(msg, connections);
-        Ok(())
+        Ok(!done)
     }
 
+    /// Replies to the status query from a separate thread, so that reading the controller's
+    /// stdout never waits for the agent's supervisor or for the controller to read its stdin.
+    /// Replies are written in the order of the queries.
+    fn send_query(&mut self, agent: Agent) {
+        let controller = &self.controller;
+        let queries = self.queries.get_or_insert_with(|| {
+            let (sender, receiver) = channel::<Agent>();
+            let controller = controller.clone();
+            thread::spawn(move || {
+                for agent in receiver {
+                    let status = agent
+                        .query_status()
+                        .map_or(AgentStatus::default(), AgentStatus::from);
+                    // The controller's stdin is closed once every agent has exited, there is
+                    // nobody to reply to then.
+                    let _ = controller.write_message(status.encode(agent.idx()).as_bytes());
+                }
+            });
+            sender
+        });
+        let _ = queries.send(agent);
+    }
+
+    /// Replies with the `DeliveryStatus` of the data sent to the agent from a separate thread,
+    /// once the agent replies to it or exits.
+    fn send_delivery_status(&self, agent: &Agent) {
+        let delivery = agent.track_delivery();
+        let agent_idx = agent.idx();
+        let controller = self.controller.clone();
+        thread::spawn(move || {
+            let _ = controller.write_message(delivery.wait().encode(agent_idx).as_bytes());
+        });
+    }
+
+    /// Data is sent to the addressed agents that read the controller's stdout. Agents whose
+    /// stdin comes from elsewhere, e.g. a file, have no connection here, so data addressed to
+    /// them is dropped, while control messages still reach them through `Agent`. Data is
+    /// transcoded into the encoding set for the agent and always ends with '\n', files get the
+    /// raw messages.
     fn transmit_msg(&self, msg: Message, connections: &mut [Connection]) {
         for c in connections {
             let agent_idx = self.agent_by_stdin_id.get(&c.destination_id()).copied();
 
             match (agent_idx, msg.kind()) {
-                (Some(_), MessageKind::Data(data)) => {
-                    if agent_idx == msg.agent_idx() {
-                        c.send(data);
+                (Some(idx), MessageKind::Data(data))
+                | (Some(idx), MessageKind::TrackedData(data)) => {
+                    if msg.agents().any(|i| i == idx) {
+                        let line = [data, &b"\n"[..]].concat();
+                        c.send(&self.agents[idx.0].encoding().encode(&line));
                     }
                 }
                 (Some(_), _) => {
@@ -79,20 +185,37 @@ impl ControllerStdout {
     }
 
     fn read_stdout(&mut self, stdout: &mut ReadPipe, connections: &mut [Connection]) -> Result<()> {
-        let mut stdout_reader = BufReader::new(stdout);
+        let mut buf = [0u8; 8192];
         let mut msg_buf = MessageBuf::new();
-        let mut data_len = 0;
+        let mut session_finished = false;
         loop {
-            stdout_reader.consume(data_len);
-            let data = stdout_reader.fill_buf().unwrap_or(&[]);
-            data_len = data.len();
-            if data_len == 0 {
-                return Ok(());
+            let data = match stdout.read_to_buf(&mut buf) {
+                Ok(ReadResult::Data(n)) => &buf[..n],
+                // The controller has closed its stdout.
+                Ok(ReadResult::Eof) | Err(_) => return Ok(()),
+            };
+            if session_finished {
+                // Drain the rest of the output so the controller never blocks on a full pipe.
+                continue;
             }
 
             let mut next_msg_data = msg_buf.write(data)?;
             while msg_buf.is_msg_ready() {
-                self.handle_msg(msg_buf.as_msg()?, connections)?;
+                let msg = msg_buf.as_msg()?;
+                // Tell which message was rejected, to help finding protocol desyncs.
+                let (seq, crc) = (msg.seq(), msg.crc32());
+                let handled = self.handle_msg(msg, connections).map_err(|e| {
+                    Error::from(format!(
+                        "{} (controller message {}, payload crc32 {:08x})",
+                        e, seq, crc
+                    ))
+                })?;
+                if !handled {
+                    session_finished = true;
+                    // Treat the rest of the session as if the controller has exited.
+                    self.agents.iter().for_each(Agent::resume);
+                    break;
+                }
                 msg_buf.clear();
                 next_msg_data = msg_buf.write(next_msg_data)?;
             }
@@ -124,8 +247,11 @@ impl AgentStdout {
         let mut stdout_reader = BufReader::new(stdout);
         let mut msg_buf = MessageBuf::new();
         let msg_prefix = format!("{}#", self.0.idx().0 + 1);
+        let done_message = format!("{}E#\n", self.0.idx().0 + 1);
         msg_buf.write(msg_prefix.as_bytes()).unwrap();
         let mut data_len = 0;
+        // Undecoded bytes of an incomplete character, see `StreamEncoding::decode`.
+        let mut pending = Vec::new();
 
         loop {
             stdout_reader.consume(data_len);
@@ -135,12 +261,32 @@ impl AgentStdout {
                 return Ok(());
             }
 
+            let encoding = self.0.encoding();
+            let decoded;
+            let data = if encoding == StreamEncoding::Utf8 && pending.is_empty() {
+                data
+            } else {
+                pending.extend_from_slice(data);
+                let (utf8, consumed) = encoding.decode(&pending);
+                decoded = utf8.into_owned();
+                pending.drain(..consumed);
+                &decoded[..]
+            };
+
             let mut next_msg_data = msg_buf.write(data)?;
             while msg_buf.is_msg_ready() {
                 self.0.suspend();
-
+                self.0.acknowledge();
+
+                // Agents on Windows may end the line with "\r\n".
+                let line = &msg_buf.as_slice()[msg_prefix.len()..];
+                let msg = if line == b"E#\n" || line == b"E#\r\n" {
+                    done_message.as_bytes()
+                } else {
+                    msg_buf.as_slice()
+                };
                 for c in connections.iter_mut() {
-                    c.send(msg_buf.as_slice());
+                    c.send(msg);
                 }
 
                 msg_buf.clear();
@@ -160,6 +306,7 @@ impl SourceReader for AgentStdout {
         });
 
         // No more data is available to read.
+        self.0.close_deliveries();
         let term_message = format!("{}T#\n", self.0.idx().0 + 1);
         for c in connections.iter_mut() {
             c.send(term_message.as_bytes());
@@ -170,14 +317,15 @@ impl SourceReader for AgentStdout {
 }
 
 impl MessageBuf {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             buf: Vec::new(),
             max_size: 65536, // Default buffer size in c++ spawner.
+            seq: 0,
         }
     }
 
-    fn write<'a>(&mut self, data: &'a [u8]) -> Result<&'a [u8]> {
+    pub(crate) fn write<'a>(&mut self, data: &'a [u8]) -> Result<&'a [u8]> {
         let data_len = match data.iter().position(|&b| b == b'\n') {
             Some(pos) => pos + 1,
             None => data.len(),
@@ -191,19 +339,21 @@ impl MessageBuf {
         }
     }
 
-    fn clear(&mut self) {
+    /// Clears the buffer and advances the sequence number of the next message.
+    pub(crate) fn clear(&mut self) {
         self.buf.clear();
+        self.seq += 1;
     }
 
-    fn is_msg_ready(&self) -> bool {
+    pub(crate) fn is_msg_ready(&self) -> bool {
         self.buf.ends_with(&[b'\n'])
     }
 
-    fn as_slice(&self) -> &[u8] {
+    pub(crate) fn as_slice(&self) -> &[u8] {
         self.buf.as_slice()
     }
 
-    fn as_msg(&self) -> Result<Message> {
-        Message::parse(self.as_slice())
+    pub(crate) fn as_msg(&self) -> Result<Message<'_>> {
+        Message::parse(self.as_slice()).map(|msg| msg.with_seq(self.seq))
     }
 }
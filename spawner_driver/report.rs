@@ -7,6 +7,7 @@ use spawner::{Error, ProgramResult, TerminationReason};
 use json::{array, object, JsonValue};
 
 use std::fmt::{self, Display, Formatter};
+use std::io::BufRead;
 
 #[derive(Debug)]
 pub struct Report {
@@ -21,17 +22,53 @@ pub struct Report {
     pub stdin: Vec<String>,
     pub stdout: Vec<String>,
     pub stderr: Vec<String>,
+    /// Bytes written to stdout, `None` if spawner didn't read the stream, e.g. when stdout
+    /// goes straight to a single file or is discarded, see `spawner::Report::stdout_bytes`.
+    pub stdout_bytes: Option<u64>,
+    /// The same as `stdout_bytes` for stderr, also `None` when stderr goes straight to a
+    /// single file.
+    pub stderr_bytes: Option<u64>,
     pub result: ReportResult,
     pub terminate_reason: TerminateReason,
     pub exit_code: u32,
     pub exit_status: String,
     pub spawner_error: Vec<Error>,
+    /// Processes spawned by the application. Empty if per-process accounting is not
+    /// available on the platform.
+    pub children: Vec<ChildInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChildInfo {
+    pub pid: u32,
+    /// `None` if the process is still running or its exit code is unknown.
+    pub exit_code: Option<u32>,
+    /// Peak memory usage in bytes, `None` if not tracked.
+    pub peak_memory: Option<u64>,
 }
 
 #[derive(Debug, PartialEq)]
 pub enum ReportKind {
     Json,
     Legacy,
+    /// One row per report, fields are separated by the given character. A report is displayed
+    /// as its row only, the header is written by `TableReport`.
+    Table(char),
+}
+
+/// A column of `TableReport`, named after the corresponding JSON report field.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ReportColumn {
+    Application,
+    Arguments,
+    Time,
+    WallClockTime,
+    Memory,
+    BytesWritten,
+    TerminateReason,
+    ExitCode,
+    ExitStatus,
+    SpawnerError,
 }
 
 #[derive(Debug)]
@@ -107,33 +144,129 @@ struct FltSecsOrInf(Option<f64>);
 struct Mb(f64);
 struct FltSecs(f64);
 
+/// The exit codes used by `Report::to_exit_code` for runs terminated by spawner.
+pub const DEFAULT_EXIT_CODES: [(TerminateReason, i32); 8] = [
+    (TerminateReason::TimeLimitExceeded, 10),
+    (TerminateReason::IdleTimeLimitExceeded, 11),
+    (TerminateReason::WriteLimitExceeded, 12),
+    (TerminateReason::MemoryLimitExceeded, 13),
+    (TerminateReason::ProcessesCountLimitExceeded, 14),
+    (TerminateReason::ActiveProcessesCountLimitExceeded, 15),
+    (TerminateReason::ActiveConnectionCountLimitExceeded, 16),
+    (TerminateReason::TerminatedByController, 17),
+];
+
+/// The columns used when a report is printed as a table.
+pub const DEFAULT_REPORT_COLUMNS: [ReportColumn; 10] = [
+    ReportColumn::Application,
+    ReportColumn::Arguments,
+    ReportColumn::Time,
+    ReportColumn::WallClockTime,
+    ReportColumn::Memory,
+    ReportColumn::BytesWritten,
+    ReportColumn::TerminateReason,
+    ReportColumn::ExitCode,
+    ReportColumn::ExitStatus,
+    ReportColumn::SpawnerError,
+];
+
+/// A relative change of time or memory usage above which `ReportDiff` counts it as
+/// significant, 10% of the baseline.
+pub const SIGNIFICANT_CHANGE: f64 = 0.1;
+
+/// The outcome of a baseline run and a new run side by side, see `Report::diff`. Every pair is
+/// `(baseline, new)`. Displayed as one line per value with significant changes marked by `*`,
+/// exit code and terminate reason are shown only if they have changed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportDiff {
+    pub time: (f64, f64),
+    pub wall_clock_time: (f64, f64),
+    pub memory: (u64, u64),
+    pub exit_code: (u32, u32),
+    pub terminate_reason: (TerminateReason, TerminateReason),
+}
+
+/// Reports rendered as a header row followed by one row per report, e.g. for importing
+/// into a spreadsheet. Fields containing the separator, quotes or line breaks are quoted.
+pub struct TableReport<'a> {
+    pub reports: &'a [&'a Report],
+    pub columns: &'a [ReportColumn],
+    pub separator: char,
+}
+
+/// Called with the index of the command and its report, see `AggregateReport::new`.
+type ReportCallback<'a> = Box<dyn FnMut(usize, &Report) + 'a>;
+
+/// Reports of every program of a run, assembled as the programs finish rather than after
+/// the whole run. Each report is passed to the callback as soon as it is added, so reports
+/// are emitted in completion order, whereas `into_reports` returns them in command order,
+/// the same as `Driver::run`.
+pub struct AggregateReport<'a> {
+    reports: Vec<Option<Report>>,
+    on_report: ReportCallback<'a>,
+}
+
 impl Report {
     pub fn new(cmd: &Command, result: ProgramResult) -> Self {
         let mut report = Report::from(cmd);
         match result {
             Ok(runner_report) => {
                 report.result = ReportResult::from(&runner_report);
+                report.stdout_bytes = runner_report.stdout_bytes;
+                report.stderr_bytes = runner_report.stderr_bytes;
+                report.children = runner_report
+                    .children
+                    .iter()
+                    .map(|p| ChildInfo {
+                        pid: p.pid,
+                        exit_code: None,
+                        peak_memory: p.peak_memory,
+                    })
+                    .collect();
+                let mut reasons = Vec::new();
                 match runner_report.exit_status {
                     ExitStatus::Finished(code) => {
                         report.exit_code = code;
                         report.exit_status = code.to_string();
                     }
                     ExitStatus::Crashed(cause) => {
-                        report.terminate_reason = TerminateReason::AbnormalExitProcess;
+                        reasons.push(TerminateReason::AbnormalExitProcess);
                         report.exit_status = cause;
                     }
                 }
-                if let Some(tr) = runner_report.termination_reason {
-                    report.terminate_reason = TerminateReason::from(tr);
-                }
+                reasons.extend(runner_report.termination_reason.map(TerminateReason::from));
+                report.terminate_reason = TerminateReason::resolve(&reasons);
             }
             Err(e) => report.spawner_error = e.errors,
         }
         report
     }
 
+    /// Maps the outcome of the run to an exit code using `DEFAULT_EXIT_CODES`, see
+    /// `to_exit_code_with`.
+    pub fn to_exit_code(&self) -> i32 {
+        self.to_exit_code_with(&DEFAULT_EXIT_CODES)
+    }
+
+    /// Maps the outcome of the run to an exit code. The code is 1 if the program couldn't be
+    /// run, otherwise the code of the terminate reason is looked up in `table`. Reasons that
+    /// are not in the table pass the program's exit code through, except that a crashed
+    /// program with a zero exit code maps to 1.
+    pub fn to_exit_code_with(&self, table: &[(TerminateReason, i32)]) -> i32 {
+        if !self.spawner_error.is_empty() {
+            return 1;
+        }
+        match table.iter().find(|(tr, _)| *tr == self.terminate_reason) {
+            Some(&(_, code)) => code,
+            None => match self.terminate_reason {
+                TerminateReason::AbnormalExitProcess if self.exit_code == 0 => 1,
+                _ => self.exit_code as i32,
+            },
+        }
+    }
+
     pub fn to_json(&self) -> JsonValue {
-        object! {
+        let mut report = object! {
             "Application" => self.application.clone(),
             "Arguments" => self.arguments.clone(),
             "Limit" => self.limit.to_json(),
@@ -178,6 +311,89 @@ impl Report {
                     })
                     .collect::<Vec<JsonValue>>().into()
             }
+        };
+        if let Some(n) = self.stdout_bytes {
+            report["StdOutBytes"] = n.into();
+        }
+        if let Some(n) = self.stderr_bytes {
+            report["StdErrBytes"] = n.into();
+        }
+        if !self.children.is_empty() {
+            report["Children"] = self
+                .children
+                .iter()
+                .map(ChildInfo::to_json)
+                .collect::<Vec<JsonValue>>()
+                .into();
+        }
+        report
+    }
+
+    /// Parses a report produced by `to_json`. Missing fields get their default values, fields
+    /// of the wrong type are an error. The parsed report is printed as JSON.
+    pub fn from_json(value: &JsonValue) -> Result<Self, Error> {
+        if !value.is_object() {
+            return Err(Error::from("Report must be an object"));
+        }
+        let application = json_str(value, "Application")?
+            .ok_or_else(|| Error::from("Missing field 'Application'"))?;
+        let result = &value["Result"];
+        let terminate_reason = match json_str(value, "TerminateReason")? {
+            Some(reason) => TerminateReason::from_name(&reason)
+                .ok_or_else(|| Error::from(format!("Invalid terminate reason '{}'", reason)))?,
+            None => TerminateReason::ExitProcess,
+        };
+        let spawner_error = json_strings(value, "SpawnerError")?
+            .into_iter()
+            .filter(|e| e != "<none>")
+            .map(Error::from)
+            .collect();
+        let children = value["Children"]
+            .members()
+            .map(ChildInfo::from_json)
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            application,
+            arguments: json_strings(value, "Arguments")?,
+            kind: ReportKind::Json,
+            limit: ReportLimit::from_json(&value["Limit"])?,
+            options: ReportOptions {
+                search_in_path: json_bool(&value["Options"], "SearchInPath")?.unwrap_or(false),
+                debug: false,
+            },
+            working_directory: json_str(value, "WorkingDirectory")?.filter(|s| !s.is_empty()),
+            create_process_method: json_str(value, "CreateProcessMethod")?.unwrap_or_default(),
+            username: json_str(value, "UserName")?.filter(|s| !s.is_empty()),
+            stdin: json_strings(value, "StdIn")?,
+            stdout: json_strings(value, "StdOut")?,
+            stderr: json_strings(value, "StdErr")?,
+            stdout_bytes: json_u64(value, "StdOutBytes")?,
+            stderr_bytes: json_u64(value, "StdErrBytes")?,
+            result: ReportResult {
+                time: json_f64(result, "Time")?.unwrap_or_default(),
+                wall_clock_time: json_f64(result, "WallClockTime")?.unwrap_or_default(),
+                memory: json_u64(result, "Memory")?.unwrap_or_default(),
+                bytes_written: json_u64(result, "BytesWritten")?.unwrap_or_default(),
+                kernel_time: json_f64(result, "KernelTime")?.unwrap_or_default(),
+                processor_load: json_f64(result, "ProcessorLoad")?.unwrap_or_default(),
+                processes_created: 0,
+            },
+            terminate_reason,
+            exit_code: json_u64(value, "ExitCode")?.unwrap_or_default() as u32,
+            exit_status: json_str(value, "ExitStatus")?.unwrap_or_default(),
+            spawner_error,
+            children,
+        })
+    }
+
+    /// Compares the report of a baseline run with the report of a new run, `other`.
+    pub fn diff(&self, other: &Report) -> ReportDiff {
+        ReportDiff {
+            time: (self.result.time, other.result.time),
+            wall_clock_time: (self.result.wall_clock_time, other.result.wall_clock_time),
+            memory: (self.result.memory, other.result.memory),
+            exit_code: (self.exit_code, other.exit_code),
+            terminate_reason: (self.terminate_reason, other.terminate_reason),
         }
     }
 
@@ -209,6 +425,12 @@ impl Display for Report {
         match self.kind {
             ReportKind::Json => write!(f, "{:#}", self.to_json()),
             ReportKind::Legacy => write!(f, "{}", self.as_legacy()),
+            ReportKind::Table(separator) => TableReport {
+                reports: &[self],
+                columns: &DEFAULT_REPORT_COLUMNS,
+                separator,
+            }
+            .write_rows(f),
         }
     }
 }
@@ -224,6 +446,10 @@ impl From<&Command> for Report {
             arguments: argv.cloned().collect(),
             kind: if cmd.use_json {
                 ReportKind::Json
+            } else if cmd.use_csv {
+                ReportKind::Table(',')
+            } else if cmd.use_tsv {
+                ReportKind::Table('\t')
             } else {
                 ReportKind::Legacy
             },
@@ -238,21 +464,112 @@ impl From<&Command> for Report {
             stdin: Vec::from(&cmd.stdin_redirect),
             stdout: Vec::from(&cmd.stdout_redirect),
             stderr: Vec::from(&cmd.stderr_redirect),
+            stdout_bytes: None,
+            stderr_bytes: None,
             result: ReportResult::default(),
             terminate_reason: TerminateReason::ExitProcess,
             exit_code: 0,
             exit_status: "0".to_string(),
             spawner_error: Vec::new(),
+            children: Vec::new(),
         }
     }
 }
 
+impl ChildInfo {
+    fn to_json(&self) -> JsonValue {
+        object! {
+            "Pid" => self.pid,
+            "ExitCode" => self.exit_code,
+            "PeakMemory" => self.peak_memory,
+        }
+    }
+
+    fn from_json(value: &JsonValue) -> Result<Self, Error> {
+        Ok(Self {
+            pid: json_u64(value, "Pid")?.unwrap_or_default() as u32,
+            exit_code: json_u64(value, "ExitCode")?.map(|c| c as u32),
+            peak_memory: json_u64(value, "PeakMemory")?,
+        })
+    }
+}
+
 impl ReportKind {
     pub fn is_json(&self) -> bool {
         matches!(self, ReportKind::Json)
     }
 }
 
+impl ReportColumn {
+    fn name(&self) -> &'static str {
+        match self {
+            ReportColumn::Application => "Application",
+            ReportColumn::Arguments => "Arguments",
+            ReportColumn::Time => "Time",
+            ReportColumn::WallClockTime => "WallClockTime",
+            ReportColumn::Memory => "Memory",
+            ReportColumn::BytesWritten => "BytesWritten",
+            ReportColumn::TerminateReason => "TerminateReason",
+            ReportColumn::ExitCode => "ExitCode",
+            ReportColumn::ExitStatus => "ExitStatus",
+            ReportColumn::SpawnerError => "SpawnerError",
+        }
+    }
+
+    fn value(&self, report: &Report) -> String {
+        match self {
+            ReportColumn::Application => report.application.clone(),
+            ReportColumn::Arguments => report.arguments.join(" "),
+            ReportColumn::Time => report.result.time.to_string(),
+            ReportColumn::WallClockTime => report.result.wall_clock_time.to_string(),
+            ReportColumn::Memory => report.result.memory.to_string(),
+            ReportColumn::BytesWritten => report.result.bytes_written.to_string(),
+            ReportColumn::TerminateReason => report.terminate_reason.to_string(),
+            ReportColumn::ExitCode => report.exit_code.to_string(),
+            ReportColumn::ExitStatus => report.exit_status.clone(),
+            ReportColumn::SpawnerError => report
+                .spawner_error
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+                .join("; "),
+        }
+    }
+}
+
+impl<'a> TableReport<'a> {
+    fn write_rows(&self, f: &mut Formatter) -> fmt::Result {
+        for report in self.reports {
+            self.write_row(f, self.columns.iter().map(|c| c.value(report)))?;
+        }
+        Ok(())
+    }
+
+    fn write_row<I>(&self, f: &mut Formatter, fields: I) -> fmt::Result
+    where
+        I: Iterator<Item = String>,
+    {
+        for (idx, field) in fields.enumerate() {
+            if idx > 0 {
+                write!(f, "{}", self.separator)?;
+            }
+            if field.contains([self.separator, '"', '\n', '\r']) {
+                write!(f, "\"{}\"", field.replace('"', "\"\""))?;
+            } else {
+                f.write_str(&field)?;
+            }
+        }
+        writeln!(f)
+    }
+}
+
+impl<'a> Display for TableReport<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.write_row(f, self.columns.iter().map(|c| c.name().to_string()))?;
+        self.write_rows(f)
+    }
+}
+
 impl From<&spawner::Report> for ReportResult {
     fn from(report: &spawner::Report) -> Self {
         let timers = report.timers.unwrap_or_default();
@@ -300,6 +617,18 @@ impl ReportLimit {
         }
         limit
     }
+
+    fn from_json(value: &JsonValue) -> Result<Self, Error> {
+        Ok(Self {
+            time: json_f64(value, "Time")?,
+            wall_clock_time: json_f64(value, "WallClockTime")?,
+            memory: json_u64(value, "Memory")?,
+            security_level: json_u64(value, "SecurityLevel")?.map(|lvl| lvl as u32),
+            io_bytes: json_u64(value, "IOBytes")?,
+            idleness_time: json_f64(value, "IdlenessTime")?,
+            idleness_processor_load: json_f64(value, "IdlenessProcessorLoad")?,
+        })
+    }
 }
 
 impl From<&Command> for ReportLimit {
@@ -316,6 +645,216 @@ impl From<&Command> for ReportLimit {
     }
 }
 
+impl<'a> AggregateReport<'a> {
+    /// Creates an aggregate of `len` reports, `on_report` is called with the index of the
+    /// command and its report.
+    pub fn new<F>(len: usize, on_report: F) -> Self
+    where
+        F: FnMut(usize, &Report) + 'a,
+    {
+        Self {
+            reports: (0..len).map(|_| None).collect(),
+            on_report: Box::new(on_report),
+        }
+    }
+
+    /// Adds the report of the command `idx` and emits it. Fails if `idx` is out of range or
+    /// the report of the command has already been added.
+    pub fn add(&mut self, idx: usize, report: Report) -> Result<(), Error> {
+        match self.reports.get_mut(idx) {
+            Some(slot @ None) => {
+                (self.on_report)(idx, slot.insert(report));
+                Ok(())
+            }
+            Some(Some(_)) => Err(Error::from(format!(
+                "Report #{} has already been added",
+                idx
+            ))),
+            None => Err(Error::from(format!(
+                "Report #{} is out of range, there are {} reports",
+                idx,
+                self.reports.len()
+            ))),
+        }
+    }
+
+    /// Adds every `(idx, report)` pair as it is yielded, e.g. from a `Receiver` filled by the
+    /// threads waiting for the programs. Stops at the first error.
+    pub fn add_all<I>(&mut self, reports: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = (usize, Report)>,
+    {
+        reports
+            .into_iter()
+            .try_for_each(|(idx, report)| self.add(idx, report))
+    }
+
+    /// Whether the report of every command has been added.
+    pub fn is_complete(&self) -> bool {
+        self.reports.iter().all(Option::is_some)
+    }
+
+    /// The reports added so far in command order.
+    pub fn reports(&self) -> impl Iterator<Item = &Report> {
+        self.reports.iter().flatten()
+    }
+
+    /// Returns the reports added so far in command order, missing reports are skipped.
+    pub fn into_reports(self) -> Vec<Report> {
+        self.reports.into_iter().flatten().collect()
+    }
+}
+
+impl ReportDiff {
+    /// The change of user time in seconds, negative if the new run is faster.
+    pub fn time_delta(&self) -> f64 {
+        self.time.1 - self.time.0
+    }
+
+    /// The change of wall clock time in seconds.
+    pub fn wall_clock_time_delta(&self) -> f64 {
+        self.wall_clock_time.1 - self.wall_clock_time.0
+    }
+
+    /// The change of peak memory usage in bytes.
+    pub fn memory_delta(&self) -> i64 {
+        self.memory.1 as i64 - self.memory.0 as i64
+    }
+
+    /// Whether user time has changed by more than `SIGNIFICANT_CHANGE`.
+    pub fn is_time_significant(&self) -> bool {
+        is_significant(self.time.0, self.time.1)
+    }
+
+    pub fn is_wall_clock_time_significant(&self) -> bool {
+        is_significant(self.wall_clock_time.0, self.wall_clock_time.1)
+    }
+
+    pub fn is_memory_significant(&self) -> bool {
+        is_significant(self.memory.0 as f64, self.memory.1 as f64)
+    }
+
+    /// Whether the runs differ in a way worth reporting: a significant change of time or
+    /// memory, or a different exit code or terminate reason.
+    pub fn is_significant(&self) -> bool {
+        self.is_time_significant()
+            || self.is_wall_clock_time_significant()
+            || self.is_memory_significant()
+            || self.exit_code.0 != self.exit_code.1
+            || self.terminate_reason.0 != self.terminate_reason.1
+    }
+}
+
+fn is_significant(baseline: f64, new: f64) -> bool {
+    if baseline == 0.0 {
+        new != 0.0
+    } else {
+        ((new - baseline) / baseline).abs() > SIGNIFICANT_CHANGE
+    }
+}
+
+impl Display for ReportDiff {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mark = |significant| if significant { '*' } else { ' ' };
+        let percent = |baseline: f64, new: f64| {
+            if baseline == 0.0 {
+                String::new()
+            } else {
+                format!(", {:+.1}%", (new - baseline) / baseline * 100.0)
+            }
+        };
+        writeln!(
+            f,
+            "{} Time: {} -> {} ({:+}{})",
+            mark(self.is_time_significant()),
+            self.time.0,
+            self.time.1,
+            self.time_delta(),
+            percent(self.time.0, self.time.1)
+        )?;
+        writeln!(
+            f,
+            "{} WallClockTime: {} -> {} ({:+}{})",
+            mark(self.is_wall_clock_time_significant()),
+            self.wall_clock_time.0,
+            self.wall_clock_time.1,
+            self.wall_clock_time_delta(),
+            percent(self.wall_clock_time.0, self.wall_clock_time.1)
+        )?;
+        writeln!(
+            f,
+            "{} Memory: {} -> {} ({:+}{})",
+            mark(self.is_memory_significant()),
+            self.memory.0,
+            self.memory.1,
+            self.memory_delta(),
+            percent(self.memory.0 as f64, self.memory.1 as f64)
+        )?;
+        if self.exit_code.0 != self.exit_code.1 {
+            writeln!(
+                f,
+                "* ExitCode: {} -> {}",
+                self.exit_code.0, self.exit_code.1
+            )?;
+        }
+        if self.terminate_reason.0 != self.terminate_reason.1 {
+            writeln!(
+                f,
+                "* TerminateReason: {} -> {}",
+                self.terminate_reason.0, self.terminate_reason.1
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl TerminateReason {
+    /// Picks the reason to report when several limits are exceeded at once. From the highest
+    /// priority to the lowest: memory, time, idle time, write, process count, active process
+    /// count, active connection count limits, termination by the controller, abnormal exit and
+    /// normal exit. Returns `ExitProcess` if `reasons` is empty.
+    pub fn resolve(reasons: &[TerminateReason]) -> TerminateReason {
+        reasons
+            .iter()
+            .copied()
+            .max_by_key(TerminateReason::priority)
+            .unwrap_or(TerminateReason::ExitProcess)
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [
+            TerminateReason::ExitProcess,
+            TerminateReason::AbnormalExitProcess,
+            TerminateReason::TimeLimitExceeded,
+            TerminateReason::IdleTimeLimitExceeded,
+            TerminateReason::WriteLimitExceeded,
+            TerminateReason::MemoryLimitExceeded,
+            TerminateReason::ProcessesCountLimitExceeded,
+            TerminateReason::ActiveProcessesCountLimitExceeded,
+            TerminateReason::ActiveConnectionCountLimitExceeded,
+            TerminateReason::TerminatedByController,
+        ]
+        .iter()
+        .copied()
+        .find(|reason| reason.to_string() == name)
+    }
+
+    fn priority(&self) -> u8 {
+        match self {
+            TerminateReason::ExitProcess => 0,
+            TerminateReason::AbnormalExitProcess => 1,
+            TerminateReason::TerminatedByController => 2,
+            TerminateReason::ActiveConnectionCountLimitExceeded => 3,
+            TerminateReason::ActiveProcessesCountLimitExceeded => 4,
+            TerminateReason::ProcessesCountLimitExceeded => 5,
+            TerminateReason::WriteLimitExceeded => 6,
+            TerminateReason::IdleTimeLimitExceeded => 7,
+            TerminateReason::TimeLimitExceeded => 8,
+            TerminateReason::MemoryLimitExceeded => 9,
+        }
+    }
+}
+
 impl Display for TerminateReason {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         f.write_str(match self {
@@ -357,6 +896,78 @@ impl From<TerminationReason> for TerminateReason {
     }
 }
 
+/// Reads newline-delimited JSON reports, one report per line, e.g. collected from several
+/// `--json` runs. Blank lines are skipped. The error tells the line of the invalid report.
+pub fn read_json_reports<R: BufRead>(reader: R) -> Result<Vec<Report>, Error> {
+    let mut reports = Vec::new();
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let report = json::parse(&line)
+            .map_err(|e| Error::from(e.to_string()))
+            .and_then(|value| Report::from_json(&value))
+            .map_err(|e| Error::from(format!("Invalid report on line {}: {}", idx + 1, e)))?;
+        reports.push(report);
+    }
+    Ok(reports)
+}
+
+fn json_field<'a>(value: &'a JsonValue, name: &str) -> Option<&'a JsonValue> {
+    match &value[name] {
+        JsonValue::Null => None,
+        field => Some(field),
+    }
+}
+
+fn invalid_field(name: &str) -> Error {
+    Error::from(format!("Invalid field '{}'", name))
+}
+
+fn json_str(value: &JsonValue, name: &str) -> Result<Option<String>, Error> {
+    json_field(value, name)
+        .map(|v| {
+            v.as_str()
+                .map(str::to_string)
+                .ok_or_else(|| invalid_field(name))
+        })
+        .transpose()
+}
+
+fn json_strings(value: &JsonValue, name: &str) -> Result<Vec<String>, Error> {
+    match json_field(value, name) {
+        Some(v) if v.is_array() => v
+            .members()
+            .map(|s| {
+                s.as_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| invalid_field(name))
+            })
+            .collect(),
+        Some(_) => Err(invalid_field(name)),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn json_f64(value: &JsonValue, name: &str) -> Result<Option<f64>, Error> {
+    json_field(value, name)
+        .map(|v| v.as_f64().ok_or_else(|| invalid_field(name)))
+        .transpose()
+}
+
+fn json_u64(value: &JsonValue, name: &str) -> Result<Option<u64>, Error> {
+    json_field(value, name)
+        .map(|v| v.as_u64().ok_or_else(|| invalid_field(name)))
+        .transpose()
+}
+
+fn json_bool(value: &JsonValue, name: &str) -> Result<Option<bool>, Error> {
+    json_field(value, name)
+        .map(|v| v.as_bool().ok_or_else(|| invalid_field(name)))
+        .transpose()
+}
+
 macro_rules! line {
     ($f:expr, $name:expr, $val:expr) => {
         write!($f, "{0: <27}{1}\n", $name, $val)
@@ -1,14 +1,56 @@
+use crate::agent_limits::{AgentLimits, AgentLimitsConfig};
 use crate::cmd::*;
-use crate::value_parser::StdinRedirectParser;
+use crate::protocol_entities::{
+    Agent, AgentIdx, AgentStatus, Controller, DeliveryStatus, Message, MessageKind, StalledStdin,
+    StreamEncoding,
+};
+use crate::protocol_handlers::{AgentStdout, ControllerStdout, MessageBuf};
+use crate::report::{
+    read_json_reports, AggregateReport, ChildInfo, Report, ReportColumn, ReportKind, TableReport,
+    TerminateReason, DEFAULT_REPORT_COLUMNS, SIGNIFICANT_CHANGE,
+};
+use crate::value_parser::{MemValueParser, StdinRedirectParser};
+
+use spawner::dataflow::{Graph, SourceReader};
+use spawner::pipe;
+use spawner::process::{ExitStatus, GroupProcess};
+use spawner::TerminationReason::{TerminatedByRunner, WallClockTimeLimitExceeded};
+use spawner::{Session, StdioMapping};
 
 use spawner_opts::{CmdLineOptions, OptionValueParser};
 
+use std::cell::RefCell;
+use std::io::{Read, Write};
+use std::sync::mpsc::channel;
+use std::thread;
 use std::time::Duration;
 
 fn fsec2dur(s: f64) -> Duration {
     Duration::from_micros((s * 1e6) as u64)
 }
 
+/// A command running `argv` with every other option left at its default.
+fn test_command(argv: &[&str]) -> Command {
+    Command {
+        argv: argv.iter().map(|a| a.to_string()).collect(),
+        ..Default::default()
+    }
+}
+
+/// The report of `test_command(argv)` that has not been run.
+fn test_report(argv: &[&str]) -> Report {
+    Report::from(&test_command(argv))
+}
+
+/// A mapping of fresh pipes nobody reads or writes, for the entities that are never run.
+fn test_mapping(sess: &mut Session) -> StdioMapping {
+    StdioMapping {
+        stdin: sess.graph_mut().add_destination(pipe::create().unwrap().1),
+        stdout: sess.graph_mut().add_source(pipe::create().unwrap().0),
+        stderr: sess.graph_mut().add_source(pipe::create().unwrap().0),
+    }
+}
+
 macro_rules! check_opt {
     ($argv:expr, $field:ident, $value:expr) => {{
         let mut cmd = Command::default();
@@ -62,9 +104,22 @@ fn parse_basic_opts() {
     check_opt!(&["--controller"], controller, true);
     check_opt!(&["-j"], use_json, true);
     check_opt!(&["--json"], use_json, true);
+    check_opt!(&["--csv"], use_csv, true);
+    check_opt!(&["--tsv"], use_tsv, true);
     check_opt!(&["--wait-for-children"], wait_for_children, true);
 }
 
+#[test]
+fn parse_stops_at_first_non_option() {
+    let mut cmd = Command::default();
+    assert_eq!(cmd.parse_argv(["-d", "2", "prog", "-d", "3"]), Ok(2));
+    assert_eq!(cmd.wall_clock_time_limit, Some(fsec2dur(2.0)));
+
+    let mut cmd = Command::default();
+    assert_eq!(cmd.parse_argv(["-d=2", "-tl", "1", "prog"]), Ok(3));
+    assert_eq!(cmd.parse_argv(["prog", "-d", "3"]), Ok(0));
+}
+
 #[test]
 fn parse_env_type() {
     let mut cmd = Command::default();
@@ -284,3 +339,972 @@ fn parse_pipe_redirect() {
     check_redirect!(("*fe:", "*:", "*:std"), "*-f-e:std");
     check_redirect!(("*fe:", "*:", "*std"), "*f-e:std");
 }
+
+#[test]
+fn controller_message_seq() {
+    let mut msg_buf = MessageBuf::new();
+    let mut data: &[u8] = b"1#first\n2W#\n1#third\n";
+    let mut seqs = Vec::new();
+    while !data.is_empty() {
+        data = msg_buf.write(data).unwrap();
+        assert!(msg_buf.is_msg_ready());
+        seqs.push(msg_buf.as_msg().unwrap().seq());
+        msg_buf.clear();
+    }
+    assert_eq!(seqs, [0, 1, 2]);
+}
+
+#[test]
+fn controller_message_crc() {
+    let msg = Message::parse(b"1#123456789\n").unwrap();
+    assert_eq!(msg.payload(), b"123456789");
+    assert_eq!(msg.crc32(), 0xCBF4_3926);
+    assert_eq!(Message::parse(b"1W#\n").unwrap().crc32(), 0);
+}
+
+#[test]
+fn controller_message_error_tells_seq() {
+    let mut sess = Session::new();
+    let mapping = test_mapping(&mut sess);
+    let controller = Controller::new(channel().0, mapping);
+    let agent = Agent::new(AgentIdx(0), channel().0, mapping);
+    let mut reader = ControllerStdout::new(controller, vec![agent]);
+
+    let (mut stdout_r, mut stdout_w) = pipe::create().unwrap();
+    stdout_w.write_all(b"1W#\n2#123456789\n").unwrap();
+    drop(stdout_w);
+    assert_eq!(
+        reader.read(&mut stdout_r, &mut []).unwrap_err().to_string(),
+        "Agent index '2' is out of range (controller message 1, payload crc32 cbf43926)"
+    );
+}
+
+#[test]
+fn controller_message_framing_errors() {
+    let err = |data: &[u8]| Message::parse(data).err().unwrap().to_string();
+    assert_eq!(err(b"A\n"), "Missing '#' in controller message at byte 1");
+    assert_eq!(
+        err(b"12W data\n"),
+        "Missing '#' in controller message at byte 3"
+    );
+    assert_eq!(err(b"\n"), "Missing '#' in controller message at byte 0");
+    assert_eq!(
+        err(b"1#data"),
+        "Missing trailing '\\n' in controller message at byte 6"
+    );
+    assert_eq!(
+        err(b"2-3S#"),
+        "Missing trailing '\\n' in controller message at byte 5"
+    );
+}
+
+#[test]
+fn parse_crlf_message() {
+    let msg = Message::parse(b"3#data\r\n").unwrap();
+    assert_eq!(msg.payload(), b"data");
+    assert!(matches!(msg.kind(), MessageKind::Data(b"data")));
+    assert_eq!(msg.as_raw(), b"3#data\r\n");
+
+    assert_eq!(Message::parse(b"3#\r\n").unwrap().payload(), b"");
+    assert_eq!(Message::parse(b"3#a\rb\n").unwrap().payload(), b"a\rb");
+    assert!(matches!(
+        Message::parse(b"0E#\r\n").unwrap().kind(),
+        MessageKind::Done
+    ));
+    assert!(matches!(
+        Message::parse(b"V=1#\r\n").unwrap().kind(),
+        MessageKind::Version(1)
+    ));
+}
+
+#[test]
+fn parse_done_message() {
+    let msg = Message::parse(b"0E#\n").unwrap();
+    assert!(matches!(msg.kind(), MessageKind::Done));
+    assert!(msg.agent_idx().is_none());
+
+    let msg = Message::parse(b"2E#\n").unwrap();
+    assert!(matches!(msg.kind(), MessageKind::Done));
+    assert!(msg.agent_idx() == Some(AgentIdx(1)));
+
+    assert_eq!(
+        Message::parse(b"1E#data\n").err().unwrap().to_string(),
+        "Controller command 'E' doesn't accept data in '1E'"
+    );
+}
+
+#[test]
+fn parse_encoding_message() {
+    let msg = Message::parse(b"3N=utf16#\n").unwrap();
+    assert!(matches!(
+        msg.kind(),
+        MessageKind::Encoding(StreamEncoding::Utf16Le)
+    ));
+    assert!(msg.agent_idx() == Some(AgentIdx(2)));
+    assert!(matches!(
+        Message::parse(b"1N=UTF16BE#\n").unwrap().kind(),
+        MessageKind::Encoding(StreamEncoding::Utf16Be)
+    ));
+
+    let err = |data: &[u8]| Message::parse(data).err().unwrap().to_string();
+    assert_eq!(err(b"1N=koi8#\n"), "Unknown encoding 'koi8'");
+    assert_eq!(
+        err(b"1N=utf8#data\n"),
+        "Controller command 'N' doesn't accept data in '1N=utf8'"
+    );
+}
+
+#[test]
+fn transcode_agent_stdio() {
+    assert_eq!(&*StreamEncoding::Utf8.encode(b"hi\n"), b"hi\n");
+    assert_eq!(
+        &*StreamEncoding::Utf16Le.encode("hé\n".as_bytes()),
+        &[b'h', 0, 0xE9, 0, b'\n', 0]
+    );
+    assert_eq!(
+        &*StreamEncoding::Utf16Be.encode(b"h\xFF"),
+        &[0, b'h', 0xFF, 0xFD]
+    );
+
+    let (utf8, consumed) = StreamEncoding::Utf16Le.decode(&[b'h', 0, 0xE9, 0, b'\n']);
+    assert_eq!((&*utf8, consumed), ("hé".as_bytes(), 4));
+    // A surrogate pair split between reads is decoded once it is complete.
+    let (utf8, consumed) = StreamEncoding::Utf16Be.decode(&[0, b'a', 0xD8, 0x3D]);
+    assert_eq!((&*utf8, consumed), (&b"a"[..], 2));
+    let (utf8, consumed) = StreamEncoding::Utf16Be.decode(&[0xD8, 0x3D, 0xDE, 0x00]);
+    assert_eq!((&*utf8, consumed), ("\u{1F600}".as_bytes(), 4));
+
+    let mut graph = Graph::new();
+    let (agent_stdout_r, mut agent_stdout_w) = pipe::create().unwrap();
+    let (mut controller_stdin_r, controller_stdin_w) = pipe::create().unwrap();
+    let stdout = graph.add_source(agent_stdout_r);
+    let stdin = graph.add_destination(controller_stdin_w);
+    graph.connect(stdout, stdin);
+    let mapping = StdioMapping {
+        stdin,
+        stdout,
+        stderr: stdout,
+    };
+    let agent = Agent::new(AgentIdx(0), channel().0, mapping);
+    agent.set_encoding(StreamEncoding::Utf16Le);
+    graph
+        .source_mut(stdout)
+        .unwrap()
+        .set_reader(AgentStdout::new(agent));
+    let transmitter = graph.transmit_data();
+
+    agent_stdout_w.write_all(&[b'h', 0, 0xE9]).unwrap();
+    agent_stdout_w.write_all(&[0, b'\n', 0]).unwrap();
+    drop(agent_stdout_w);
+    let mut received = vec![0u8; "1#hé\n1T#\n".len()];
+    controller_stdin_r.read_exact(&mut received).unwrap();
+    assert_eq!(received, "1#hé\n1T#\n".as_bytes());
+    transmitter.wait();
+}
+
+#[test]
+fn agent_done_with_crlf() {
+    let mut graph = Graph::new();
+    let (agent_stdout_r, mut agent_stdout_w) = pipe::create().unwrap();
+    let (mut controller_stdin_r, controller_stdin_w) = pipe::create().unwrap();
+    let stdout = graph.add_source(agent_stdout_r);
+    let stdin = graph.add_destination(controller_stdin_w);
+    graph.connect(stdout, stdin);
+    let mapping = StdioMapping {
+        stdin,
+        stdout,
+        stderr: stdout,
+    };
+    let agent = Agent::new(AgentIdx(1), channel().0, mapping);
+    graph
+        .source_mut(stdout)
+        .unwrap()
+        .set_reader(AgentStdout::new(agent));
+    let transmitter = graph.transmit_data();
+
+    agent_stdout_w.write_all(b"E#\r\nE#\n").unwrap();
+    drop(agent_stdout_w);
+    let mut received = vec![0u8; "2E#\n2E#\n2T#\n".len()];
+    controller_stdin_r.read_exact(&mut received).unwrap();
+    assert_eq!(received, b"2E#\n2E#\n2T#\n");
+    transmitter.wait();
+}
+
+#[test]
+fn forward_crlf_data() {
+    let mut graph = Graph::new();
+    let (controller_stdout_r, mut controller_stdout_w) = pipe::create().unwrap();
+    let (mut agent_stdin_r, agent_stdin_w) = pipe::create().unwrap();
+    let stdout = graph.add_source(controller_stdout_r);
+    let stdin = graph.add_destination(agent_stdin_w);
+    graph.connect(stdout, stdin);
+    let mapping = StdioMapping {
+        stdin,
+        stdout,
+        stderr: stdout,
+    };
+    let controller = Controller::new(channel().0, mapping);
+    let agent = Agent::new(AgentIdx(0), channel().0, mapping);
+    graph
+        .source_mut(stdout)
+        .unwrap()
+        .set_reader(ControllerStdout::new(controller, vec![agent]));
+    let transmitter = graph.transmit_data();
+
+    controller_stdout_w
+        .write_all(b"1#e2e4\r\n1#\r\n1#e7e5\n")
+        .unwrap();
+    drop(controller_stdout_w);
+    let mut received = vec![0u8; "e2e4\n\ne7e5\n".len()];
+    agent_stdin_r.read_exact(&mut received).unwrap();
+    assert_eq!(received, b"e2e4\n\ne7e5\n");
+    transmitter.wait();
+}
+
+#[test]
+fn parse_version_handshake() {
+    let msg = Message::parse(b"V=2#\n").unwrap();
+    assert!(matches!(msg.kind(), MessageKind::Version(2)));
+    assert!(msg.agent_idx().is_none());
+
+    assert!(matches!(
+        Message::parse(b"V=1#\n").unwrap().kind(),
+        MessageKind::Version(1)
+    ));
+    assert_eq!(
+        Message::parse(b"V=2#data\n").err().unwrap().to_string(),
+        "Controller command 'V' doesn't accept data in 'V=2'"
+    );
+    assert_eq!(
+        Message::parse(b"V=x#\n").err().unwrap().to_string(),
+        "Invalid protocol version 'x'"
+    );
+}
+
+#[test]
+fn reject_unsupported_protocol_version() {
+    assert_eq!(
+        Message::parse(b"V=3#\n").err().unwrap().to_string(),
+        "Unsupported protocol version '3', supported versions are [1, 2]"
+    );
+}
+
+#[test]
+fn agent_idx_bounds() {
+    assert_eq!(AgentIdx::new(0, 3), Some(AgentIdx(0)));
+    assert_eq!(AgentIdx::new(2, 3), Some(AgentIdx(2)));
+    assert_eq!(AgentIdx::new(3, 3), None);
+    assert_eq!(AgentIdx::new(0, 0), None);
+    assert_eq!(AgentIdx::new(2, 3).map(AgentIdx::index), Some(2));
+}
+
+#[test]
+fn parse_agent_range() {
+    let msg = Message::parse(b"2-4S#\n").unwrap();
+    assert!(matches!(msg.kind(), MessageKind::Terminate));
+    assert!(msg.agents().eq([AgentIdx(1), AgentIdx(2), AgentIdx(3)]));
+    assert!(msg.agent_idx() == Some(AgentIdx(1)));
+
+    let msg = Message::parse(b"3S#\n").unwrap();
+    assert!(msg.agents().eq([AgentIdx(2)]));
+
+    let msg = Message::parse(b"1-2#data\n").unwrap();
+    assert!(matches!(msg.kind(), MessageKind::Data(b"data")));
+    assert_eq!(msg.agents().count(), 2);
+
+    assert_eq!(Message::parse(b"0S#\n").unwrap().agents().count(), 0);
+    assert_eq!(
+        Message::parse(b"4-2S#\n").err().unwrap().to_string(),
+        "Invalid agent range '4-2' in '4-2S'"
+    );
+    assert_eq!(
+        Message::parse(b"0-2S#\n").err().unwrap().to_string(),
+        "Invalid agent range '0-2' in '0-2S'"
+    );
+}
+
+#[test]
+fn parse_status_query() {
+    let msg = Message::parse(b"3?#\n").unwrap();
+    assert!(matches!(msg.kind(), MessageKind::Query));
+    assert!(msg.agent_idx() == Some(AgentIdx(2)));
+    assert_eq!(
+        Message::parse(b"3?#data\n").err().unwrap().to_string(),
+        "Controller command '?' doesn't accept data in '3?'"
+    );
+}
+
+#[test]
+fn status_reply_round_trip() {
+    let status = AgentStatus {
+        wall_clock_time: Some(Duration::from_millis(1230)),
+        peak_memory: Some(45 << 20),
+    };
+    let reply = status.encode(AgentIdx(2));
+    assert_eq!(reply, "3?=1.230s,45M#\n");
+    let (idx, parsed) = AgentStatus::parse(reply.as_bytes()).unwrap();
+    assert!(idx == AgentIdx(2));
+    assert_eq!(parsed, status);
+
+    let reply = AgentStatus::default().encode(AgentIdx(0));
+    assert_eq!(reply, "1?=-,-#\n");
+    assert_eq!(
+        AgentStatus::parse(reply.as_bytes()).unwrap().1,
+        AgentStatus::default()
+    );
+
+    assert_eq!(
+        AgentStatus::parse(b"3?=1.2,45M#\n")
+            .err()
+            .unwrap()
+            .to_string(),
+        "Invalid status reply '3?=1.2,45M#'"
+    );
+}
+
+#[test]
+fn report_children() {
+    let cmd = test_command(&["app"]);
+    let mut report = Report::from(&cmd);
+    assert!(report.children.is_empty());
+    assert!(!report.to_json().has_key("Children"));
+
+    report.children = vec![
+        ChildInfo {
+            pid: 10,
+            exit_code: Some(0),
+            peak_memory: Some(1024),
+        },
+        ChildInfo {
+            pid: 11,
+            exit_code: None,
+            peak_memory: None,
+        },
+    ];
+    let json = report.to_json();
+    assert_eq!(json["Children"].len(), 2);
+    assert_eq!(json["Children"][0]["Pid"], 10);
+    assert_eq!(json["Children"][0]["ExitCode"], 0);
+    assert_eq!(json["Children"][0]["PeakMemory"], 1024);
+    assert_eq!(json["Children"][1]["Pid"], 11);
+    assert!(json["Children"][1]["ExitCode"].is_null());
+    assert!(json["Children"][1]["PeakMemory"].is_null());
+
+    let runner_report = spawner::Report {
+        wall_clock_time: Duration::from_millis(10),
+        memory: None,
+        io: None,
+        timers: None,
+        pid_counters: None,
+        network: None,
+        children: vec![GroupProcess {
+            pid: 12,
+            peak_memory: Some(2048),
+        }],
+        exit_status: ExitStatus::Finished(0),
+        termination_reason: None,
+        stdout_bytes: None,
+        stderr_bytes: None,
+    };
+    assert_eq!(
+        Report::new(&cmd, Ok(runner_report)).children,
+        [ChildInfo {
+            pid: 12,
+            exit_code: None,
+            peak_memory: Some(2048),
+        }]
+    );
+}
+
+#[test]
+fn message_debug() {
+    let msg = Message::parse(b"1#hello\n").unwrap();
+    assert_eq!(
+        format!("{:?}", msg),
+        "Message { agent_idx: Some(0), kind: Data(5 bytes) }"
+    );
+    let msg = Message::parse(b"3S#\n").unwrap();
+    assert_eq!(
+        format!("{:?}", msg),
+        "Message { agent_idx: Some(2), kind: Terminate }"
+    );
+}
+
+#[test]
+fn controller_write_message_is_atomic() {
+    let mut sess = Session::new();
+    let (mut stdin_r, stdin_w) = pipe::create().unwrap();
+    let stdin = sess.graph_mut().add_destination(stdin_w);
+    let mapping = StdioMapping {
+        stdin,
+        ..test_mapping(&mut sess)
+    };
+    let controller = Controller::new(channel().0, mapping)
+        .with_stdin_writer(sess.destination_writer(stdin).unwrap());
+
+    // Messages larger than a pipe buffer, so each write takes several syscalls.
+    let len = 1 << 17;
+    let writers = [b'a', b'b']
+        .iter()
+        .map(|&c| {
+            let controller = controller.clone();
+            thread::spawn(move || {
+                let mut msg = vec![c; len];
+                msg.push(b'\n');
+                controller.write_message(&msg).unwrap();
+            })
+        })
+        .collect::<Vec<_>>();
+    drop(controller);
+
+    let reader = thread::spawn(move || {
+        let mut data = Vec::new();
+        let mut buf = [0u8; 4096];
+        while data.len() < 2 * (len + 1) {
+            let n = stdin_r.read(&mut buf).unwrap();
+            data.extend_from_slice(&buf[..n]);
+        }
+        data
+    });
+    for w in writers {
+        w.join().unwrap();
+    }
+    let data = reader.join().unwrap();
+    let lines = data.split(|&b| b == b'\n').collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3);
+    for line in &lines[..2] {
+        assert_eq!(line.len(), len);
+        assert!(line.iter().all(|&b| b == line[0]));
+    }
+    assert_ne!(lines[0][0], lines[1][0]);
+}
+
+#[test]
+fn status_queries_dont_block_controller_stdout() {
+    let mut sess = Session::new();
+    let (controller_stdin_r, controller_stdin_w) = pipe::create().unwrap();
+    let stdin = sess.graph_mut().add_destination(controller_stdin_w);
+    let mapping = StdioMapping {
+        stdin,
+        ..test_mapping(&mut sess)
+    };
+    let controller = Controller::new(channel().0, mapping)
+        .with_stdin_writer(sess.destination_writer(stdin).unwrap());
+    let agent = Agent::new(AgentIdx(0), channel().0, mapping);
+    let mut reader = ControllerStdout::new(controller, vec![agent]);
+
+    // The controller never reads its stdin, so the replies overflow the pipe.
+    let (mut stdout_r, mut stdout_w) = pipe::create().unwrap();
+    let queries = thread::spawn(move || {
+        for _ in 0..20000 {
+            stdout_w.write_all(b"1?#\n").unwrap();
+        }
+    });
+    let (done_sender, done) = channel();
+    thread::spawn(move || {
+        let _ = reader.read(&mut stdout_r, &mut []);
+        done_sender.send(()).unwrap();
+    });
+
+    done.recv_timeout(Duration::from_secs(5)).unwrap();
+    queries.join().unwrap();
+    drop(controller_stdin_r);
+}
+
+#[test]
+fn watch_stalled_agent_stdin() {
+    let mut sess = Session::new();
+    let (mut stdin_r, stdin_w) = pipe::create().unwrap();
+    let stdin = sess.graph_mut().add_destination(stdin_w);
+    let mapping = StdioMapping {
+        stdin,
+        ..test_mapping(&mut sess)
+    };
+    let agent = Agent::new(AgentIdx(2), channel().0, mapping);
+    let (sender, stalls) = channel();
+    assert!(agent.watch_stdin(sess.graph(), Duration::from_millis(20), sender));
+
+    // Nobody reads the pipe, so the message can't fit into its buffer.
+    let writer = sess.destination_writer(stdin).unwrap();
+    let msg = vec![b'x'; 1 << 20];
+    let write = thread::spawn(move || writer.write_message(&msg).unwrap());
+
+    let stall = stalls.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(stall.agent, AgentIdx(2));
+    assert!(stall.stalled_for >= Duration::from_millis(20));
+    assert_eq!(
+        StalledStdin {
+            agent: AgentIdx(2),
+            stalled_for: Duration::from_millis(1500),
+        }
+        .to_string(),
+        "Agent#3 didn't read its stdin for 1.5s"
+    );
+
+    let mut buf = vec![0u8; 1 << 16];
+    let mut received = 0;
+    while received < 1 << 20 {
+        received += stdin_r.read(&mut buf).unwrap();
+    }
+    write.join().unwrap();
+}
+
+#[test]
+fn parse_infinite_time_limit() {
+    check_opt!(["-tl=2", "-tl=inf"], time_limit, None);
+    check_opt!(["-tl=2", "-tl=infinity"], time_limit, None);
+    check_opt!(["-d=2", "-d=-1"], wall_clock_time_limit, None);
+    check_opt!(["-y=2.5s"], idle_time_limit, Some(fsec2dur(2.5)));
+}
+
+#[test]
+fn resolve_terminate_reason() {
+    use TerminateReason::*;
+    assert_eq!(TerminateReason::resolve(&[]), ExitProcess);
+    assert_eq!(
+        TerminateReason::resolve(&[WriteLimitExceeded]),
+        WriteLimitExceeded
+    );
+    assert_eq!(
+        TerminateReason::resolve(&[TimeLimitExceeded, MemoryLimitExceeded, WriteLimitExceeded]),
+        MemoryLimitExceeded
+    );
+    assert_eq!(
+        TerminateReason::resolve(&[WriteLimitExceeded, TimeLimitExceeded]),
+        TimeLimitExceeded
+    );
+    assert_eq!(
+        TerminateReason::resolve(&[IdleTimeLimitExceeded, TerminatedByController]),
+        IdleTimeLimitExceeded
+    );
+    assert_eq!(
+        TerminateReason::resolve(&[ExitProcess, AbnormalExitProcess]),
+        AbnormalExitProcess
+    );
+}
+
+#[test]
+fn parse_memory_with_digit_separators() {
+    let parse = |v| {
+        let mut mem = None;
+        MemValueParser::parse(&mut mem, v).map(|_| mem)
+    };
+    assert_eq!(parse("1_000_000B"), Ok(Some(1e6 / f64::powf(2.0, 20.0))));
+    assert_eq!(parse("1_024k"), Ok(Some(1.0)));
+    assert_eq!(parse("_100"), Err("Invalid value '_100'".to_string()));
+    assert_eq!(parse("1__0"), Err("Invalid value '1__0'".to_string()));
+    assert_eq!(parse("1_M"), Err("Invalid value '1_M'".to_string()));
+}
+
+#[test]
+fn stdio_mapping_ids() {
+    let mut sess = Session::new();
+    let first = test_mapping(&mut sess);
+    let second = test_mapping(&mut sess);
+    let clone = first;
+
+    assert_ne!(first, second);
+    assert_ne!(first.to_string(), second.to_string());
+    assert_eq!(first, clone);
+    assert_eq!(first.to_string(), clone.to_string());
+}
+
+#[test]
+fn report_exit_code() {
+    let mut report = test_report(&["app"]);
+    assert_eq!(report.to_exit_code(), 0);
+
+    report.exit_code = 3;
+    assert_eq!(report.to_exit_code(), 3);
+
+    report.terminate_reason = TerminateReason::TimeLimitExceeded;
+    assert_eq!(report.to_exit_code(), 10);
+    assert_eq!(
+        report.to_exit_code_with(&[(TerminateReason::TimeLimitExceeded, 124)]),
+        124
+    );
+    assert_eq!(
+        report.to_exit_code_with(&[(TerminateReason::MemoryLimitExceeded, 137)]),
+        3
+    );
+}
+
+#[test]
+fn report_terminated_by_controller() {
+    let cmd = test_command(&["app"]);
+    let runner_report = |tr| spawner::Report {
+        wall_clock_time: Duration::from_millis(10),
+        memory: None,
+        io: None,
+        timers: None,
+        pid_counters: None,
+        network: None,
+        children: Vec::new(),
+        exit_status: ExitStatus::Finished(1),
+        termination_reason: tr,
+        stdout_bytes: None,
+        stderr_bytes: None,
+    };
+
+    let report = Report::new(&cmd, Ok(runner_report(Some(TerminatedByRunner))));
+    assert_eq!(
+        report.terminate_reason,
+        TerminateReason::TerminatedByController
+    );
+    assert_eq!(report.to_exit_code(), 17);
+    assert_eq!(
+        report.to_json()["TerminateReason"],
+        "TerminatedByController"
+    );
+
+    let report = Report::new(&cmd, Ok(runner_report(Some(WallClockTimeLimitExceeded))));
+    assert_eq!(report.terminate_reason, TerminateReason::TimeLimitExceeded);
+
+    let report = Report::new(&cmd, Ok(runner_report(None)));
+    assert_eq!(report.terminate_reason, TerminateReason::ExitProcess);
+
+    // A program killed by the controller exits abnormally, the more specific reason wins.
+    let crashed = |tr| spawner::Report {
+        exit_status: ExitStatus::Crashed("Process terminated by the 'SIGKILL' signal".into()),
+        ..runner_report(tr)
+    };
+    let report = Report::new(&cmd, Ok(crashed(Some(TerminatedByRunner))));
+    assert_eq!(
+        report.terminate_reason,
+        TerminateReason::TerminatedByController
+    );
+    let report = Report::new(&cmd, Ok(crashed(None)));
+    assert_eq!(
+        report.terminate_reason,
+        TerminateReason::AbnormalExitProcess
+    );
+}
+
+#[cfg(feature = "json-events")]
+#[test]
+fn agent_json_event() {
+    use crate::protocol_entities::AgentEvent;
+
+    let (idx, event) = AgentEvent::parse(b"2#{\"type\":\"move\",\"data\":[1,2]}\n").unwrap();
+    assert!(idx == AgentIdx(1));
+    assert_eq!(event.event_type(), Some("move"));
+    assert_eq!(event.data(), Some(&json::array![1, 2]));
+}
+
+#[cfg(feature = "json-events")]
+#[test]
+fn agent_raw_event() {
+    use crate::protocol_entities::AgentEvent;
+
+    let (idx, event) = AgentEvent::parse(b"1#move 1 2\n").unwrap();
+    assert!(idx == AgentIdx(0));
+    assert_eq!(event, AgentEvent::Raw(b"move 1 2"));
+    assert_eq!(event.event_type(), None);
+    assert_eq!(event.data(), None);
+    assert!(AgentEvent::parse(b"1T#\n").is_err());
+}
+
+#[test]
+fn agent_delivery_ack() {
+    let mut sess = Session::new();
+    let mapping = test_mapping(&mut sess);
+    let (sender, _receiver) = channel();
+    let agent = Agent::new(AgentIdx(0), sender, mapping);
+    let delivery = agent.track_delivery();
+    assert_eq!(delivery.wait_timeout(Duration::from_millis(10)), None);
+
+    let (mut stdout, mut w) = pipe::create().unwrap();
+    let mut reader = AgentStdout::new(agent.clone());
+    let handle = thread::spawn(move || reader.read(&mut stdout, &mut []));
+    w.write_all(b"move\n").unwrap();
+    assert_eq!(delivery.wait(), DeliveryStatus::Acknowledged);
+
+    let pending = agent.track_delivery();
+    drop(w);
+    handle.join().unwrap().unwrap();
+    assert_eq!(pending.wait(), DeliveryStatus::AgentExited);
+    assert_eq!(agent.track_delivery().wait(), DeliveryStatus::AgentExited);
+}
+
+#[test]
+fn tracked_data_reply() {
+    let msg = Message::parse(b"2A#e2e4\r\n").unwrap();
+    assert!(matches!(msg.kind(), MessageKind::TrackedData(b"e2e4")));
+    assert!(msg.agents().eq([AgentIdx(1)]));
+    assert_eq!(
+        DeliveryStatus::AgentExited.encode(AgentIdx(1)),
+        "2A=exited#\n"
+    );
+
+    let mut sess = Session::new();
+    let (mut controller_stdin_r, controller_stdin_w) = pipe::create().unwrap();
+    let stdin = sess.graph_mut().add_destination(controller_stdin_w);
+    let mapping = StdioMapping {
+        stdin,
+        ..test_mapping(&mut sess)
+    };
+    let controller = Controller::new(channel().0, mapping)
+        .with_stdin_writer(sess.destination_writer(stdin).unwrap());
+    let agent = Agent::new(AgentIdx(0), channel().0, test_mapping(&mut sess));
+    let mut reader = ControllerStdout::new(controller, vec![agent.clone()]);
+
+    let (mut stdout_r, mut stdout_w) = pipe::create().unwrap();
+    stdout_w.write_all(b"1A#e2e4\n").unwrap();
+    drop(stdout_w);
+    reader.read(&mut stdout_r, &mut []).unwrap();
+    // The agent reads the data and replies.
+    agent.acknowledge();
+    let mut reply = vec![0u8; "1A=ok#\n".len()];
+    controller_stdin_r.read_exact(&mut reply).unwrap();
+    assert_eq!(reply, b"1A=ok#\n");
+
+    let err = |msg: &[u8]| {
+        let mut reader =
+            ControllerStdout::new(Controller::new(channel().0, mapping), vec![agent.clone()]);
+        let (mut stdout_r, mut stdout_w) = pipe::create().unwrap();
+        stdout_w.write_all(msg).unwrap();
+        drop(stdout_w);
+        reader.read(&mut stdout_r, &mut []).unwrap_err().to_string()
+    };
+    assert_eq!(
+        err(b"0A#e2e4\n"),
+        "Controller command 'A' must be sent to an agent (controller message 0, payload crc32 \
+         336d8284)"
+    );
+}
+
+#[test]
+fn aggregate_report_streaming() {
+    let report = |app| test_report(&[app]);
+    let (sender, receiver) = channel();
+    let emitted = RefCell::new(Vec::new());
+    let mut aggregate = AggregateReport::new(3, |idx, report: &Report| {
+        emitted.borrow_mut().push((idx, report.application.clone()))
+    });
+
+    sender.send((2, report("c.exe"))).unwrap();
+    aggregate.add_all(receiver.try_iter()).unwrap();
+    assert_eq!(*emitted.borrow(), [(2, "c.exe".to_string())]);
+    assert!(!aggregate.is_complete());
+
+    sender.send((0, report("a.exe"))).unwrap();
+    aggregate.add_all(receiver.try_iter()).unwrap();
+    assert_eq!(emitted.borrow().len(), 2);
+    assert_eq!(emitted.borrow()[1], (0, "a.exe".to_string()));
+    assert!(aggregate
+        .reports()
+        .map(|r| &r.application)
+        .eq(["a.exe", "c.exe"]));
+
+    sender.send((1, report("b.exe"))).unwrap();
+    drop(sender);
+    aggregate.add_all(receiver).unwrap();
+    assert_eq!(emitted.borrow().len(), 3);
+    assert_eq!(emitted.borrow()[2], (1, "b.exe".to_string()));
+    assert!(aggregate.is_complete());
+
+    assert!(aggregate.add(1, report("b.exe")).is_err());
+    assert!(aggregate.add(3, report("d.exe")).is_err());
+    assert_eq!(emitted.borrow().len(), 3);
+    let apps = aggregate
+        .into_reports()
+        .into_iter()
+        .map(|r| r.application)
+        .collect::<Vec<_>>();
+    assert_eq!(apps, ["a.exe", "b.exe", "c.exe"]);
+}
+
+#[test]
+fn read_ndjson_reports() {
+    let report = |app| {
+        Report::from(&Command {
+            memory_limit: Some(64.0),
+            ..test_command(&[app, "-x"])
+        })
+    };
+    let mut crashed = report("b.exe");
+    crashed.terminate_reason = TerminateReason::AbnormalExitProcess;
+    crashed.exit_code = 3;
+    crashed.children = vec![ChildInfo {
+        pid: 42,
+        exit_code: None,
+        peak_memory: Some(1024),
+    }];
+    let stream = format!(
+        "{}\n{}  \n\n{}\n",
+        report("a.exe").to_json().dump(),
+        crashed.to_json().dump(),
+        report("c.exe").to_json().dump()
+    );
+
+    let reports = read_json_reports(stream.as_bytes()).unwrap();
+    assert!(reports
+        .iter()
+        .map(|r| &r.application)
+        .eq(["a.exe", "b.exe", "c.exe"]));
+    assert_eq!(reports[0].arguments, ["-x"]);
+    assert_eq!(reports[0].limit.memory, Some(64 * 1024 * 1024));
+    assert!(reports[0].spawner_error.is_empty());
+    assert_eq!(
+        reports[1].terminate_reason,
+        TerminateReason::AbnormalExitProcess
+    );
+    assert_eq!(reports[1].exit_code, 3);
+    assert_eq!(reports[1].children, crashed.children);
+    assert_eq!(reports[1].to_json(), crashed.to_json());
+
+    let stream = format!(
+        "{}\n{{\"Application\": 1}}\n",
+        report("a.exe").to_json().dump()
+    );
+    assert_eq!(
+        read_json_reports(stream.as_bytes())
+            .unwrap_err()
+            .to_string(),
+        "Invalid report on line 2: Invalid field 'Application'"
+    );
+}
+
+#[test]
+fn report_diff() {
+    let mut baseline = test_report(&["a.exe"]);
+    baseline.result.time = 2.0;
+    baseline.result.wall_clock_time = 4.0;
+    baseline.result.memory = 1000;
+    let mut new = test_report(&["a.exe"]);
+    new.result.time = 3.0;
+    new.result.wall_clock_time = 4.2;
+    new.result.memory = 900;
+
+    let diff = baseline.diff(&new);
+    assert_eq!(diff.time_delta(), 1.0);
+    assert!((diff.wall_clock_time_delta() - 0.2).abs() < 1e-9);
+    assert_eq!(diff.memory_delta(), -100);
+    assert!(diff.is_time_significant());
+    assert!(!diff.is_wall_clock_time_significant());
+    assert!(!diff.is_memory_significant());
+    assert!(diff.is_significant());
+    let text = diff.to_string();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], "* Time: 2 -> 3 (+1, +50.0%)");
+    assert!(lines[1].starts_with("  WallClockTime: 4 -> 4.2 "));
+    assert_eq!(lines[2], "  Memory: 1000 -> 900 (-100, -10.0%)");
+
+    new.result = Default::default();
+    new.result.time = 2.0 * (1.0 + SIGNIFICANT_CHANGE / 2.0);
+    new.result.wall_clock_time = 4.0;
+    new.result.memory = 1000;
+    assert!(!baseline.diff(&new).is_significant());
+
+    new.exit_code = 1;
+    new.terminate_reason = TerminateReason::TimeLimitExceeded;
+    let diff = baseline.diff(&new);
+    assert!(diff.is_significant());
+    assert_eq!(diff.exit_code, (0, 1));
+    let text = diff.to_string();
+    assert!(text.contains("\n* ExitCode: 0 -> 1\n"));
+    assert!(text.ends_with("\n* TerminateReason: ExitProcess -> TimeLimitExceeded\n"));
+}
+
+#[test]
+fn csv_report() {
+    let first = Report::from(&Command {
+        use_csv: true,
+        ..test_command(&["a.exe", "x,y"])
+    });
+    assert_eq!(first.kind, ReportKind::Table(','));
+
+    let mut second = Report::from(&Command {
+        use_csv: true,
+        ..test_command(&["b.exe"])
+    });
+    second.exit_status = "say \"hi\"".to_string();
+
+    let csv = TableReport {
+        reports: &[&first, &second],
+        columns: &DEFAULT_REPORT_COLUMNS,
+        separator: ',',
+    }
+    .to_string();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0].split(',').count(), DEFAULT_REPORT_COLUMNS.len());
+    assert!(lines[0].starts_with("Application,Arguments,Time,"));
+    assert!(lines[1].starts_with("a.exe,\"x,y\","));
+    assert!(lines[2].starts_with("b.exe,,"));
+    assert!(lines[2].contains(",\"say \"\"hi\"\"\","));
+
+    let tsv = TableReport {
+        reports: &[&first],
+        columns: &[ReportColumn::Application, ReportColumn::ExitCode],
+        separator: '\t',
+    }
+    .to_string();
+    assert_eq!(tsv, "Application\tExitCode\na.exe\t0\n");
+    // A report alone is displayed without the header, so rows can be appended to a table.
+    assert_eq!(first.to_string(), format!("{}\n", lines[1]));
+
+    let err = crate::run([
+        "--separator=@",
+        "-sr=report.csv",
+        "--csv",
+        "a.exe",
+        "--@",
+        "-sr=report.csv",
+        "--tsv",
+        "b.exe",
+    ])
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Reports written to the same file 'report.csv' must have the same format"
+    );
+
+    let mut cmd = Command::default();
+    assert!(cmd.parse_argv(["--csv", "--json", "a.exe"]).is_err());
+}
+
+#[test]
+fn read_agent_limits() {
+    let config = AgentLimitsConfig::read(
+        &br#"{"Name": "solver", "TimeLimit": "1.5s", "MemoryLimit": "64M", "WriteLimit": 2}
+
+{"Name": "checker", "TimeLimit": "500ms"}
+"#[..],
+    )
+    .unwrap();
+    let solver = AgentLimits {
+        time_limit: Some(Duration::from_millis(1500)),
+        memory_limit: Some(64.0),
+        write_limit: Some(2.0),
+    };
+    let checker = AgentLimits {
+        time_limit: Some(Duration::from_millis(500)),
+        ..AgentLimits::default()
+    };
+    assert_eq!(config.by_name("solver"), Some(&solver));
+    assert_eq!(config.by_name("checker"), Some(&checker));
+    assert_eq!(config.by_name("interactor"), None);
+    assert_eq!(config.by_idx(AgentIdx(0)), Some(&solver));
+    assert_eq!(config.by_idx(AgentIdx(1)), Some(&checker));
+    assert_eq!(config.by_idx(AgentIdx(2)), None);
+
+    let err = |config: &str| {
+        AgentLimitsConfig::read(config.as_bytes())
+            .unwrap_err()
+            .to_string()
+    };
+    assert_eq!(
+        err("{\"Name\": \"a\"}\n{\"Name\": \"a\"}"),
+        "Duplicate agent name 'a' on line 2"
+    );
+    assert_eq!(
+        err("{\"Name\": \"a\", \"MemoryLimit\": \"lots\"}"),
+        "Invalid agent limits on line 1: Invalid field 'MemoryLimit': Invalid value 'lots'"
+    );
+    assert_eq!(
+        err("{\"TimeLimit\": \"1s\"}"),
+        "Invalid agent limits on line 1: Missing field 'Name'"
+    );
+}
@@ -3,6 +3,7 @@ use crate::cmd::{
     StdinRedirectList, StdoutRedirectList,
 };
 
+use spawner_opts::value_parser::strip_digit_separators;
 use spawner_opts::OptionValueParser;
 
 use std::time::Duration;
@@ -17,7 +18,7 @@ pub struct FileFlagsParser;
 
 impl OptionValueParser<Option<usize>> for DefaultValueParser {
     fn parse(opt: &mut Option<usize>, v: &str) -> Result<(), String> {
-        if let Ok(v) = v.parse::<usize>() {
+        if let Some(v) = strip_digit_separators(v).and_then(|v| v.parse::<usize>().ok()) {
             *opt = Some(v);
             Ok(())
         } else {
@@ -93,22 +94,25 @@ impl OptionValueParser<Duration> for DefaultValueParser {
     }
 }
 
+/// `infinity`, `inf` and `-1` are parsed as `None`, which disables the corresponding limit.
 impl OptionValueParser<Option<Duration>> for DefaultValueParser {
     fn parse(opt: &mut Option<Duration>, v: &str) -> Result<(), String> {
-        *opt = Some(parse_time_value(v)?);
+        *opt = match v {
+            "infinity" | "inf" | "-1" => None,
+            _ => Some(parse_time_value(v)?),
+        };
         Ok(())
     }
 }
 
 impl OptionValueParser<Option<f64>> for MemValueParser {
     fn parse(opt: &mut Option<f64>, v: &str) -> Result<(), String> {
-        parse_value(v, parse_mem_degree, parse_mem_unit).map_or(
-            Err(format!("Invalid value '{}'", v)),
-            |(val, mult)| {
+        strip_digit_separators(v)
+            .and_then(|num| parse_value(&num, parse_mem_degree, parse_mem_unit))
+            .map_or(Err(format!("Invalid value '{}'", v)), |(val, mult)| {
                 *opt = Some(val * mult.map_or(1.0, |m| m / f64::powf(2.0, 20.0)));
                 Ok(())
-            },
-        )
+            })
     }
 }
 
@@ -0,0 +1,40 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate spawner_opts;
+
+use spawner_opts::*;
+
+const MAX_OCCURRENCES: usize = 16;
+
+struct ListParser;
+
+impl OptionValueParser<Vec<String>> for ListParser {
+    fn parse(opt: &mut Vec<String>, v: &str) -> Result<(), String> {
+        opt.push(v.to_string());
+        Ok(())
+    }
+}
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(delimeters = "=", default_parser = "ListParser")]
+struct Opts {
+    #[opt(names("-i", "--include"))]
+    include: Vec<String>,
+
+    #[opt(name = "-e")]
+    env: Vec<String>,
+
+    #[flag(name = "-v")]
+    verbose: bool,
+}
+
+// Each line of the input is one token of argv.
+fuzz_target!(|data: &[u8]| {
+    let input = String::from_utf8_lossy(data);
+    let mut opts = Opts::default();
+    if opts.parse_bounded(input.lines(), MAX_OCCURRENCES).is_ok() {
+        assert!(opts.include.len() <= MAX_OCCURRENCES);
+        assert!(opts.env.len() <= MAX_OCCURRENCES);
+    }
+});
@@ -39,18 +39,37 @@
 //! }
 //! ```
 
+#[cfg(feature = "glob")]
+extern crate glob;
+#[cfg(feature = "regex")]
+extern crate regex;
+#[cfg(feature = "serde")]
+extern crate serde_json;
 extern crate spawner_opts_derive;
 
 pub mod parser;
+pub mod value_parser;
+
+#[cfg(test)]
+extern crate self as spawner_opts;
+
+#[cfg(test)]
+mod tests;
 
 pub use spawner_opts_derive::*;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fmt;
+use std::io::{self, IsTerminal};
 
 pub struct OptionHelp {
     pub names: Vec<String>,
     pub desc: Option<String>,
     pub value_desc: Option<String>,
     pub env: Option<String>,
+    /// Extra hints for external renderers, e.g. the widget type or the units of the value,
+    /// see the `meta(...)` attribute. Not shown by `Display`.
+    pub metadata: HashMap<String, String>,
 }
 
 pub struct Help {
@@ -58,41 +77,639 @@ pub struct Help {
     pub usage: Option<String>,
     pub delimeters: Option<String>,
     pub options: Vec<OptionHelp>,
+    /// Print short names (`-v`) and long names (`--verbose`) in separate aligned columns.
+    pub name_columns: bool,
+    /// The width in characters the usage is wrapped to, see `DEFAULT_HELP_WIDTH`.
+    pub width: usize,
+    /// Whether option names are printed in bold.
+    pub color: HelpColor,
+    /// A footer printed after the options, e.g. examples or where to report bugs. Wrapped to
+    /// `width` like the usage.
+    pub epilog: Option<String>,
+}
+
+/// When `Help` highlights option names with ANSI escape codes. Alignment is the same either
+/// way, the escape codes take no columns.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum HelpColor {
+    #[default]
+    Never,
+    /// Highlight if stdout is a terminal and `NO_COLOR` is not set.
+    Auto,
+    Always,
+}
+
+/// The width of `Help` generated by the derive, fits a standard terminal.
+pub const DEFAULT_HELP_WIDTH: usize = 80;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// An option value was rejected by its parser or by a validator.
+    InvalidValue(String),
+    /// An option value was rejected by its parser, see `CmdLineOptions::parse_from`. Carries
+    /// the index of the token holding the value in the whole command line and the message.
+    InvalidToken(usize, String),
+    /// One of the `auto_version` flags was given. Carries the version string.
+    VersionRequested(String),
+    /// One of the `auto_help` flags was given.
+    HelpRequested,
+}
+
+/// How `CmdLineOptions::parse_tokens` parses the command line.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ParseSettings {
+    /// Fail if any option or flag is given more times, see `CmdLineOptions::parse_bounded`.
+    pub max_occurrences: usize,
+    /// Don't recognize the `auto_help` and `auto_version` flags, so the caller can handle help
+    /// and version itself. The flags are then parsed as any other option: a field declaring
+    /// the same name is set, otherwise parsing stops at the flag as at any unknown option.
+    pub quiet: bool,
+    /// Parse a prefix of the command line, see `ParserState`. An option at the end that is
+    /// missing its value is consumed and returned as `Parsed::pending`, and the checks that
+    /// need the whole command line, i.e. validators, `requires` and `conflicts_with`, are
+    /// skipped.
+    pub partial: bool,
+    /// Report option values rejected by their parsers as `ParseError::InvalidToken`, with the
+    /// token index counted from the given index of the whole command line.
+    pub start_index: Option<usize>,
+}
+
+/// The result of `CmdLineOptions::parse_outcome`, telling a successful parse from a request
+/// for help or version that should end the program.
+pub enum ParseOutcome {
+    /// The options were parsed, carries the index of the first program argument.
+    Consumed(usize),
+    /// One of the `auto_help` flags was given, carries the help to print.
+    Help(Help),
+    /// One of the `auto_version` flags was given, carries the version string.
+    Version(String),
+}
+
+/// The result of `CmdLineOptions::parse_tokens`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parsed {
+    /// The index of the first program argument.
+    pub args_start: usize,
+    /// An option at the end that takes the tokens given after it, i.e. one missing its value
+    /// or a `rest` option, or `--` at the end. Always `None` unless `ParseSettings::partial`
+    /// is set. Parsing it again followed by the next tokens resumes the parse.
+    pub pending: Option<String>,
+}
+
+/// Parses a command line fed one token at a time, e.g. as the tokens are read from a pipe.
+/// Options are set as soon as their tokens are fed, an option waiting for its value stays
+/// pending until the next token. Validators, `requires` and `conflicts_with` run in `finish`.
+///
+/// Each token is parsed once, along with the pending option if there is one, so feeding the
+/// whole command line takes linear time. `finish` parses the tokens fed so far once more
+/// into `O::default()`, so values set on the options before are not kept.
+pub struct ParserState<O> {
+    opts: O,
+    tokens: Vec<String>,
+    settings: ParseSettings,
+    warnings: Vec<String>,
+    /// See `Parsed::pending`.
+    pending: Option<String>,
+    done: Option<Done>,
+}
+
+/// The options have ended, see `ParserState::feed`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Done {
+    /// The index of the first program argument among the tokens fed so far.
+    pub args_start: usize,
 }
 
 pub trait CmdLineOptions: Sized {
     fn help() -> Help;
-    fn parse_argv<T, U>(&mut self, argv: T) -> Result<usize, String>
+
+    /// Returns every name declared by options and flags, in declaration order.
+    fn option_names() -> Vec<&'static str>;
+
+    /// Parses options until the first token that is not an option and returns its index.
+    /// The remaining tokens are never interpreted, even if they look like options.
+    /// A `--` that ends the options is skipped, the returned index is the token after it.
+    fn parse_argv<T, U>(&mut self, argv: T) -> Result<usize, ParseError>
+    where
+        T: IntoIterator<Item = U>,
+        U: AsRef<str>,
+    {
+        self.parse_argv_with_warnings(argv, &mut Vec::new())
+    }
+
+    /// Same as `parse_argv`, but returns help and version requests as outcomes rather than
+    /// errors, so callers can handle every case with one exhaustive `match`.
+    fn parse_outcome<T, U>(&mut self, argv: T) -> Result<ParseOutcome, ParseError>
+    where
+        T: IntoIterator<Item = U>,
+        U: AsRef<str>,
+    {
+        match self.parse_argv(argv) {
+            Ok(n) => Ok(ParseOutcome::Consumed(n)),
+            Err(ParseError::HelpRequested) => Ok(ParseOutcome::Help(Self::help())),
+            Err(ParseError::VersionRequested(v)) => Ok(ParseOutcome::Version(v)),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Same as `parse_argv`, but skips the first `start_index` tokens, e.g. the program name
+    /// or a subcommand, and returns the index of the first program argument in the whole
+    /// `argv`, so it can be used for slicing `argv` directly. Rejected option values are
+    /// reported as `ParseError::InvalidToken` with the index in the whole `argv` as well.
+    fn parse_from<T, U>(&mut self, argv: T, start_index: usize) -> Result<usize, ParseError>
+    where
+        T: IntoIterator<Item = U>,
+        U: AsRef<str>,
+    {
+        let settings = ParseSettings {
+            start_index: Some(start_index),
+            ..ParseSettings::default()
+        };
+        self.parse_with_settings(
+            argv.into_iter().skip(start_index),
+            settings,
+            &mut Vec::new(),
+        )
+        .map(|n| n + start_index)
+    }
+
+    /// Same as `parse_argv` for the common case of a slice, e.g. `&[&str]` or `&[String]`.
+    /// The returned index can be used to slice the program arguments off `argv`.
+    fn parse_slice<S: AsRef<str>>(&mut self, argv: &[S]) -> Result<usize, ParseError> {
+        self.parse_argv(argv.iter().map(AsRef::as_ref))
+    }
+
+    /// Same as `parse_argv`, but also collects the warnings reported by value parsers.
+    fn parse_argv_with_warnings<T, U>(
+        &mut self,
+        argv: T,
+        warnings: &mut Vec<String>,
+    ) -> Result<usize, ParseError>
+    where
+        T: IntoIterator<Item = U>,
+        U: AsRef<str>,
+    {
+        self.parse_bounded_with_warnings(argv, usize::MAX, warnings)
+    }
+
+    /// Same as `parse_argv`, but fails if any option or flag is given more than
+    /// `max_occurrences` times. Use it for untrusted command lines, where accumulating
+    /// options, such as `Vec` fields, could otherwise grow without bound.
+    fn parse_bounded<T, U>(&mut self, argv: T, max_occurrences: usize) -> Result<usize, ParseError>
+    where
+        T: IntoIterator<Item = U>,
+        U: AsRef<str>,
+    {
+        self.parse_bounded_with_warnings(argv, max_occurrences, &mut Vec::new())
+    }
+
+    /// Same as `parse_bounded`, but also collects the warnings reported by value parsers.
+    fn parse_bounded_with_warnings<T, U>(
+        &mut self,
+        argv: T,
+        max_occurrences: usize,
+        warnings: &mut Vec<String>,
+    ) -> Result<usize, ParseError>
+    where
+        T: IntoIterator<Item = U>,
+        U: AsRef<str>,
+    {
+        let settings = ParseSettings {
+            max_occurrences,
+            ..ParseSettings::default()
+        };
+        self.parse_with_settings(argv, settings, warnings)
+    }
+
+    /// Same as `parse_argv`, but never returns `HelpRequested` or `VersionRequested`, see
+    /// `ParseSettings::quiet`.
+    fn parse_quiet<T, U>(&mut self, argv: T) -> Result<usize, ParseError>
+    where
+        T: IntoIterator<Item = U>,
+        U: AsRef<str>,
+    {
+        let settings = ParseSettings {
+            quiet: true,
+            ..ParseSettings::default()
+        };
+        self.parse_with_settings(argv, settings, &mut Vec::new())
+    }
+
+    /// Same as `parse_argv_with_warnings`, but parses as told by `settings`.
+    fn parse_with_settings<T, U>(
+        &mut self,
+        argv: T,
+        settings: ParseSettings,
+        warnings: &mut Vec<String>,
+    ) -> Result<usize, ParseError>
+    where
+        T: IntoIterator<Item = U>,
+        U: AsRef<str>,
+    {
+        self.parse_tokens(argv, settings, warnings)
+            .map(|parsed| parsed.args_start)
+    }
+
+    /// Same as `parse_with_settings`, but also returns the pending option of a partial parse.
+    fn parse_tokens<T, U>(
+        &mut self,
+        argv: T,
+        settings: ParseSettings,
+        warnings: &mut Vec<String>,
+    ) -> Result<Parsed, ParseError>
     where
         T: IntoIterator<Item = U>,
         U: AsRef<str>;
 
-    fn parse_env(&mut self) -> Result<(), String>;
+    /// Restores the default values, e.g. before parsing another command line with the same
+    /// struct. Since options accumulate, such as `Vec` fields, parsing without a reset carries
+    /// the previous values over.
+    fn reset(&mut self)
+    where
+        Self: Default,
+    {
+        *self = Self::default();
+    }
+
+    fn parse_env(&mut self) -> Result<(), ParseError> {
+        self.parse_env_with_warnings(&mut Vec::new())
+    }
+
+    /// Same as `parse_env`, but also collects the warnings reported by value parsers.
+    fn parse_env_with_warnings(&mut self, warnings: &mut Vec<String>) -> Result<(), ParseError>;
+
+    /// Sets options from `(key, value)` entries, e.g. read from a config file. Keys are field
+    /// names, values are parsed the same way as on the command line, so `("memory", "256M")`
+    /// goes through the parser of the `memory` field. Flags take `true`, `false`, `1` or `0`.
+    /// Repeated keys accumulate like repeated options.
+    ///
+    /// To let the command line override the config, parse the config first: options given
+    /// later replace single values.
+    fn parse_config<I, K, V>(&mut self, entries: I) -> Result<(), ParseError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        self.parse_config_with_warnings(entries, &mut Vec::new())
+    }
+
+    /// Same as `parse_config`, but also collects the warnings reported by value parsers.
+    fn parse_config_with_warnings<I, K, V>(
+        &mut self,
+        entries: I,
+        warnings: &mut Vec<String>,
+    ) -> Result<(), ParseError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>;
+
+    /// Creates the options from a config object, e.g. a JSON or TOML file deserialized into
+    /// a `serde_json::Value`. Fields missing from the config keep their default values, see
+    /// `parse_config_value`.
+    #[cfg(feature = "serde")]
+    fn from_config(value: &serde_json::Value) -> Result<Self, ParseError>
+    where
+        Self: Default,
+    {
+        let mut opts = Self::default();
+        opts.parse_config_value(value, &mut Vec::new())?;
+        Ok(opts)
+    }
+
+    /// Sets options from a config object with `parse_config_with_warnings`. Strings are
+    /// passed as is, numbers and booleans as their text and arrays as one entry per element,
+    /// so `{"memory": "256M", "include": ["a", "b"]}` goes through the same parsers as the
+    /// command line. `null` values are skipped.
+    ///
+    /// Like `parse_config`, parse the config before the command line to let it override
+    /// the config.
+    #[cfg(feature = "serde")]
+    fn parse_config_value(
+        &mut self,
+        value: &serde_json::Value,
+        warnings: &mut Vec<String>,
+    ) -> Result<(), ParseError> {
+        let obj = match value.as_object() {
+            Some(obj) => obj,
+            None => {
+                return Err(ParseError::InvalidValue(
+                    "Config must be an object".to_string(),
+                ))
+            }
+        };
+        let mut entries = Vec::new();
+        for (key, val) in obj {
+            match val {
+                serde_json::Value::Array(items) => {
+                    for item in items {
+                        entries.push((key, config_scalar(key, item)?));
+                    }
+                }
+                serde_json::Value::Null => {}
+                _ => entries.push((key, config_scalar(key, val)?)),
+            }
+        }
+        self.parse_config_with_warnings(entries, warnings)
+    }
 }
 
 pub trait OptionValueParser<T> {
     fn parse(opt: &mut T, val: &str) -> Result<(), String>;
+
+    /// Parses the value, allowing the parser to accept it while reporting warnings, e.g. when
+    /// a deprecated form of the value is used. By default no warnings are reported.
+    fn parse_with_warnings(
+        opt: &mut T,
+        val: &str,
+        warnings: &mut Vec<String>,
+    ) -> Result<(), String> {
+        let _ = warnings;
+        Self::parse(opt, val)
+    }
 }
 
-impl fmt::Display for Help {
+/// A parser that can read the other fields of the options struct `C` while parsing the value,
+/// e.g. to check it against another option.
+pub trait ContextualOptionValueParser<C, T> {
+    fn parse(ctx: &C, opt: &mut T, val: &str) -> Result<(), String>;
+}
+
+impl Default for ParseSettings {
+    fn default() -> Self {
+        Self {
+            max_occurrences: usize::MAX,
+            quiet: false,
+            partial: false,
+            start_index: None,
+        }
+    }
+}
+
+impl<O: CmdLineOptions + Default> ParserState<O> {
+    pub fn new() -> Self {
+        Self::with_settings(ParseSettings::default())
+    }
+
+    pub fn with_settings(settings: ParseSettings) -> Self {
+        Self {
+            opts: O::default(),
+            tokens: Vec::new(),
+            settings,
+            warnings: Vec::new(),
+            pending: None,
+            done: None,
+        }
+    }
+
+    /// Parses the next token. Returns `Done` once the options have ended, i.e. a token that is
+    /// not an option or the one after `--` is fed. That token is the program, it and the rest
+    /// of the command line don't need to be fed.
+    pub fn feed(&mut self, token: &str) -> Result<Option<Done>, ParseError> {
+        self.tokens.push(token.to_string());
+        if self.done.is_some() {
+            return Ok(self.done);
+        }
+        let settings = ParseSettings {
+            partial: true,
+            ..self.settings
+        };
+        let pending = self.pending.take();
+        // The pending option is parsed again, it stands for its own token.
+        let start = self.tokens.len() - 1 - usize::from(pending.is_some());
+        let chunk = pending.iter().map(String::as_str).chain(Some(token));
+        let parsed = self
+            .opts
+            .parse_tokens(chunk, settings, &mut self.warnings)?;
+        let chunk_len = self.tokens.len() - start;
+        if parsed.args_start < chunk_len {
+            self.done = Some(Done {
+                args_start: start + parsed.args_start,
+            });
+        }
+        self.pending = parsed.pending;
+        Ok(self.done)
+    }
+
+    /// Parses the tokens fed so far as a complete command line, running the checks `feed`
+    /// skips. An option still missing its value is taken for the program, as `parse_argv`
+    /// does.
+    pub fn finish(&mut self) -> Result<Done, ParseError> {
+        self.opts.reset();
+        self.warnings.clear();
+        self.opts
+            .parse_with_settings(&self.tokens, self.settings, &mut self.warnings)
+            .map(|args_start| Done { args_start })
+    }
+
+    pub fn options(&self) -> &O {
+        &self.opts
+    }
+
+    pub fn into_options(self) -> O {
+        self.opts
+    }
+
+    /// The warnings reported by value parsers for the tokens fed so far.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+}
+
+impl<O: CmdLineOptions + Default> Default for ParserState<O> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HelpColor {
+    pub fn enabled(self) -> bool {
+        match self {
+            HelpColor::Never => false,
+            HelpColor::Auto => std::env::var_os("NO_COLOR").is_none() && io::stdout().is_terminal(),
+            HelpColor::Always => true,
+        }
+    }
+}
+
+impl ParseError {
+    /// The exit code of a program that stops because of the error: 2 for invalid values, the
+    /// usual code for command line usage errors, and 0 for help and version requests, which
+    /// are not failures.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ParseError::InvalidValue(_) | ParseError::InvalidToken(..) => 2,
+            ParseError::VersionRequested(_) | ParseError::HelpRequested => 0,
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::InvalidValue(s) => f.write_str(s),
+            ParseError::InvalidToken(idx, s) => write!(f, "{} (argument {})", s, idx),
+            ParseError::VersionRequested(v) => f.write_str(v),
+            ParseError::HelpRequested => f.write_str("Help requested"),
+        }
+    }
+}
+
+impl From<String> for ParseError {
+    fn from(s: String) -> Self {
+        ParseError::InvalidValue(s)
+    }
+}
+
+impl From<ParseError> for String {
+    fn from(e: ParseError) -> Self {
+        e.to_string()
+    }
+}
+
+impl Help {
+    /// Renders the options as a Markdown table with `Option` and `Description` columns.
+    pub fn to_markdown(&self) -> String {
+        let delim = self.delim();
+        let mut table = String::from("| Option | Description |\n| --- | --- |\n");
+        for opt in self.options.iter() {
+            let names: Vec<String> = opt
+                .names
+                .iter()
+                .map(|name| {
+                    let mut name = name.clone();
+                    if let Some(ref vd) = opt.value_desc {
+                        name.push(delim);
+                        name.push_str(vd);
+                    }
+                    format!("`{}`", escape_markdown_cell(&name))
+                })
+                .collect();
+            let desc = opt.desc.as_ref().map_or(String::new(), |desc| {
+                escape_markdown_cell(desc).replace('\n', "<br>")
+            });
+            table.push_str(&format!("| {} | {} |\n", names.join("<br>"), desc));
+        }
+        table
+    }
+
+    /// Returns the option that declares `name`. Leading dashes may be omitted, so both
+    /// `--time-limit` and `time-limit` find the option declared as `--time-limit`.
+    pub fn option_by_name(&self, name: &str) -> Option<&OptionHelp> {
+        self.options.iter().find(|opt| {
+            opt.names
+                .iter()
+                .any(|n| n == name || n.trim_start_matches('-') == name)
+        })
+    }
+
+    /// Renders the help of the option that declares `name`, see `option_by_name`, in the same
+    /// format as `Display` but without the overview, the usage and the other options.
+    pub fn render_option(&self, name: &str) -> Option<String> {
+        let opt = self.option_by_name(name)?;
+        let mut text = String::new();
+        let names = join_names(opt.names.iter(), opt, self.delim());
+        write_opt(&mut text, opt, &names, self.color.enabled()).ok()?;
+        if opt.env.is_some() {
+            text.push_str("\nEnvironment variables and corresponding options:\n");
+            write_env_desc(&mut text, opt).ok()?;
+        }
+        Some(text)
+    }
+
+    /// The width in characters of the widest option names column as rendered by `Display`,
+    /// including value descriptions joined with `delim`. Returns 0 if there are no options.
+    pub fn max_name_width(&self, delim: char) -> usize {
+        let short_width = self.short_names_width();
+        self.options
+            .iter()
+            .map(|opt| {
+                if self.name_columns {
+                    name_columns(opt, delim, short_width).chars().count()
+                } else {
+                    join_names_len(opt.names.iter(), opt)
+                }
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn delim(&self) -> char {
+        match self.delimeters {
+            Some(ref d) => d.chars().next().unwrap_or(' '),
+            None => ' ',
+        }
+    }
+
+    fn short_names_width(&self) -> usize {
+        self.options
+            .iter()
+            .map(|opt| join_names_len(opt.names.iter().filter(|n| !is_long_name(n)), opt))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Writes the same text as `Display` without building it in memory first.
+    pub fn write_to<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut writer = IoWriter {
+            inner: w,
+            error: None,
+        };
+        self.write_help(&mut writer).map_err(|_| {
+            writer
+                .error
+                .take()
+                .unwrap_or_else(|| io::Error::other("Unable to format help"))
+        })
+    }
+
+    fn write_help<W: fmt::Write>(&self, f: &mut W) -> fmt::Result {
         if let Some(ref overview) = self.overview {
-            write!(f, "Overview: {}\n\n", overview)?;
+            write!(f, "Overview: {}\n\n", normalize_whitespace(overview))?;
         }
         if let Some(ref usage) = self.usage {
-            write!(f, "Usage: {}\n\n", usage)?;
+            let prefix = "Usage: ";
+            let usage = normalize_whitespace(usage);
+            write!(
+                f,
+                "{}{}\n\n",
+                prefix,
+                wrap_words(&usage, prefix.len(), self.width)
+            )?;
         }
+        self.write_options(f)?;
+        if let Some(ref epilog) = self.epilog {
+            if !self.options.is_empty() {
+                f.write_str("\n")?;
+            }
+            writeln!(
+                f,
+                "{}",
+                wrap_words(&normalize_whitespace(epilog), 0, self.width)
+            )?;
+        }
+        Ok(())
+    }
+
+    fn write_options<W: fmt::Write>(&self, f: &mut W) -> fmt::Result {
         if self.options.is_empty() {
             return Ok(());
         }
 
-        let delim = match self.delimeters {
-            Some(ref d) => d.chars().next().unwrap_or(' '),
-            None => ' ',
-        };
+        let delim = self.delim();
+        let short_width = self.short_names_width();
+        let bold = self.color.enabled();
         f.write_str("Options:\n")?;
         for opt in self.options.iter() {
-            write_opt(f, opt, delim)?;
+            let names = if self.name_columns {
+                name_columns(opt, delim, short_width)
+            } else {
+                join_names(opt.names.iter(), opt, delim)
+            };
+            write_opt(f, opt, &names, bold)?;
         }
 
         if self.options.iter().any(|opt| opt.env.is_some()) {
@@ -105,10 +722,49 @@ impl fmt::Display for Help {
     }
 }
 
-fn write_env_desc(f: &mut fmt::Formatter, opt: &OptionHelp) -> fmt::Result {
+/// The text of a single config value, as it would be given on the command line.
+#[cfg(feature = "serde")]
+fn config_scalar(key: &str, value: &serde_json::Value) -> Result<String, ParseError> {
+    match value {
+        serde_json::Value::String(s) => Ok(s.clone()),
+        serde_json::Value::Number(n) => Ok(n.to_string()),
+        serde_json::Value::Bool(b) => Ok(b.to_string()),
+        _ => Err(ParseError::InvalidValue(format!(
+            "Invalid value for config key '{}', expected a string, a number or a boolean",
+            key
+        ))),
+    }
+}
+
+fn escape_markdown_cell(s: &str) -> String {
+    s.replace('|', "\\|")
+}
+
+impl fmt::Display for Help {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_help(f)
+    }
+}
+
+/// Forwards `fmt::Write` to `io::Write`, keeping the I/O error that `fmt::Error` can't carry.
+struct IoWriter<'a, W: io::Write> {
+    inner: &'a mut W,
+    error: Option<io::Error>,
+}
+
+impl<'a, W: io::Write> fmt::Write for IoWriter<'a, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            fmt::Error
+        })
+    }
+}
+
+fn write_env_desc<W: fmt::Write>(f: &mut W, opt: &OptionHelp) -> fmt::Result {
     if let Some(ref env) = opt.env {
         let indent = "  ";
-        let spaces = 30 - (env.len() + indent.len());
+        let spaces = 30usize.saturating_sub(env.chars().count() + indent.len());
         write!(f, "{}{}{:3$}", indent, env, " ", spaces)?;
         for (idx, name) in opt.names.iter().enumerate() {
             if idx > 0 {
@@ -121,39 +777,142 @@ fn write_env_desc(f: &mut fmt::Formatter, opt: &OptionHelp) -> fmt::Result {
     Ok(())
 }
 
-fn write_names(f: &mut fmt::Formatter, opt: &OptionHelp, delim: char) -> Result<usize, fmt::Error> {
-    let mut names_len = 0;
-    for (no, name) in opt.names.iter().enumerate() {
+/// Expands tabs and drops carriage returns, so the length of each line is its display width.
+/// Borrows the text if there is nothing to replace, which is the common case.
+fn normalize_whitespace(s: &str) -> Cow<'_, str> {
+    if !s.contains(['\t', '\r']) {
+        return Cow::Borrowed(s);
+    }
+    let tab_width = 4;
+    let mut result = String::with_capacity(s.len());
+    let mut column = 0;
+    for c in s.chars() {
+        match c {
+            '\r' => {}
+            '\t' => {
+                let spaces = tab_width - column % tab_width;
+                result.extend(std::iter::repeat_n(' ', spaces));
+                column += spaces;
+            }
+            '\n' => {
+                result.push(c);
+                column = 0;
+            }
+            _ => {
+                result.push(c);
+                column += 1;
+            }
+        }
+    }
+    Cow::Owned(result)
+}
+
+/// Breaks the lines of `s` between words so that they fit `width` when the text starts at
+/// column `indent`. Continuation lines are indented by `indent`, words longer than the width
+/// are kept whole. Leading spaces of a line are kept, e.g. for indented examples.
+fn wrap_words(s: &str, indent: usize, width: usize) -> Cow<'_, str> {
+    if s.lines().all(|line| indent + line.chars().count() <= width) {
+        return Cow::Borrowed(s);
+    }
+    let mut result = String::with_capacity(s.len());
+    for (no, line) in s.split('\n').enumerate() {
         if no > 0 {
-            f.write_str(", ")?;
-            names_len += 2;
+            result.push('\n');
+            result.extend(std::iter::repeat_n(' ', indent));
+        }
+        let words = line.trim_start_matches(' ');
+        let lead = line.len() - words.len();
+        result.push_str(&line[..lead]);
+        let mut column = indent + lead;
+        for (idx, word) in words.split(' ').filter(|w| !w.is_empty()).enumerate() {
+            let len = word.chars().count();
+            if idx > 0 && column + 1 + len > width {
+                result.push('\n');
+                result.extend(std::iter::repeat_n(' ', indent));
+                column = indent;
+            } else if idx > 0 {
+                result.push(' ');
+                column += 1;
+            }
+            result.push_str(word);
+            column += len;
         }
-        f.write_str(name)?;
-        names_len += name.len();
+    }
+    Cow::Owned(result)
+}
+
+fn is_long_name(name: &str) -> bool {
+    name.starts_with("--")
+}
+
+fn join_names<'a, I>(names: I, opt: &OptionHelp, delim: char) -> String
+where
+    I: Iterator<Item = &'a String>,
+{
+    let mut result = String::new();
+    for (no, name) in names.enumerate() {
+        if no > 0 {
+            result.push_str(", ");
+        }
+        result.push_str(name);
         if let Some(ref vd) = opt.value_desc {
-            write!(f, "{}{}", delim, vd)?;
-            names_len += 1 + vd.len();
+            result.push(delim);
+            result.push_str(vd);
         }
     }
-    Ok(names_len)
+    result
+}
+
+/// The length of `join_names` over the same names in characters, without building the string.
+fn join_names_len<'a, I>(names: I, opt: &OptionHelp) -> usize
+where
+    I: Iterator<Item = &'a String>,
+{
+    let value_desc_len = opt
+        .value_desc
+        .as_ref()
+        .map_or(0, |vd| 1 + vd.chars().count());
+    names
+        .enumerate()
+        .map(|(no, name)| if no > 0 { 2 } else { 0 } + name.chars().count() + value_desc_len)
+        .sum()
+}
+
+fn short_names_column(opt: &OptionHelp, delim: char) -> String {
+    join_names(opt.names.iter().filter(|n| !is_long_name(n)), opt, delim)
+}
+
+fn name_columns(opt: &OptionHelp, delim: char, short_width: usize) -> String {
+    let short = short_names_column(opt, delim);
+    let long = join_names(opt.names.iter().filter(|n| is_long_name(n)), opt, delim);
+    if long.is_empty() {
+        short
+    } else if short.is_empty() {
+        let padding = if short_width > 0 { short_width + 2 } else { 0 };
+        format!("{:2$}{}", "", long, padding)
+    } else {
+        format!("{:2$}, {}", short, long, short_width)
+    }
 }
 
-fn write_opt(f: &mut fmt::Formatter, opt: &OptionHelp, delim: char) -> fmt::Result {
+fn write_opt<W: fmt::Write>(f: &mut W, opt: &OptionHelp, names: &str, bold: bool) -> fmt::Result {
     let desc_offset = 30;
     let opt_offset = 2;
-    let empty = &String::new();
+    let desc = opt
+        .desc
+        .as_deref()
+        .map_or(Cow::Borrowed(""), normalize_whitespace);
 
     write!(f, "{:1$}", " ", opt_offset)?;
-    let written = opt_offset + write_names(f, opt, delim)?;
+    if bold {
+        write!(f, "\x1b[1m{}\x1b[0m", names)?;
+    } else {
+        f.write_str(names)?;
+    }
+    // Columns are counted in characters rather than bytes, so that non-ASCII names align.
+    let written = opt_offset + names.chars().count();
 
-    for (no, line) in opt
-        .desc
-        .as_ref()
-        .unwrap_or(empty)
-        .split('\n')
-        .filter(|line| !line.is_empty())
-        .enumerate()
-    {
+    for (no, line) in desc.split('\n').filter(|line| !line.is_empty()).enumerate() {
         if no == 0 && written < desc_offset {
             write!(f, "{:1$}", " ", desc_offset - written)?;
         } else {
@@ -3,6 +3,19 @@ use std::collections::HashMap;
 pub enum Entries {
     Flag(Vec<String>),
     Opt(Vec<String>),
+    /// An option whose value may be omitted, in which case the default value is used.
+    OptionalOpt(Vec<String>, &'static str),
+    /// An option that takes every token after it.
+    Rest(Vec<String>),
+}
+
+/// Where parsing stopped. The two indices differ only when parsing stopped at `--`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ParseSplit {
+    /// The index of the first unparsed token, `--` itself if parsing stopped at it.
+    pub options_end: usize,
+    /// The index of the first token that should be treated as the program and its arguments.
+    pub args_start: usize,
 }
 
 pub struct Parser<T, U>
@@ -12,8 +25,19 @@ where
 {
     pos: std::iter::Peekable<<T as IntoIterator>::IntoIter>,
     entries: Vec<Entries>,
+    /// The indices of the tokens holding the values of each entry.
+    value_tokens: Vec<Vec<usize>>,
+    /// The index of the token being parsed.
+    token: usize,
     optmap: HashMap<&'static str, usize>,
     delims: &'static str,
+    unknown_opts: Vec<String>,
+    max_occurrences: usize,
+    exceeded_opt: Option<String>,
+    forward_unknown: bool,
+    forwarded: Vec<String>,
+    partial: bool,
+    pending: Option<String>,
 }
 
 impl<T, U> Parser<T, U>
@@ -25,12 +49,47 @@ where
         Parser {
             pos: argv.into_iter().peekable(),
             entries: Vec::new(),
+            value_tokens: Vec::new(),
+            token: 0,
             optmap: HashMap::new(),
             delims,
+            unknown_opts: Vec::new(),
+            max_occurrences: usize::MAX,
+            exceeded_opt: None,
+            forward_unknown: false,
+            forwarded: Vec::new(),
+            partial: false,
+            pending: None,
         }
     }
 
+    /// Limits how many times each option or flag may be given, so that a hostile command line
+    /// can't make the accumulated values grow without bound. Parsing stops at the first option
+    /// given more than `max` times, see `exceeded_opt`.
+    pub fn max_occurrences(&mut self, max: usize) -> &mut Self {
+        self.max_occurrences = max;
+        self
+    }
+
+    /// Makes unrecognized long options, such as `--foo` or `--foo=bar`, end up in `forwarded`
+    /// instead of stopping the parsing. Whether `--foo` takes a value can't be known, so the
+    /// next token is forwarded along with it unless that token looks like an option. An
+    /// unknown flag right before the program should therefore be given after `--`.
+    pub fn forward_unknown(&mut self) -> &mut Self {
+        self.forward_unknown = true;
+        self
+    }
+
+    /// Takes the command line for a prefix of a longer one, so an option at the end that is
+    /// missing its value is consumed rather than stopping the parsing. The value is expected
+    /// to follow, see `ParserState`.
+    pub fn partial(&mut self) -> &mut Self {
+        self.partial = true;
+        self
+    }
+
     fn add_names(&mut self, names: &[&'static str]) {
+        self.value_tokens.push(Vec::new());
         let idx = self.entries.len() - 1;
         for name in names {
             self.optmap.insert(name, idx);
@@ -43,6 +102,24 @@ where
         self
     }
 
+    /// Registers an option that can be given without a value, e.g. `--color` alongside
+    /// `--color=always`. A bare occurrence is recorded as `default`. The value must be
+    /// attached with a delimeter, the next token is never consumed.
+    pub fn optional_opt(&mut self, names: &[&'static str], default: &'static str) -> &mut Self {
+        self.entries.push(Entries::OptionalOpt(Vec::new(), default));
+        self.add_names(names);
+        self
+    }
+
+    /// Registers an option that takes every remaining token as its value, e.g. `--command`
+    /// in `--command prog -a b`. A value attached with a delimeter becomes the first token.
+    /// Parsing ends at such an option, so it is given at most once.
+    pub fn rest_opt(&mut self, names: &[&'static str]) -> &mut Self {
+        self.entries.push(Entries::Rest(Vec::new()));
+        self.add_names(names);
+        self
+    }
+
     pub fn flag(&mut self, names: &[&'static str]) -> &mut Self {
         self.entries.push(Entries::Flag(Vec::new()));
         self.add_names(names);
@@ -59,55 +136,247 @@ where
         })
     }
 
+    /// Returns the name under which the flag was given last time, e.g. to tell `--foo` from
+    /// `--no-foo` when both are registered as the same flag.
+    pub fn last_flag(&self, flag: &str) -> Option<&str> {
+        self.optmap.get(flag).and_then(|i| match self.entries[*i] {
+            Entries::Flag(ref e) => e.last().map(String::as_str),
+            _ => None,
+        })
+    }
+
     pub fn get_opt(&self, opt: &str) -> Option<&Vec<String>> {
-        self.optmap.get(opt).and_then(|i| {
-            if let Entries::Opt(ref e) = self.entries[*i] {
-                Some(e)
-            } else {
-                None
-            }
+        self.optmap.get(opt).and_then(|i| match self.entries[*i] {
+            Entries::Opt(ref e) | Entries::OptionalOpt(ref e, _) | Entries::Rest(ref e) => Some(e),
+            Entries::Flag(_) => None,
         })
     }
 
-    fn parse_opt(&mut self, arg: &str) -> bool {
-        let (name, val) = match arg.find(|x| self.delims.find(x).is_some()) {
-            Some(pos) => (&arg[0..pos], Some(&arg[pos + 1..arg.len()])),
-            None => (&arg[0..arg.len()], None),
+    /// Returns the index of the token holding the `n`-th value of the option. For a value
+    /// attached to the option or a default value that is the option's own token.
+    pub fn value_token(&self, opt: &str, n: usize) -> Option<usize> {
+        self.optmap
+            .get(opt)
+            .and_then(|i| self.value_tokens[*i].get(n).copied())
+    }
+
+    /// Returns the tokens that look like options (`-x`, `--xyz`) but were not recognized.
+    /// Since parsing stops at the first unrecognized token, this is either empty or holds the
+    /// token parsing stopped at, which callers may want to warn about.
+    pub fn unknown_opts(&self) -> &[String] {
+        &self.unknown_opts
+    }
+
+    /// Returns the unrecognized long options and their values, see `forward_unknown`.
+    pub fn forwarded(&self) -> &[String] {
+        &self.forwarded
+    }
+
+    /// Returns the option at the end of a partial command line that takes the tokens to come,
+    /// see `partial`. For a `rest` option that is its name, since its values are already
+    /// parsed.
+    pub fn pending(&self) -> Option<&str> {
+        self.pending.as_deref()
+    }
+
+    /// Returns the name of the option that was given more times than `max_occurrences` allows.
+    pub fn exceeded_opt(&self) -> Option<&str> {
+        self.exceeded_opt.as_deref()
+    }
+
+    /// Splits a single-dash option with the value attached directly, e.g. `-m256M`. The longest
+    /// declared name that `arg` starts with is taken, so with both `-v` and `-value` declared
+    /// `-value5` is `-value` given `5`. Only options that take a value are split, so a flag
+    /// followed by other characters is not recognized.
+    fn split_attached<'b>(&self, arg: &'b str) -> Option<(&'b str, Option<String>)> {
+        if arg.starts_with("--") {
+            return None;
+        }
+        let (name, &idx) = self
+            .optmap
+            .iter()
+            .filter(|(name, _)| name.len() > 1 && name.starts_with('-') && !name.starts_with("--"))
+            .filter(|(name, _)| name.len() < arg.len() && arg.starts_with(**name))
+            .max_by_key(|(name, _)| name.len())?;
+        match self.entries[idx] {
+            Entries::Opt(_) | Entries::OptionalOpt(..) => Some((
+                &arg[..name.len()],
+                Some(unescape(&arg[name.len()..], self.delims)),
+            )),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of tokens consumed by the option, or `None` if `arg` is not an option.
+    /// An empty token is never an option, even if an empty name was declared.
+    ///
+    /// The option is split at the first delimeter that is not escaped with a backslash, and
+    /// escaped delimeters in values lose the backslash, so both `--expr=a=b` and `--expr a\=b`
+    /// give `a=b`.
+    fn parse_opt(&mut self, arg: &str) -> Option<usize> {
+        if arg.is_empty() {
+            return None;
+        }
+        let (name, val) = match find_unescaped(arg, self.delims) {
+            Some(pos) => (&arg[0..pos], Some(unescape(&arg[pos + 1..], self.delims))),
+            None => (arg, None),
         };
+        let (name, val) = match self.optmap.get(name) {
+            Some(_) => (name, val),
+            None => self.split_attached(arg).unwrap_or((name, val)),
+        };
+        let delims = self.delims;
+        let token = self.token;
         if let Some(opt_idx) = self.optmap.get(name) {
             let entries = &mut self.entries[*opt_idx];
+            let value_tokens = &mut self.value_tokens[*opt_idx];
+            let occurrences = match entries {
+                Entries::Flag(e)
+                | Entries::Opt(e)
+                | Entries::OptionalOpt(e, _)
+                | Entries::Rest(e) => e.len(),
+            };
+            if occurrences >= self.max_occurrences {
+                self.exceeded_opt = Some(name.to_string());
+                return Some(0);
+            }
             match (entries, val) {
                 (Entries::Flag(e), None) => {
                     e.push(name.to_string());
-                    true
+                    Some(1)
                 }
                 (Entries::Opt(e), Some(v)) => {
-                    e.push(v.to_string());
-                    true
+                    e.push(v);
+                    value_tokens.push(token);
+                    Some(1)
+                }
+                // A bare-word option such as `sr` could as well be the program name, so its
+                // value must be attached, e.g. `sr=file`.
+                (Entries::Opt(_), None) if !name.starts_with('-') => None,
+                (Entries::Opt(e), None) => match self.pos.next() {
+                    Some(next) => {
+                        e.push(unescape(next.as_ref(), delims));
+                        value_tokens.push(token + 1);
+                        Some(2)
+                    }
+                    None if self.partial => {
+                        self.pending = Some(name.to_string());
+                        Some(1)
+                    }
+                    None => None,
+                },
+                (Entries::OptionalOpt(e, _), Some(v)) => {
+                    e.push(v);
+                    value_tokens.push(token);
+                    Some(1)
+                }
+                (Entries::OptionalOpt(e, default), None) => {
+                    e.push(default.to_string());
+                    value_tokens.push(token);
+                    Some(1)
                 }
-                (Entries::Opt(e), None) => {
-                    if let Some(next) = self.pos.next() {
-                        e.push(next.as_ref().to_string());
-                        true
-                    } else {
-                        false
+                (Entries::Rest(e), val) => {
+                    if self.partial {
+                        self.pending = Some(name.to_string());
                     }
+                    let attached = val.is_some();
+                    let first = match val {
+                        Some(v) => v,
+                        None => match self.pos.next() {
+                            Some(next) => next.as_ref().to_string(),
+                            None if self.partial => return Some(1),
+                            None => return None,
+                        },
+                    };
+                    e.push(first);
+                    e.extend(self.pos.by_ref().map(|next| next.as_ref().to_string()));
+                    Some(e.len() + usize::from(!attached))
                 }
-                _ => false,
+                _ => None,
             }
         } else {
-            false
+            None
         }
     }
 
+    /// Parses options until the first token that is not a recognized option. That token and
+    /// everything after it are left untouched, even if they look like options, so they can be
+    /// treated as the program and its arguments. An empty token stops parsing as well, unless
+    /// it is the value of an option, e.g. `--name ""`.
+    ///
+    /// Returns the index of the first unparsed token. If parsing stopped at `--`, that is the
+    /// index of `--`, see `parse_split` to skip it.
     pub fn parse(&mut self) -> usize {
-        let mut parsed_opts = 0;
+        self.parse_split().options_end
+    }
+
+    /// Same as `parse`, but also tells where the program arguments start, which is past `--`
+    /// if parsing stopped at it.
+    pub fn parse_split(&mut self) -> ParseSplit {
+        let mut consumed = 0;
+        let mut args_start = None;
         while let Some(arg) = self.pos.next() {
-            if !self.parse_opt(arg.as_ref()) {
-                break;
+            self.token = consumed;
+            match self.parse_opt(arg.as_ref()) {
+                Some(_) if self.exceeded_opt.is_some() => break,
+                Some(n) => consumed += n,
+                None if self.forward_unknown && is_long_opt(arg.as_ref()) => {
+                    let arg = arg.as_ref();
+                    self.forwarded.push(arg.to_string());
+                    consumed += 1;
+                    if arg.find(|x| self.delims.contains(x)).is_none() {
+                        if let Some(val) = self.pos.next_if(|next| !next.as_ref().starts_with('-'))
+                        {
+                            self.forwarded.push(val.as_ref().to_string());
+                            consumed += 1;
+                        }
+                    }
+                }
+                None => {
+                    let arg = arg.as_ref();
+                    if arg == "--" {
+                        args_start = Some(consumed + 1);
+                        if self.partial && self.pos.peek().is_none() {
+                            self.pending = Some(arg.to_string());
+                        }
+                    } else if arg.starts_with('-') && arg != "-" {
+                        self.unknown_opts.push(arg.to_string());
+                    }
+                    break;
+                }
             }
-            parsed_opts += 1;
         }
-        parsed_opts
+        ParseSplit {
+            options_end: consumed,
+            args_start: args_start.unwrap_or(consumed),
+        }
     }
 }
+
+/// Returns the position of the first delimeter that is not preceded by a backslash.
+fn find_unescaped(arg: &str, delims: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (pos, c) in arg.char_indices() {
+        if delims.contains(c) && !escaped {
+            return Some(pos);
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    None
+}
+
+/// Removes the backslashes escaping delimeters, other backslashes are kept as is.
+fn unescape(v: &str, delims: &str) -> String {
+    let mut result = String::with_capacity(v.len());
+    let mut chars = v.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' || !chars.peek().is_some_and(|&next| delims.contains(next)) {
+            result.push(c);
+        }
+    }
+    result
+}
+
+fn is_long_opt(arg: &str) -> bool {
+    arg.starts_with("--") && arg != "--"
+}
@@ -0,0 +1,1838 @@
+use spawner_opts::parser::{ParseSplit, Parser};
+use spawner_opts::value_parser::{
+    ArrayParser, Auto, BoundedIntParser, CommandLineParser, EndpointParser, ExitCodeListParser,
+    Keyword, KeywordOr, KeywordOrParser, NumberOr, RangeParser,
+};
+use spawner_opts::*;
+
+use std::collections::HashMap;
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(delimeters = "=", default_parser = "StringParser")]
+struct Opts {
+    #[flag(names("-f", "--flag"), desc = "a flag")]
+    flag: bool,
+
+    #[opt(name = "-o", desc = "an option")]
+    opt: String,
+
+    #[opt(names("-i", "--in"))]
+    #[opt(name = "--out")]
+    redirect: String,
+
+    #[opt(name = "-l", parser = "LevelParser")]
+    level: u32,
+}
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(
+    delimeters = "=",
+    default_parser = "LevelParser",
+    validator = "check_order",
+    validator = "check_limit"
+)]
+struct Range {
+    #[opt(name = "--min")]
+    min: u32,
+
+    #[opt(name = "--max")]
+    max: u32,
+}
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(
+    delimeters = "=",
+    default_parser = "LevelParser",
+    validator = "check_order",
+    validator = "check_limit",
+    validation = "all"
+)]
+struct RangeAll {
+    #[opt(name = "--min")]
+    min: u32,
+
+    #[opt(name = "--max")]
+    max: u32,
+}
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(delimeters = "=", default_parser = "LevelParser")]
+struct Limits {
+    #[opt(name = "--max-files", context_parser = "MaxFilesParser")]
+    max_files: u32,
+
+    #[opt(name = "--memory")]
+    memory: u32,
+}
+
+struct MaxFilesParser;
+
+impl ContextualOptionValueParser<Limits, u32> for MaxFilesParser {
+    fn parse(ctx: &Limits, opt: &mut u32, v: &str) -> Result<(), String> {
+        let files: u32 = v.parse().map_err(|_| format!("Invalid value '{}'", v))?;
+        if files > ctx.memory {
+            return Err(format!(
+                "--max-files={} is greater than --memory={}",
+                files, ctx.memory
+            ));
+        }
+        *opt = files;
+        Ok(())
+    }
+}
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(delimeters = "=", default_parser = "StringParser")]
+struct ColorOpts {
+    #[opt(name = "--color", optional_value = "always")]
+    color: String,
+}
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(delimeters = "=", default_parser = "StringParser")]
+struct DocOpts {
+    /// Input file.
+    /// Use - for stdin.
+    #[opt(name = "--in")]
+    input: String,
+
+    /// Ignored in favor of desc.
+    #[opt(name = "--out", desc = "output file")]
+    output: String,
+}
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(
+    delimeters = "=",
+    default_parser = "LevelParser",
+    auto_version = "1.2.3",
+    version_flags("-V", "--version")
+)]
+struct VersionOpts {
+    #[opt(name = "-l")]
+    level: u32,
+}
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(
+    delimeters = "=",
+    default_parser = "LevelParser",
+    auto_version = "1.2.3",
+    auto_help,
+    help_flags("-h", "--help")
+)]
+struct OutcomeOpts {
+    #[opt(name = "-l", desc = "level")]
+    level: u32,
+}
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(
+    delimeters = "=",
+    default_parser = "LevelParser",
+    auto_version = "1.2.3",
+    auto_help,
+    help_flags("-h", "--help")
+)]
+struct QuietOpts {
+    #[opt(name = "-l")]
+    level: u32,
+
+    #[flag(name = "-h")]
+    human: bool,
+}
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(delimeters = "=", default_parser = "LevelParser", auto_version)]
+struct PkgVersionOpts {
+    #[opt(name = "-l")]
+    level: u32,
+}
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(delimeters = "=", default_parser = "StringParser")]
+struct NegatableOpts {
+    #[flag(names("-v", "--verbose"), negatable)]
+    verbose: bool,
+
+    #[flag(name = "--quiet")]
+    quiet: bool,
+}
+
+#[derive(CmdLineOptions)]
+#[optcont(delimeters = "=", default_parser = "StringParser")]
+struct PresenceOpts {
+    #[flag(name = "--color", negatable)]
+    color: bool,
+
+    #[flag(name = "--plain", present = false, negatable)]
+    styled: bool,
+}
+
+trait Bounds {
+    fn bounds(&self) -> (u32, u32);
+}
+
+impl Bounds for Range {
+    fn bounds(&self) -> (u32, u32) {
+        (self.min, self.max)
+    }
+}
+
+impl Bounds for RangeAll {
+    fn bounds(&self) -> (u32, u32) {
+        (self.min, self.max)
+    }
+}
+
+fn check_order<T: Bounds>(opts: &T) -> Result<(), String> {
+    match opts.bounds() {
+        (min, max) if min > max => Err(format!("--min={} is greater than --max={}", min, max)),
+        _ => Ok(()),
+    }
+}
+
+fn check_limit<T: Bounds>(opts: &T) -> Result<(), String> {
+    match opts.bounds() {
+        (min, _) if min > 10 => Err(format!("--min={} exceeds 10", min)),
+        _ => Ok(()),
+    }
+}
+
+struct StringParser;
+
+impl OptionValueParser<String> for StringParser {
+    fn parse(opt: &mut String, v: &str) -> Result<(), String> {
+        *opt = v.to_string();
+        Ok(())
+    }
+}
+
+impl OptionValueParser<bool> for StringParser {
+    fn parse(opt: &mut bool, v: &str) -> Result<(), String> {
+        *opt = v == "1";
+        Ok(())
+    }
+}
+
+struct LevelParser;
+
+impl OptionValueParser<u32> for LevelParser {
+    fn parse(opt: &mut u32, v: &str) -> Result<(), String> {
+        Self::parse_with_warnings(opt, v, &mut Vec::new())
+    }
+
+    fn parse_with_warnings(
+        opt: &mut u32,
+        v: &str,
+        warnings: &mut Vec<String>,
+    ) -> Result<(), String> {
+        *opt = match v {
+            "high" => {
+                warnings.push(format!("'{}' is deprecated, use '2' instead", v));
+                2
+            }
+            _ => v.parse().map_err(|_| format!("Invalid value '{}'", v))?,
+        };
+        Ok(())
+    }
+}
+
+#[test]
+fn option_names() {
+    let mut names = Opts::option_names();
+    names.sort();
+    assert_eq!(names, ["--flag", "--in", "--out", "-f", "-i", "-l", "-o"]);
+}
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(delimeters = "=", default_parser = "LevelParser")]
+struct HiddenOpts {
+    #[opt(name = "-l", desc = "level")]
+    level: u32,
+
+    #[opt(
+        names("--debug-level", "-D"),
+        desc = "internal",
+        env = "HIDDEN_DEBUG_LEVEL",
+        hidden
+    )]
+    debug_level: u32,
+
+    #[flag(name = "--trace", hidden)]
+    trace: bool,
+}
+
+#[test]
+fn hidden_options() {
+    let mut opts = HiddenOpts::default();
+    assert_eq!(opts.parse_argv(["-D=2", "--trace", "-l=1"]), Ok(3));
+    assert_eq!((opts.level, opts.debug_level, opts.trace), (1, 2, true));
+
+    assert_eq!(HiddenOpts::option_names(), ["-l"]);
+    let help = HiddenOpts::help();
+    assert_eq!(help.options.len(), 1);
+    assert!(help.option_by_name("--debug-level").is_none());
+    assert!(help.option_by_name("--trace").is_none());
+    let text = help.to_string();
+    assert!(!text.contains("--debug-level") && !text.contains("HIDDEN_DEBUG_LEVEL"));
+    assert!(!help.to_markdown().contains("--trace"));
+}
+
+#[test]
+fn parse_error_exit_code() {
+    assert_eq!(ParseError::InvalidValue("x".to_string()).exit_code(), 2);
+    assert_eq!(
+        ParseError::VersionRequested("1.0".to_string()).exit_code(),
+        0
+    );
+    assert_eq!(ParseError::HelpRequested.exit_code(), 0);
+    assert_eq!(
+        Range::default()
+            .parse_argv(["--min=x"])
+            .unwrap_err()
+            .exit_code(),
+        2
+    );
+}
+
+#[test]
+fn parser_warnings() {
+    let mut opts = Opts::default();
+    let mut warnings = Vec::new();
+    assert!(opts
+        .parse_argv_with_warnings(["-l=1"], &mut warnings)
+        .is_ok());
+    assert_eq!(opts.level, 1);
+    assert!(warnings.is_empty());
+
+    assert!(opts
+        .parse_argv_with_warnings(["-l=high"], &mut warnings)
+        .is_ok());
+    assert_eq!(opts.level, 2);
+    assert_eq!(warnings, ["'high' is deprecated, use '2' instead"]);
+}
+
+#[test]
+fn validation_first() {
+    assert!(Range::default().parse_argv(["--min=1", "--max=2"]).is_ok());
+    assert_eq!(
+        Range::default().parse_argv(["--min=20", "--max=2"]),
+        Err(ParseError::InvalidValue(
+            "--min=20 is greater than --max=2".to_string()
+        ))
+    );
+}
+
+#[test]
+fn validation_all() {
+    assert!(RangeAll::default()
+        .parse_argv(["--min=1", "--max=2"])
+        .is_ok());
+    assert_eq!(
+        RangeAll::default().parse_argv(["--min=20", "--max=2"]),
+        Err(ParseError::InvalidValue(
+            "--min=20 is greater than --max=2\n--min=20 exceeds 10".to_string()
+        ))
+    );
+}
+
+#[test]
+fn contextual_parser() {
+    let mut limits = Limits::default();
+    assert!(limits.parse_argv(["--max-files=2", "--memory=4"]).is_ok());
+    assert_eq!(limits.max_files, 2);
+    assert_eq!(
+        Limits::default().parse_argv(["--max-files=8", "--memory=4"]),
+        Err(ParseError::InvalidValue(
+            "--max-files=8 is greater than --memory=4".to_string()
+        ))
+    );
+}
+
+#[test]
+fn help_name_columns() {
+    let mut help = Opts::help();
+    help.name_columns = true;
+    let help = help.to_string();
+    let lines: Vec<&str> = help.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "Options:",
+            "  -f, --flag                  a flag",
+            "  -o                          an option",
+            "      --out",
+            "  -i, --in",
+            "  -l",
+        ]
+    );
+}
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(delimeters = "=", default_parser = "StringParser")]
+struct MarkdownOpts {
+    #[opt(
+        names("-m", "--mode"),
+        value_desc = "<a|b>",
+        desc = "either a | b\nor nothing"
+    )]
+    mode: String,
+
+    #[flag(name = "-q")]
+    quiet: bool,
+}
+
+#[test]
+fn help_to_markdown() {
+    let table = MarkdownOpts::help().to_markdown();
+    let lines: Vec<&str> = table.lines().collect();
+    assert_eq!(lines[0], "| Option | Description |");
+    assert_eq!(lines[1], "| --- | --- |");
+    assert!(lines.contains(&"| `-m=<a\\|b>`<br>`--mode=<a\\|b>` | either a \\| b<br>or nothing |"));
+    assert!(lines.contains(&"| `-q` |  |"));
+}
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(delimeters = "=")]
+struct ThreadOpts {
+    #[opt(name = "-t", parser = "BoundedIntParser<1, 64>")]
+    threads: usize,
+
+    #[opt(name = "-n", parser = "BoundedIntParser<{ i64::MIN }, 0>")]
+    nice: i32,
+}
+
+#[test]
+fn bounded_int_parser() {
+    let parse = |argv: &[&str]| {
+        let mut opts = ThreadOpts::default();
+        opts.parse_argv(argv).map(|_| (opts.threads, opts.nice))
+    };
+    assert_eq!(parse(&["-t", "1", "-n", "-20"]), Ok((1, -20)));
+    assert_eq!(parse(&["-t=64"]), Ok((64, 0)));
+    assert_eq!(
+        parse(&["-t=0"]),
+        Err(ParseError::InvalidValue(
+            "value 0 out of range [1, 64]".to_string()
+        ))
+    );
+    assert_eq!(
+        parse(&["-t=65"]),
+        Err(ParseError::InvalidValue(
+            "value 65 out of range [1, 64]".to_string()
+        ))
+    );
+    assert_eq!(
+        parse(&["-n=5"]),
+        Err(ParseError::InvalidValue(
+            "value 5 out of range [-inf, 0]".to_string()
+        ))
+    );
+    assert_eq!(
+        parse(&["-t=x"]),
+        Err(ParseError::InvalidValue("Invalid value 'x'".to_string()))
+    );
+
+    let mut large = 0u64;
+    assert_eq!(
+        BoundedIntParser::<0>::parse(&mut large, "1_000_000"),
+        Ok(())
+    );
+    assert_eq!(large, 1_000_000);
+    for v in ["_100", "1__0", "100_", "-_1"].iter() {
+        assert_eq!(
+            BoundedIntParser::<0>::parse(&mut large, v),
+            Err(format!("Invalid value '{}'", v))
+        );
+    }
+
+    let mut unbounded = 0i64;
+    assert_eq!(BoundedIntParser::<1>::parse(&mut unbounded, "5"), Ok(()));
+    assert_eq!(
+        BoundedIntParser::<1>::parse(&mut unbounded, "-5"),
+        Err("value -5 out of range [1, inf]".to_string())
+    );
+}
+
+#[test]
+fn help_normalizes_whitespace() {
+    let help = Help {
+        overview: None,
+        usage: Some("prog\t[options]\r".to_string()),
+        delimeters: None,
+        options: vec![
+            OptionHelp {
+                names: vec!["-a".to_string()],
+                desc: Some("col\tumn".to_string()),
+                value_desc: None,
+                env: None,
+                metadata: HashMap::new(),
+            },
+            OptionHelp {
+                names: vec!["-b".to_string()],
+                desc: Some("first\r\nsecond\r\n".to_string()),
+                value_desc: None,
+                env: None,
+                metadata: HashMap::new(),
+            },
+        ],
+        name_columns: false,
+        width: DEFAULT_HELP_WIDTH,
+        color: HelpColor::Never,
+        epilog: None,
+    }
+    .to_string();
+    let lines: Vec<&str> = help.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "Usage: prog    [options]",
+            "",
+            "Options:",
+            "  -a                          col umn",
+            "  -b                          first",
+            "                              second",
+        ]
+    );
+}
+
+#[test]
+fn parse_slice() {
+    let argv: Vec<String> = ["-f", "-o", "x", "prog", "-f"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    let mut opts = Opts::default();
+    let pos = opts.parse_slice(&argv).unwrap();
+    assert_eq!(pos, 3);
+    assert_eq!(&argv[pos..], ["prog", "-f"]);
+    assert!(opts.flag);
+    assert_eq!(opts.opt, "x");
+}
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(delimeters = "=", default_parser = "StringParser")]
+struct EmptyOpts {
+    #[opt(name = "--name", reject_empty)]
+    name: String,
+
+    #[opt(name = "--suffix")]
+    suffix: String,
+}
+
+#[test]
+fn empty_values() {
+    let mut opts = EmptyOpts::default();
+    assert_eq!(
+        opts.parse_argv(["--name="]),
+        Err(ParseError::InvalidValue(
+            "Option '--name' requires a non-empty value".to_string()
+        ))
+    );
+
+    let mut opts = EmptyOpts {
+        suffix: "x".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(opts.parse_argv(["--suffix=", "--name=a"]), Ok(2));
+    assert_eq!(opts.suffix, "");
+    assert_eq!(opts.name, "a");
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn regex_parser() {
+    use spawner_opts::value_parser::{Pattern, RegexParser};
+
+    struct Identifier;
+    impl Pattern for Identifier {
+        const PATTERN: &'static str = "[a-z_][a-z0-9_]*";
+    }
+    struct Unclosed;
+    impl Pattern for Unclosed {
+        const PATTERN: &'static str = "[a-z";
+    }
+
+    let mut value = String::new();
+    assert_eq!(
+        RegexParser::<Identifier>::parse(&mut value, "max_len"),
+        Ok(())
+    );
+    assert_eq!(value, "max_len");
+    assert_eq!(
+        RegexParser::<Identifier>::parse(&mut value, "max len"),
+        Err("Invalid value 'max len'. The value must match '[a-z_][a-z0-9_]*'".to_string())
+    );
+    assert_eq!(value, "max_len");
+
+    let err = RegexParser::<Unclosed>::compile().unwrap_err();
+    assert!(err.starts_with("Invalid pattern '[a-z':"));
+    assert_eq!(RegexParser::<Unclosed>::parse(&mut value, "a"), Err(err));
+}
+
+#[cfg(feature = "glob")]
+#[test]
+fn glob_parser() {
+    use spawner_opts::value_parser::GlobParser;
+    use std::fs;
+    use std::path::PathBuf;
+
+    let dir = std::env::temp_dir().join(format!("spawner_opts_glob_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for name in ["a.txt", "b.txt", "c.log"] {
+        fs::write(dir.join(name), "").unwrap();
+    }
+    let pattern = |p: &str| dir.join(p).to_str().unwrap().to_string();
+
+    let mut paths: Vec<PathBuf> = Vec::new();
+    assert_eq!(<GlobParser>::parse(&mut paths, &pattern("*.txt")), Ok(()));
+    assert_eq!(paths, [dir.join("a.txt"), dir.join("b.txt")]);
+
+    let mut paths: Vec<PathBuf> = Vec::new();
+    assert_eq!(<GlobParser>::parse(&mut paths, &pattern("*.md")), Ok(()));
+    assert!(paths.is_empty());
+    assert_eq!(
+        GlobParser::<false>::parse(&mut paths, &pattern("*.md")),
+        Err(format!("No files match '{}'", pattern("*.md")))
+    );
+    assert!(<GlobParser>::parse(&mut paths, "[a").is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reset_between_parses() {
+    let mut opts = Opts::default();
+    opts.parse_argv(["-f", "-o", "x", "-l", "2"]).unwrap();
+    assert!(opts.flag);
+
+    opts.reset();
+    assert!(!opts.flag);
+    assert_eq!(opts.opt, "");
+    assert_eq!(opts.level, 0);
+
+    opts.parse_argv(["-i", "in"]).unwrap();
+    assert!(!opts.flag);
+    assert_eq!(opts.opt, "");
+    assert_eq!(opts.redirect, "in");
+    assert_eq!(opts.level, 0);
+}
+
+#[test]
+fn help_single_option() {
+    let help = Opts::help();
+    assert_eq!(help.option_by_name("--out").unwrap().names, ["--out"]);
+    assert_eq!(help.option_by_name("in").unwrap().names, ["-i", "--in"]);
+    assert!(help.option_by_name("out-file").is_none());
+
+    assert_eq!(
+        help.render_option("o").unwrap(),
+        format!("  -o{:26}an option\n", "")
+    );
+    assert!(help.render_option("-x").is_none());
+}
+
+#[test]
+fn help_write_to() {
+    let help = Opts::help();
+    let mut buf = Vec::new();
+    help.write_to(&mut buf).unwrap();
+    assert_eq!(String::from_utf8(buf).unwrap(), help.to_string());
+}
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(delimeters = "=", default_parser = "StringParser")]
+struct ConflictOpts {
+    #[flag(name = "--json", conflicts_with = "quiet")]
+    json: bool,
+
+    #[flag(names("-q", "--quiet"))]
+    quiet: bool,
+
+    #[opt(name = "--out", conflicts_with = "json")]
+    out: String,
+}
+
+#[test]
+fn conflicting_options() {
+    let parse = |argv: &[&str]| ConflictOpts::default().parse_argv(argv).map(|_| ());
+    assert_eq!(parse(&["--json"]), Ok(()));
+    assert_eq!(parse(&["--quiet", "--out=x"]), Ok(()));
+    assert_eq!(
+        parse(&["--quiet", "--json"]),
+        Err(ParseError::InvalidValue(
+            "Options '--json' and '-q' cannot be used together".to_string()
+        ))
+    );
+    assert_eq!(
+        parse(&["--json", "--out", "x"]),
+        Err(ParseError::InvalidValue(
+            "Options '--out' and '--json' cannot be used together".to_string()
+        ))
+    );
+}
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(delimeters = "=", default_parser = "StringParser")]
+struct RequiresOpts {
+    #[opt(name = "-p", requires = "user")]
+    password: String,
+
+    #[opt(name = "-u", requires = "host")]
+    user: String,
+
+    #[opt(name = "-h")]
+    host: String,
+}
+
+#[test]
+fn required_options() {
+    let parse = |argv: &[&str]| RequiresOpts::default().parse_argv(argv).map(|_| ());
+    assert_eq!(parse(&[]), Ok(()));
+    assert_eq!(parse(&["-h=x"]), Ok(()));
+    assert_eq!(parse(&["-p=1", "-u=a", "-h=x"]), Ok(()));
+    assert_eq!(
+        parse(&["-p=1"]),
+        Err(ParseError::InvalidValue(
+            "Option '-p' requires '-u'".to_string()
+        ))
+    );
+    assert_eq!(
+        parse(&["-p=1", "-u=a"]),
+        Err(ParseError::InvalidValue(
+            "Option '-u' requires '-h'".to_string()
+        ))
+    );
+}
+
+#[test]
+fn range_parser() {
+    let parse = |v: &str| {
+        let mut range = 0..=0;
+        RangeParser::parse(&mut range, v).map(|_| range)
+    };
+    assert_eq!(parse("1..10"), Ok(1..=10));
+    assert_eq!(parse("5"), Ok(5..=5));
+    assert_eq!(parse("3-7"), Ok(3..=7));
+    assert_eq!(parse("-3..-1"), Ok(-3..=-1));
+    assert_eq!(parse("-3--1"), Ok(-3..=-1));
+    assert_eq!(parse("-5"), Ok(-5..=-5));
+    assert_eq!(
+        parse("10..1").unwrap_err(),
+        "Invalid range '10..1'. The start must not be greater than the end"
+    );
+    assert!(parse("1..x").is_err());
+}
+
+#[test]
+fn exit_code_list_parser() {
+    let parse = |v: &str| {
+        let mut codes = Vec::new();
+        ExitCodeListParser::parse(&mut codes, v).map(|_| codes)
+    };
+    assert_eq!(parse("0,1,42"), Ok(vec![0, 1, 42]));
+    assert_eq!(parse("0-3"), Ok(vec![0, 1, 2, 3]));
+    assert_eq!(parse("42, 1..2,1"), Ok(vec![1, 2, 42]));
+    assert_eq!(parse("0,x").unwrap_err(), "Invalid exit code 'x' in '0,x'");
+    assert_eq!(
+        parse("0-99999999").unwrap_err(),
+        "Exit code range '0-99999999' in '0-99999999' is too large"
+    );
+
+    let mut codes = vec![5, 1];
+    ExitCodeListParser::parse(&mut codes, "3,1").unwrap();
+    assert_eq!(codes, [1, 3, 5]);
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Limit {
+    Unlimited,
+    Inherit,
+}
+
+impl Keyword for Limit {
+    fn from_keyword(v: &str) -> Option<Self> {
+        match v {
+            "unlimited" => Some(Limit::Unlimited),
+            "inherit" => Some(Limit::Inherit),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn array_parser() {
+    type SizeParser = ArrayParser<BoundedIntParser<1>>;
+    let parse = |v: &str| {
+        let mut size = [0u32; 2];
+        SizeParser::parse(&mut size, v).map(|_| size)
+    };
+    assert_eq!(parse("1024x768"), Ok([1024, 768]));
+    assert_eq!(
+        parse("1024").unwrap_err(),
+        "Expected 2 values separated by 'x' in '1024'"
+    );
+    assert_eq!(
+        parse("1x2x3").unwrap_err(),
+        "Expected 2 values separated by 'x' in '1x2x3'"
+    );
+    assert_eq!(parse("0x1").unwrap_err(), "value 0 out of range [1, inf]");
+
+    let mut rgb = [0i64; 3];
+    ArrayParser::<BoundedIntParser, ','>::parse(&mut rgb, "255,-1,0").unwrap();
+    assert_eq!(rgb, [255, -1, 0]);
+}
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(delimeters = "=", no_duplicates)]
+struct SizeOpts {
+    #[opt(name = "--size", parser = "ArrayParser<BoundedIntParser<1>>")]
+    size: [u32; 2],
+
+    #[opt(
+        name = "--dim",
+        parser = "ArrayParser<BoundedIntParser<1>, 'x', true>",
+        occurrences = 2
+    )]
+    dims: [u32; 2],
+}
+
+#[test]
+fn array_parser_occurrences() {
+    let mut opts = SizeOpts::default();
+    assert_eq!(
+        opts.parse_argv(["--size=1024x768", "--dim=640", "--dim=480"]),
+        Ok(3)
+    );
+    assert_eq!(opts.size, [1024, 768]);
+    assert_eq!(opts.dims, [640, 480]);
+
+    assert_eq!(SizeOpts::default().parse_argv(["--size=1x1"]), Ok(1));
+    assert_eq!(
+        SizeOpts::default().parse_argv(["--dim=640"]),
+        Err(ParseError::InvalidValue(
+            "Option '--dim' must be given 2 times, got 1".to_string()
+        ))
+    );
+    assert_eq!(
+        SizeOpts::default().parse_argv(["--dim=1", "--dim=2", "--dim=3"]),
+        Err(ParseError::InvalidValue(
+            "Option '--dim' must be given 2 times, got 3".to_string()
+        ))
+    );
+    assert_eq!(
+        SizeOpts::default().parse_argv(["--dim=640", "--dim=0"]),
+        Err(ParseError::InvalidValue(
+            "value 0 out of range [1, inf]".to_string()
+        ))
+    );
+}
+
+#[test]
+fn keyword_or_parser() {
+    type Parser = KeywordOrParser<BoundedIntParser<1, 64>>;
+    let parse = |v: &str| {
+        let mut value = NumberOr::<u32>::default();
+        Parser::parse(&mut value, v).map(|_| value)
+    };
+    assert_eq!(parse("auto"), Ok(KeywordOr::Keyword(Auto)));
+    assert_eq!(parse("8"), Ok(KeywordOr::Value(8)));
+    assert_eq!(parse("x").unwrap_err(), "Invalid value 'x'");
+    assert_eq!(parse("65").unwrap_err(), "value 65 out of range [1, 64]");
+
+    let mut value = KeywordOr::<Limit, i64>::Keyword(Limit::Inherit);
+    Parser::parse(&mut value, "unlimited").unwrap();
+    assert_eq!(value, KeywordOr::Keyword(Limit::Unlimited));
+    Parser::parse(&mut value, "2").unwrap();
+    assert_eq!(value, KeywordOr::Value(2));
+    assert!(Parser::parse(&mut value, "auto").is_err());
+    assert_eq!(value, KeywordOr::Value(2));
+}
+
+#[test]
+fn endpoint_parser() {
+    use std::net::SocketAddr;
+
+    let parse = |v: &str| {
+        let mut addr: Option<SocketAddr> = None;
+        EndpointParser::parse(&mut addr, v).map(|_| addr.unwrap())
+    };
+    assert_eq!(parse("10.0.0.1:6000"), Ok("10.0.0.1:6000".parse().unwrap()));
+    assert_eq!(parse("[::1]:6000"), Ok("[::1]:6000".parse().unwrap()));
+    assert_eq!(parse(":6000"), Ok("127.0.0.1:6000".parse().unwrap()));
+    assert_eq!(
+        parse("host:abc").unwrap_err(),
+        "Invalid port 'abc' in 'host:abc'"
+    );
+    assert_eq!(
+        parse("host:6000").unwrap_err(),
+        "Invalid IP address 'host' in 'host:6000'"
+    );
+    assert_eq!(
+        parse("::1:6000").unwrap_err(),
+        "Invalid IP address '::1' in '::1:6000'"
+    );
+    assert_eq!(parse("6000").unwrap_err(), "Missing port in '6000'");
+}
+
+#[test]
+fn command_line_parser() {
+    let parse = |v: &str| {
+        let mut argv = Vec::new();
+        CommandLineParser::parse(&mut argv, v).map(|_| argv)
+    };
+    assert_eq!(parse(r#"prog -a "x y""#).unwrap(), ["prog", "-a", "x y"]);
+    assert_eq!(parse(r"prog x\ y").unwrap(), ["prog", "x y"]);
+    assert_eq!(
+        parse(r#"  'a "b"' "c \"d\"" e''f "" "#).unwrap(),
+        ["a \"b\"", "c \"d\"", "ef", ""]
+    );
+    assert_eq!(
+        parse(r#"prog "x y"#).unwrap_err(),
+        r#"Unterminated quote in 'prog "x y'"#
+    );
+}
+
+#[test]
+fn optional_value() {
+    let mut opts = ColorOpts::default();
+    assert_eq!(opts.parse_argv(["--color"]), Ok(1));
+    assert_eq!(opts.color, "always");
+
+    let mut opts = ColorOpts::default();
+    assert_eq!(opts.parse_argv(["--color=never"]), Ok(1));
+    assert_eq!(opts.color, "never");
+
+    let mut opts = ColorOpts::default();
+    assert_eq!(opts.parse_argv(["--color", "never"]), Ok(1));
+    assert_eq!(opts.color, "always");
+}
+
+#[test]
+fn unknown_opts() {
+    let unknown = |argv: &[&str]| {
+        let mut parser = Parser::new(argv, "=");
+        parser.flag(&["-f"]).opt(&["-o"]);
+        let pos = parser.parse();
+        (pos, parser.unknown_opts().to_vec())
+    };
+    assert_eq!(unknown(&["-f", "-o", "x", "prog", "-z"]), (3, vec![]));
+    assert_eq!(
+        unknown(&["-f", "--zz=1", "prog", "-y"]),
+        (1, vec!["--zz=1".to_string()])
+    );
+    assert_eq!(unknown(&["-f=1", "prog"]), (0, vec!["-f=1".to_string()]));
+    assert_eq!(unknown(&["-", "prog"]), (0, vec![]));
+}
+
+#[test]
+fn escaped_delimeter() {
+    let expr = |argv: &[&str]| {
+        let mut parser = Parser::new(argv, "=");
+        parser.opt(&["--expr"]);
+        let pos = parser.parse();
+        (pos, parser.get_opt("--expr").unwrap().clone())
+    };
+    assert_eq!(expr(&["--expr=a\\=b"]), (1, vec!["a=b".to_string()]));
+    assert_eq!(expr(&["--expr=a=b"]), (1, vec!["a=b".to_string()]));
+    assert_eq!(expr(&["--expr", "a\\=b"]), (2, vec!["a=b".to_string()]));
+    assert_eq!(expr(&["--expr=a\\b"]), (1, vec!["a\\b".to_string()]));
+    assert_eq!(expr(&["--expr\\=a", "prog"]), (0, vec![]));
+}
+
+#[test]
+fn longest_name_match() {
+    let parse = |argv: &[&str]| {
+        let mut parser = Parser::new(argv, "=");
+        parser
+            .flag(&["-v"])
+            .opt(&["-value"])
+            .opt(&["-l"])
+            .opt(&["-level"]);
+        let pos = parser.parse();
+        (
+            pos,
+            parser.has_flag("-v"),
+            parser.get_opt("-value").unwrap().clone(),
+            parser.get_opt("-l").unwrap().clone(),
+            parser.get_opt("-level").unwrap().clone(),
+        )
+    };
+    let none = Vec::<String>::new;
+    assert_eq!(parse(&["-v"]), (1, true, none(), none(), none()));
+    assert_eq!(
+        parse(&["-value=1", "-v"]),
+        (2, true, vec!["1".to_string()], none(), none())
+    );
+    assert_eq!(
+        parse(&["-value", "1"]),
+        (2, false, vec!["1".to_string()], none(), none())
+    );
+    assert_eq!(
+        parse(&["-value2"]),
+        (1, false, vec!["2".to_string()], none(), none())
+    );
+    assert_eq!(
+        parse(&["-level3", "-lx"]),
+        (
+            2,
+            false,
+            none(),
+            vec!["x".to_string()],
+            vec!["3".to_string()]
+        )
+    );
+    assert_eq!(
+        parse(&["-valu", "prog"]),
+        (0, false, none(), none(), none())
+    );
+}
+
+#[test]
+fn empty_tokens() {
+    let parse = |argv: &[&str]| {
+        let mut parser = Parser::new(argv, "=");
+        parser.flag(&["-f"]).opt(&["-o"]).flag(&[""]);
+        let split = parser.parse_split();
+        (
+            split.options_end,
+            split.args_start,
+            parser.get_opt("-o").unwrap().clone(),
+            parser.unknown_opts().to_vec(),
+        )
+    };
+    let none = Vec::<String>::new;
+    assert_eq!(parse(&["", "-f"]), (0, 0, none(), none()));
+    assert_eq!(parse(&["-f", "", "-o", "x"]), (1, 1, none(), none()));
+    assert_eq!(
+        parse(&["-o", "", "-f", "prog"]),
+        (3, 3, vec![String::new()], none())
+    );
+    assert_eq!(parse(&["-o="]), (1, 1, vec![String::new()], none()));
+    assert_eq!(parse(&["-f", "--", ""]), (1, 2, none(), none()));
+    assert_eq!(parse(&["-f", "=x"]), (1, 1, none(), none()));
+
+    let mut opts = ForwardOpts::default();
+    assert_eq!(opts.parse_argv(["--extra", "", "-v", "prog"]), Ok(3));
+    assert_eq!(opts.forwarded, ["--extra", ""]);
+    assert!(opts.verbose);
+
+    let mut opts = ForwardOpts::default();
+    assert_eq!(opts.parse_argv(["-v", "", "--out=x"]), Ok(1));
+    assert!(opts.out.is_empty());
+}
+
+#[test]
+fn parse_split_at_double_dash() {
+    let split = |argv: &[&str]| {
+        let mut parser = Parser::new(argv, "=");
+        parser.opt(&["-d"]);
+        parser.parse_split()
+    };
+    assert_eq!(
+        split(&["-d", "2", "--", "prog"]),
+        ParseSplit {
+            options_end: 2,
+            args_start: 3,
+        }
+    );
+    assert_eq!(
+        split(&["-d", "2", "prog", "--"]),
+        ParseSplit {
+            options_end: 2,
+            args_start: 2,
+        }
+    );
+
+    let mut parser = Parser::new(&["-d", "2", "--", "prog"], "=");
+    parser.opt(&["-d"]);
+    assert_eq!(parser.parse(), 2);
+    assert!(parser.unknown_opts().is_empty());
+
+    let argv = ["-o", "x", "--", "prog"];
+    let mut opts = Opts::default();
+    assert_eq!(opts.parse_argv(argv), Ok(3));
+    assert_eq!(&argv[3..], ["prog"]);
+}
+
+#[test]
+fn doc_comment_desc() {
+    let help = DocOpts::help();
+    assert_eq!(
+        help.options[0].desc.as_deref(),
+        Some("Input file.\nUse - for stdin.")
+    );
+    assert_eq!(help.options[1].desc.as_deref(), Some("output file"));
+}
+
+#[test]
+fn auto_version() {
+    let version = Err(ParseError::VersionRequested("1.2.3".to_string()));
+    assert_eq!(
+        VersionOpts::default().parse_argv(["--version", "-l=x"]),
+        version
+    );
+    assert_eq!(VersionOpts::default().parse_argv(["-l=x", "-V"]), version);
+    assert_eq!(VersionOpts::default().parse_argv(["-l=1"]), Ok(1));
+    assert_eq!(
+        PkgVersionOpts::default().parse_argv(["--version"]),
+        Err(ParseError::VersionRequested(
+            env!("CARGO_PKG_VERSION").to_string()
+        ))
+    );
+}
+
+#[test]
+fn parse_outcomes() {
+    let outcome = |argv: &[&str]| OutcomeOpts::default().parse_outcome(argv);
+    assert!(matches!(
+        outcome(&["-l=1", "prog"]),
+        Ok(ParseOutcome::Consumed(1))
+    ));
+    match outcome(&["-l=x", "-h"]) {
+        Ok(ParseOutcome::Help(help)) => assert_eq!(help.options[0].names, ["-l"]),
+        _ => panic!("expected help"),
+    }
+    assert!(matches!(outcome(&["--help"]), Ok(ParseOutcome::Help(_))));
+    match outcome(&["--version"]) {
+        Ok(ParseOutcome::Version(v)) => assert_eq!(v, "1.2.3"),
+        _ => panic!("expected version"),
+    }
+    assert!(matches!(
+        outcome(&["-l=x"]),
+        Err(ParseError::InvalidValue(_))
+    ));
+    assert_eq!(
+        OutcomeOpts::default().parse_argv(["--help"]),
+        Err(ParseError::HelpRequested)
+    );
+}
+
+#[test]
+fn quiet_parse() {
+    let mut opts = QuietOpts::default();
+    assert_eq!(opts.parse_argv(["-h"]), Err(ParseError::HelpRequested));
+    assert!(!opts.human);
+
+    assert_eq!(opts.parse_quiet(["-l=2", "-h", "prog"]), Ok(2));
+    assert!(opts.human);
+    assert_eq!(opts.level, 2);
+
+    let mut opts = QuietOpts::default();
+    assert_eq!(opts.parse_quiet(["--help", "-l=2"]), Ok(0));
+    assert_eq!(opts.parse_quiet(["-l=1", "--version"]), Ok(1));
+    assert_eq!(opts.level, 1);
+}
+
+#[test]
+fn negatable_flags() {
+    let parse = |argv: &[&str]| {
+        let mut opts = NegatableOpts {
+            verbose: true,
+            ..Default::default()
+        };
+        opts.parse_argv(argv).map(|_| opts.verbose)
+    };
+    assert_eq!(parse(&[]), Ok(true));
+    assert_eq!(parse(&["--verbose"]), Ok(true));
+    assert_eq!(parse(&["--no-verbose"]), Ok(false));
+    assert_eq!(parse(&["--verbose", "--no-verbose"]), Ok(false));
+    assert_eq!(parse(&["--no-verbose", "-v"]), Ok(true));
+
+    let mut opts = NegatableOpts::default();
+    assert_eq!(opts.parse_argv(["--no-quiet"]), Ok(0));
+    assert_eq!(
+        NegatableOpts::option_names(),
+        ["-v", "--verbose", "--no-verbose", "--quiet"]
+    );
+}
+
+#[test]
+fn flag_presence_value() {
+    let parse = |default: bool, argv: &[&str]| {
+        let mut opts = PresenceOpts {
+            color: default,
+            styled: default,
+        };
+        opts.parse_argv(argv).map(|_| (opts.color, opts.styled))
+    };
+    for &default in &[false, true] {
+        assert_eq!(parse(default, &[]), Ok((default, default)));
+        assert_eq!(parse(default, &["--color"]), Ok((true, default)));
+        assert_eq!(parse(default, &["--no-color"]), Ok((false, default)));
+        assert_eq!(parse(default, &["--plain"]), Ok((default, false)));
+        assert_eq!(parse(default, &["--no-plain"]), Ok((default, true)));
+    }
+    assert_eq!(
+        parse(true, &["--plain", "--no-plain", "--plain"]),
+        Ok((true, false))
+    );
+}
+
+struct ListParser;
+
+impl OptionValueParser<Vec<String>> for ListParser {
+    fn parse(opt: &mut Vec<String>, v: &str) -> Result<(), String> {
+        opt.push(v.to_string());
+        Ok(())
+    }
+}
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(delimeters = "=", default_parser = "ListParser")]
+struct ListOpts {
+    #[opt(names("-i", "--include"))]
+    include: Vec<String>,
+
+    #[flag(name = "-v")]
+    verbose: bool,
+}
+
+#[test]
+fn bounded_occurrences() {
+    let mut opts = ListOpts::default();
+    assert_eq!(opts.parse_bounded(["-i=a", "-i=b", "-v", "prog"], 2), Ok(3));
+    assert_eq!(opts.include, ["a", "b"]);
+
+    let argv: Vec<String> = (0..1000).map(|i| format!("--include={}", i)).collect();
+    let mut opts = ListOpts::default();
+    assert_eq!(
+        opts.parse_bounded(&argv, 2),
+        Err(ParseError::InvalidValue(
+            "Option '--include' is given more than 2 times".to_string()
+        ))
+    );
+    assert!(opts.include.is_empty());
+
+    let mut opts = ListOpts::default();
+    assert_eq!(
+        opts.parse_bounded(["-v", "-v"], 1),
+        Err(ParseError::InvalidValue(
+            "Option '-v' is given more than 1 times".to_string()
+        ))
+    );
+}
+
+#[test]
+fn help_wraps_usage() {
+    let usage = (0..12)
+        .map(|i| format!("word{:05}", i))
+        .collect::<Vec<_>>()
+        .join(" ");
+    assert_eq!(usage.len(), 119);
+    let mut help = Help {
+        overview: None,
+        usage: Some(usage),
+        delimeters: None,
+        options: Vec::new(),
+        name_columns: false,
+        width: 40,
+        color: HelpColor::Never,
+        epilog: None,
+    };
+    assert_eq!(
+        help.to_string(),
+        "Usage: word00000 word00001 word00002\n       \
+         word00003 word00004 word00005\n       \
+         word00006 word00007 word00008\n       \
+         word00009 word00010 word00011\n\n"
+    );
+
+    help.width = DEFAULT_HELP_WIDTH;
+    let text = help.to_string();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines
+        .iter()
+        .all(|line| line.chars().count() <= DEFAULT_HELP_WIDTH));
+    assert!(lines[1].starts_with("       word0000"));
+}
+
+#[test]
+fn large_help_output() {
+    let help = Help {
+        overview: None,
+        usage: None,
+        delimeters: Some("=".to_string()),
+        options: (0..1000)
+            .map(|i| OptionHelp {
+                names: vec![format!("-o{}", i), format!("--opt{}", i)],
+                desc: Some(format!("option {}", i)),
+                value_desc: Some("<n>".to_string()),
+                env: None,
+                metadata: HashMap::new(),
+            })
+            .collect(),
+        name_columns: true,
+        width: DEFAULT_HELP_WIDTH,
+        color: HelpColor::Never,
+        epilog: None,
+    };
+    let text = help.to_string();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 1001);
+    assert_eq!(lines[1], "  -o0=<n>  , --opt0=<n>       option 0");
+    assert_eq!(lines[1000], "  -o999=<n>, --opt999=<n>     option 999");
+
+    let mut written = Vec::new();
+    help.write_to(&mut written).unwrap();
+    assert_eq!(written, text.as_bytes());
+}
+
+#[test]
+fn multibyte_help_alignment() {
+    let opt = |names: &[&str], desc: &str| OptionHelp {
+        names: names.iter().map(|n| n.to_string()).collect(),
+        desc: Some(desc.to_string()),
+        value_desc: None,
+        env: None,
+        metadata: HashMap::new(),
+    };
+    let mut help = Help {
+        overview: None,
+        usage: None,
+        delimeters: None,
+        options: vec![
+            opt(&["--größe"], "size"),
+            opt(&["-ü", "--über"], "over"),
+            opt(&["-o", "--over"], "over"),
+        ],
+        name_columns: false,
+        width: DEFAULT_HELP_WIDTH,
+        color: HelpColor::Never,
+        epilog: None,
+    };
+    // Descriptions start at the same character column regardless of the name's byte length.
+    let desc_column = |line: &str| line.chars().count() - 4;
+
+    let text = help.to_string();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines[1], "  --größe                     size");
+    assert_eq!(lines[2], "  -ü, --über                  over");
+    assert!(lines[1..].iter().all(|line| desc_column(line) == 30));
+
+    help.name_columns = true;
+    let text = help.to_string();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines[1], "      --größe                 size");
+    assert_eq!(lines[2], "  -ü, --über                  over");
+    assert!(lines[1..].iter().all(|line| desc_column(line) == 30));
+}
+
+#[test]
+fn colored_help() {
+    let mut help = Opts::help();
+    let plain = help.to_string();
+    assert!(!plain.contains('\x1b'));
+
+    help.color = HelpColor::Always;
+    let colored = help.to_string();
+    assert!(colored.contains("  \x1b[1m-f, --flag\x1b[0m"));
+    assert_eq!(colored.replace("\x1b[1m", "").replace("\x1b[0m", ""), plain);
+    assert!(help.render_option("-f").unwrap().contains("\x1b[1m"));
+
+    help.color = HelpColor::Never;
+    assert_eq!(help.to_string(), plain);
+}
+
+#[test]
+fn max_name_width() {
+    let opt = |names: &[&str], value_desc: Option<&str>| OptionHelp {
+        names: names.iter().map(|n| n.to_string()).collect(),
+        desc: None,
+        value_desc: value_desc.map(str::to_string),
+        env: None,
+        metadata: HashMap::new(),
+    };
+    let mut help = Help {
+        overview: None,
+        usage: None,
+        delimeters: Some("=".to_string()),
+        options: vec![
+            opt(&["-v"], None),
+            opt(&["-o", "--output"], Some("<file>")),
+            opt(&["--verbose"], None),
+        ],
+        name_columns: false,
+        width: DEFAULT_HELP_WIDTH,
+        color: HelpColor::Never,
+        epilog: None,
+    };
+    // "-o=<file>, --output=<file>"
+    assert_eq!(help.max_name_width('='), 26);
+
+    help.name_columns = true;
+    // "-o=<file>, --output=<file>" as well, short names are padded to "-o=<file>".
+    assert_eq!(help.max_name_width('='), 26);
+    help.options.push(opt(&["-x", "-y"], Some("<n>")));
+    // The short names column widens to "-x=<n>, -y=<n>", followed by ", --output=<file>".
+    assert_eq!(help.max_name_width('='), 31);
+
+    help.options.clear();
+    assert_eq!(help.max_name_width('='), 0);
+}
+
+#[test]
+fn parse_from_offset() {
+    let argv = ["prog", "run", "-o", "x", "--", "child", "-o"];
+    let mut opts = Opts::default();
+    assert_eq!(opts.parse_from(argv, 2), Ok(5));
+    assert_eq!(opts.opt, "x");
+    assert_eq!(argv[5..], ["child", "-o"]);
+
+    let argv = ["prog", "run", "-o", "x", "child"];
+    let mut opts = Opts::default();
+    assert_eq!(opts.parse_from(argv, 2), Ok(4));
+    assert_eq!(Opts::default().parse_from(argv, 4), Ok(4));
+
+    // Rejected values tell the index of their token in the whole `argv`.
+    let argv = ["prog", "run", "-l=extreme"];
+    assert_eq!(
+        Opts::default().parse_from(argv, 2),
+        Err(ParseError::InvalidToken(
+            2,
+            "Invalid value 'extreme'".to_string()
+        ))
+    );
+    let argv = ["prog", "run", "-f", "-o", "x", "-l", "extreme"];
+    let err = Opts::default().parse_from(argv, 2).unwrap_err();
+    assert_eq!(
+        err,
+        ParseError::InvalidToken(6, "Invalid value 'extreme'".to_string())
+    );
+    assert_eq!(err.to_string(), "Invalid value 'extreme' (argument 6)");
+    assert_eq!(
+        Opts::default().parse_argv(&argv[2..]),
+        Err(ParseError::InvalidValue(
+            "Invalid value 'extreme'".to_string()
+        ))
+    );
+}
+
+#[test]
+fn config_entries() {
+    let mut opts = Opts::default();
+    let config = [("flag", "true"), ("opt", "from config"), ("level", "high")];
+    let mut warnings = Vec::new();
+    assert_eq!(
+        opts.parse_config_with_warnings(config, &mut warnings),
+        Ok(())
+    );
+    assert!(opts.flag);
+    assert_eq!(opts.opt, "from config");
+    assert_eq!(opts.level, 2);
+    assert_eq!(warnings.len(), 1);
+
+    // The command line is parsed last and overrides the config.
+    assert_eq!(opts.parse_argv(["-o=from argv"]), Ok(1));
+    assert_eq!(opts.opt, "from argv");
+    assert_eq!(opts.level, 2);
+
+    assert_eq!(
+        opts.parse_config([("flag", "yes")]),
+        Err(ParseError::InvalidValue(
+            "Invalid value 'yes' for config key 'flag', expected a boolean".to_string()
+        ))
+    );
+    assert_eq!(
+        opts.parse_config([("-o", "x")]),
+        Err(ParseError::InvalidValue(
+            "Unknown config key '-o'".to_string()
+        ))
+    );
+
+    let mut list = ListOpts::default();
+    let config = vec![("include".to_string(), "a"), ("include".to_string(), "b")];
+    assert_eq!(list.parse_config(config), Ok(()));
+    assert_eq!(list.include, ["a", "b"]);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn config_value() {
+    let config =
+        serde_json::json!({"flag": true, "opt": "from config", "level": 3, "redirect": null});
+    let mut opts = Opts::from_config(&config).unwrap();
+    assert!(opts.flag);
+    assert_eq!(opts.opt, "from config");
+    assert_eq!(opts.level, 3);
+    assert_eq!(opts.redirect, "");
+
+    // The command line is parsed last and overrides the config.
+    assert_eq!(opts.parse_argv(["-l=1"]), Ok(1));
+    assert_eq!(opts.level, 1);
+
+    let mut warnings = Vec::new();
+    let config = serde_json::json!({"level": "high"});
+    assert_eq!(opts.parse_config_value(&config, &mut warnings), Ok(()));
+    assert_eq!(opts.level, 2);
+    assert_eq!(warnings.len(), 1);
+
+    let list = ListOpts::from_config(&serde_json::json!({"include": ["a", "b"]})).unwrap();
+    assert_eq!(list.include, ["a", "b"]);
+
+    assert_eq!(
+        Opts::from_config(&serde_json::json!(["-f"])).err(),
+        Some(ParseError::InvalidValue(
+            "Config must be an object".to_string()
+        ))
+    );
+    assert_eq!(
+        Opts::from_config(&serde_json::json!({"opt": {"x": 1}})).err(),
+        Some(ParseError::InvalidValue(
+            "Invalid value for config key 'opt', expected a string, a number or a boolean"
+                .to_string()
+        ))
+    );
+}
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(delimeters = "=", default_parser = "StringParser")]
+struct RestOpts {
+    #[flag(name = "-v")]
+    verbose: bool,
+
+    #[opt(names("-c", "--command"), rest)]
+    command: Vec<String>,
+}
+
+#[test]
+fn rest_of_command_line() {
+    let mut opts = RestOpts::default();
+    let argv = ["-v", "--command", "prog", "-v", "--", "--command"];
+    assert_eq!(opts.parse_argv(argv), Ok(6));
+    assert!(opts.verbose);
+    assert_eq!(opts.command, ["prog", "-v", "--", "--command"]);
+
+    let mut opts = RestOpts::default();
+    assert_eq!(opts.parse_argv(["-c=prog", "a", "b"]), Ok(3));
+    assert!(!opts.verbose);
+    assert_eq!(opts.command, ["prog", "a", "b"]);
+
+    // Without anything to capture the option is left unparsed.
+    let mut opts = RestOpts::default();
+    assert_eq!(opts.parse_argv(["-v", "--command"]), Ok(1));
+    assert!(opts.command.is_empty());
+}
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(
+    delimeters = "=",
+    default_parser = "StringParser",
+    forward_unknown = "forwarded"
+)]
+struct ForwardOpts {
+    #[flag(name = "-v")]
+    verbose: bool,
+
+    #[opt(name = "--out")]
+    out: String,
+
+    forwarded: Vec<String>,
+}
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(
+    delimeters = "=",
+    default_parser = "MegabytesParser",
+    raw_values = "raw"
+)]
+struct RawOpts {
+    #[opt(names("-m", "--memory"))]
+    memory: u64,
+
+    #[opt(name = "-l", parser = "LevelParser")]
+    level: u32,
+
+    raw: std::collections::HashMap<String, Vec<String>>,
+}
+
+struct MegabytesParser;
+
+impl OptionValueParser<u64> for MegabytesParser {
+    fn parse(opt: &mut u64, v: &str) -> Result<(), String> {
+        let mb = v.strip_suffix('M').unwrap_or(v);
+        *opt = mb
+            .parse::<u64>()
+            .map_err(|_| format!("Invalid value '{}'", v))?
+            << 20;
+        Ok(())
+    }
+}
+
+#[test]
+fn raw_values() {
+    let mut opts = RawOpts::default();
+    assert_eq!(
+        opts.parse_argv(["-m", "256M", "-l=high", "--memory=1"]),
+        Ok(4)
+    );
+    assert_eq!(opts.memory, 1 << 20);
+    assert_eq!(opts.raw["memory"], ["256M", "1"]);
+    assert_eq!(opts.raw["level"], ["high"]);
+
+    let mut opts = RawOpts::default();
+    assert!(opts.parse_argv(["-m", "x"]).is_err());
+    assert!(opts.raw.is_empty());
+}
+
+#[test]
+fn attached_short_option_value() {
+    for argv in [&["-m256M"][..], &["-m", "256M"], &["-m=256M"]] {
+        let mut opts = RawOpts::default();
+        assert_eq!(opts.parse_argv(argv), Ok(argv.len()));
+        assert_eq!(opts.memory, 256 << 20);
+    }
+
+    let mut opts = Opts::default();
+    assert_eq!(opts.parse_argv(["-ox", "-fy", "prog"]), Ok(1));
+    assert_eq!(opts.opt, "x");
+    assert!(!opts.flag);
+}
+
+#[test]
+fn forward_unknown_options() {
+    let argv = [
+        "-v",
+        "--child-only-flag",
+        "value",
+        "--out=x",
+        "--color=always",
+        "--child-switch",
+        "--",
+        "prog",
+        "--arg",
+    ];
+    let mut opts = ForwardOpts::default();
+    assert_eq!(opts.parse_argv(argv), Ok(7));
+    assert!(opts.verbose);
+    assert_eq!(opts.out, "x");
+    assert_eq!(
+        opts.forwarded,
+        [
+            "--child-only-flag",
+            "value",
+            "--color=always",
+            "--child-switch"
+        ]
+    );
+
+    // Short options are not forwarded, parsing stops at them as usual.
+    let mut opts = ForwardOpts::default();
+    assert_eq!(opts.parse_argv(["--extra", "-x", "prog"]), Ok(1));
+    assert_eq!(opts.forwarded, ["--extra"]);
+}
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(delimeters = "=", default_parser = "StringParser")]
+struct BareOpts {
+    #[opt(names("sr", "-sr"))]
+    report: String,
+
+    #[flag(name = "hr")]
+    hide_report: bool,
+}
+
+#[test]
+fn bare_word_names() {
+    let mut opts = BareOpts::default();
+    assert_eq!(opts.parse_argv(["sr=file", "hr", "prog"]), Ok(2));
+    assert_eq!(opts.report, "file");
+    assert!(opts.hide_report);
+
+    let mut opts = BareOpts::default();
+    assert_eq!(opts.parse_argv(["-sr", "file", "prog"]), Ok(2));
+    assert_eq!(opts.report, "file");
+
+    let mut opts = BareOpts::default();
+    assert_eq!(opts.parse_argv(["sr", "arg"]), Ok(0));
+    assert_eq!(opts.parse_argv(["prog", "sr=file"]), Ok(0));
+    assert_eq!(opts.parse_argv(["hrx"]), Ok(0));
+    assert!(opts.report.is_empty());
+    assert!(!opts.hide_report);
+}
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(
+    delimeters = "=",
+    default_parser = "StringParser",
+    usage = "prog [options]",
+    epilog = "Examples:\n  prog --out=log.txt\nReport bugs at the issue tracker of the project, \
+              please include the output of prog --version."
+)]
+struct EpilogOpts {
+    #[flag(name = "-v", desc = "verbose output")]
+    verbose: bool,
+
+    #[opt(name = "--out", desc = "output file")]
+    out: String,
+}
+
+#[test]
+fn help_epilog() {
+    let mut help = EpilogOpts::help();
+    let text = help.to_string();
+    let last_option = text.find("output file").unwrap();
+    let epilog = text.find("Examples:").unwrap();
+    assert!(epilog > last_option);
+    assert!(text.ends_with(
+        "output file\n\nExamples:\n  prog --out=log.txt\nReport bugs at the issue tracker of the \
+         project, please include the output of\nprog --version.\n"
+    ));
+
+    help.options.clear();
+    assert_eq!(
+        help.to_string(),
+        "Usage: prog [options]\n\nExamples:\n  prog --out=log.txt\nReport bugs at the issue \
+         tracker of the project, please include the output of\nprog --version.\n"
+    );
+}
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(delimeters = "=", default_parser = "LevelParser")]
+struct MetaOpts {
+    #[opt(
+        name = "--timeout",
+        desc = "time limit",
+        meta(widget = "spinbox", unit = "ms"),
+        meta(step = "100")
+    )]
+    timeout: u32,
+
+    #[flag(name = "-v", meta(widget = "checkbox"))]
+    verbose: bool,
+
+    #[opt(name = "-l")]
+    level: u32,
+}
+
+#[test]
+fn option_metadata() {
+    let help = MetaOpts::help();
+    let meta = |name: &str| help.option_by_name(name).unwrap().metadata.clone();
+    let expected = |pairs: &[(&str, &str)]| {
+        pairs
+            .iter()
+            .map(|&(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>()
+    };
+    assert_eq!(
+        meta("--timeout"),
+        expected(&[("widget", "spinbox"), ("unit", "ms"), ("step", "100")])
+    );
+    assert_eq!(meta("-v"), expected(&[("widget", "checkbox")]));
+    assert!(meta("-l").is_empty());
+
+    let text = help.to_string();
+    assert!(text.contains("time limit"));
+    assert!(!text.contains("spinbox") && !text.contains("checkbox"));
+}
+
+struct SecondsParser;
+
+impl OptionValueParser<f64> for SecondsParser {
+    fn parse(opt: &mut f64, val: &str) -> Result<(), String> {
+        *opt = val
+            .parse()
+            .map_err(|_| format!("Invalid number of seconds '{}'", val))?;
+        Ok(())
+    }
+}
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(
+    delimeters = "=",
+    default_parser = "SecondsParser",
+    validator = "check_feed"
+)]
+struct FeedOpts {
+    #[opt(name = "-v")]
+    interval: f64,
+
+    #[flag(name = "-q", requires = "interval")]
+    quiet: bool,
+
+    #[opt(name = "--cmd", rest)]
+    command: Vec<String>,
+}
+
+fn check_feed(opts: &FeedOpts) -> Result<(), String> {
+    if opts.interval < 0.0 {
+        return Err("Negative interval".to_string());
+    }
+    Ok(())
+}
+
+#[test]
+fn incremental_parse() {
+    let mut state = ParserState::<FeedOpts>::new();
+    assert_eq!(state.feed("-v"), Ok(None));
+    assert_eq!(state.options().interval, 0.0);
+    assert_eq!(state.feed("3.0"), Ok(None));
+    assert_eq!(state.options().interval, 3.0);
+    assert_eq!(state.feed("prog"), Ok(Some(Done { args_start: 2 })));
+    assert_eq!(state.finish(), Ok(Done { args_start: 2 }));
+    assert_eq!(state.into_options().interval, 3.0);
+
+    // Checks that need the whole command line wait for `finish`.
+    let mut state = ParserState::<FeedOpts>::new();
+    assert_eq!(state.feed("-q"), Ok(None));
+    assert!(state.options().quiet);
+    assert!(state.finish().is_err());
+    assert_eq!(state.feed("-v=-1"), Ok(None));
+    assert_eq!(
+        state.finish(),
+        Err(ParseError::InvalidValue("Negative interval".to_string()))
+    );
+
+    let mut state = ParserState::<FeedOpts>::new();
+    assert!(state.feed("-v=x").is_err());
+
+    let mut state = ParserState::<FeedOpts>::new();
+    assert_eq!(state.feed("--"), Ok(None));
+    assert_eq!(state.feed("-v"), Ok(Some(Done { args_start: 1 })));
+
+    let mut state = ParserState::<FeedOpts>::new();
+    assert_eq!(state.feed("--cmd"), Ok(None));
+    assert_eq!(state.feed("prog"), Ok(None));
+    assert_eq!(state.feed("-v"), Ok(None));
+    assert_eq!(state.options().command, ["prog", "-v"]);
+
+    // An option still missing its value ends the options as in `parse_argv`.
+    let mut state = ParserState::<FeedOpts>::new();
+    assert_eq!(state.feed("-v"), Ok(None));
+    assert_eq!(state.finish(), Ok(Done { args_start: 0 }));
+}
+
+#[derive(CmdLineOptions, Default)]
+#[optcont(delimeters = "=", default_parser = "LevelParser", no_duplicates)]
+struct UniqueOpts {
+    #[opt(names("-v", "--level"))]
+    level: u32,
+
+    #[opt(name = "-i", parser = "ListParser", multiple)]
+    include: Vec<String>,
+
+    #[flag(name = "-q")]
+    quiet: bool,
+}
+
+#[test]
+fn reject_duplicate_options() {
+    let mut opts = UniqueOpts::default();
+    assert_eq!(opts.parse_argv(["-v", "1", "-q", "-q", "prog"]), Ok(4));
+    assert_eq!(opts.level, 1);
+
+    let mut opts = UniqueOpts::default();
+    assert_eq!(
+        opts.parse_argv(["-v", "1", "--level=2", "prog"]),
+        Err(ParseError::InvalidValue(
+            "Option '-v' may be given only once".to_string()
+        ))
+    );
+
+    let mut opts = UniqueOpts::default();
+    assert_eq!(opts.parse_argv(["-i", "a", "-i", "b"]), Ok(4));
+    assert_eq!(opts.include, ["a", "b"]);
+}
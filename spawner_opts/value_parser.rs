@@ -0,0 +1,446 @@
+//! Reusable `OptionValueParser` implementations.
+
+use crate::OptionValueParser;
+
+use std::borrow::Cow;
+#[cfg(feature = "regex")]
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::marker::PhantomData;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::ops::RangeInclusive;
+#[cfg(feature = "glob")]
+use std::path::PathBuf;
+#[cfg(feature = "regex")]
+use std::sync::{Mutex, OnceLock};
+
+#[cfg(feature = "regex")]
+use regex::Regex;
+
+/// Parses inclusive ranges written as `a..b` or `a-b`, a single value `a` means `a..=a`.
+///
+/// `..` is the preferred separator since it never clashes with negative numbers. A `-` is
+/// treated as a separator only if it follows a digit, so `-3--1` is `-3..=-1` whereas `-5` is
+/// a single value.
+pub struct RangeParser;
+
+/// Splits a shell-like command line into argv.
+///
+/// Arguments are separated by whitespace. Single quotes preserve everything literally, double
+/// quotes allow `\"` and `\\` escapes, and outside of quotes a backslash escapes any character.
+pub struct CommandLineParser;
+
+/// Parses a comma-separated list of exit codes such as `0,1,42` or `0-3,255`, where every item is
+/// either a single code or an inclusive range written like in [`RangeParser`]. Every occurrence
+/// of the option adds its codes to the list, which is kept sorted and free of duplicates.
+pub struct ExitCodeListParser;
+
+/// Parses either one of the keywords of `K` or a value that is parsed by `P`, e.g.
+/// `parser = "KeywordOrParser<BoundedIntParser<1>>"` for a `NumberOr<u32>` field accepts both
+/// `auto` and `4`. Keywords are tried first, so they take precedence over values.
+pub struct KeywordOrParser<P>(PhantomData<P>);
+
+/// A value given either as a keyword or as a regular value, see `KeywordOrParser`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum KeywordOr<K, T> {
+    Keyword(K),
+    Value(T),
+}
+
+/// A number or the word `auto`.
+pub type NumberOr<T> = KeywordOr<Auto, T>;
+
+/// The keywords accepted by `KeywordOrParser`, usually implemented by a fieldless enum.
+pub trait Keyword: Sized {
+    /// Returns the keyword that `v` spells, or `None` if `v` is not a keyword.
+    fn from_keyword(v: &str) -> Option<Self>;
+}
+
+/// The `auto` keyword.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Auto;
+
+/// Parses an integer that must lie within `MIN..=MAX`, e.g. `parser = "BoundedIntParser<1, 64>"`.
+///
+/// Either bound can be left open by using the default, `i64::MIN` or `i64::MAX`, e.g.
+/// `BoundedIntParser<1>` accepts any positive value.
+pub struct BoundedIntParser<const MIN: i64 = { i64::MIN }, const MAX: i64 = { i64::MAX }>;
+
+/// Parses exactly `N` values separated by `SEP` into a `[T; N]` field, e.g.
+/// `parser = "ArrayParser<BoundedIntParser<1>>"` for a `[u32; 2]` size accepts `1024x768`.
+/// Every value is parsed by `P`. An occurrence of the option always gives the whole array, a
+/// later occurrence replaces it.
+///
+/// With `EACH` every occurrence gives one value instead, e.g. `--size 1024 --size 768`. The
+/// value is appended to the array, shifting out the first one, so the option should be
+/// declared with `occurrences = N` to reject a wrong count.
+pub struct ArrayParser<P, const SEP: char = 'x', const EACH: bool = false>(PhantomData<P>);
+
+/// Parses a network endpoint such as `127.0.0.1:6000` or `[::1]:6000`. IPv6 addresses must be
+/// enclosed in brackets, a bare port such as `:6000` means `127.0.0.1:6000`.
+pub struct EndpointParser;
+
+/// Expands a glob pattern such as `*.txt` into the matching paths, sorted alphabetically.
+/// Every occurrence of the option adds its matches to the list.
+///
+/// A pattern that matches nothing adds nothing, `GlobParser<false>` rejects it instead.
+#[cfg(feature = "glob")]
+pub struct GlobParser<const ALLOW_NO_MATCHES: bool = true>;
+
+/// Accepts a value only if the whole of it matches the pattern `P`, e.g.
+/// `parser = "RegexParser<Identifier>"` where `Identifier` implements `Pattern`.
+///
+/// The pattern is compiled once, on first use, and an invalid pattern fails every parse. Use
+/// `compile` to check it up front.
+#[cfg(feature = "regex")]
+pub struct RegexParser<P>(PhantomData<P>);
+
+/// The pattern of a `RegexParser`, in the syntax of the `regex` crate.
+#[cfg(feature = "regex")]
+pub trait Pattern {
+    const PATTERN: &'static str;
+}
+
+impl OptionValueParser<RangeInclusive<i64>> for RangeParser {
+    fn parse(opt: &mut RangeInclusive<i64>, v: &str) -> Result<(), String> {
+        let (start, end) = match split_range(v) {
+            Some((start, end)) => (parse_bound(start, v)?, parse_bound(end, v)?),
+            None => {
+                let x = parse_bound(v, v)?;
+                (x, x)
+            }
+        };
+        if start > end {
+            return Err(format!(
+                "Invalid range '{}'. The start must not be greater than the end",
+                v
+            ));
+        }
+        *opt = start..=end;
+        Ok(())
+    }
+}
+
+impl Keyword for Auto {
+    fn from_keyword(v: &str) -> Option<Self> {
+        if v == "auto" {
+            Some(Auto)
+        } else {
+            None
+        }
+    }
+}
+
+impl<K, T: Default> Default for KeywordOr<K, T> {
+    fn default() -> Self {
+        KeywordOr::Value(T::default())
+    }
+}
+
+impl<K, T, P> OptionValueParser<KeywordOr<K, T>> for KeywordOrParser<P>
+where
+    K: Keyword,
+    T: Default,
+    P: OptionValueParser<T>,
+{
+    fn parse(opt: &mut KeywordOr<K, T>, v: &str) -> Result<(), String> {
+        Self::parse_with_warnings(opt, v, &mut Vec::new())
+    }
+
+    fn parse_with_warnings(
+        opt: &mut KeywordOr<K, T>,
+        v: &str,
+        warnings: &mut Vec<String>,
+    ) -> Result<(), String> {
+        if let Some(keyword) = K::from_keyword(v) {
+            *opt = KeywordOr::Keyword(keyword);
+            return Ok(());
+        }
+        match opt {
+            // Parse into the current value, so that accumulating parsers keep accumulating.
+            KeywordOr::Value(value) => P::parse_with_warnings(value, v, warnings),
+            KeywordOr::Keyword(_) => {
+                let mut value = T::default();
+                P::parse_with_warnings(&mut value, v, warnings)?;
+                *opt = KeywordOr::Value(value);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<T, P, const N: usize, const SEP: char, const EACH: bool> OptionValueParser<[T; N]>
+    for ArrayParser<P, SEP, EACH>
+where
+    T: Default,
+    P: OptionValueParser<T>,
+{
+    fn parse(opt: &mut [T; N], v: &str) -> Result<(), String> {
+        Self::parse_with_warnings(opt, v, &mut Vec::new())
+    }
+
+    fn parse_with_warnings(
+        opt: &mut [T; N],
+        v: &str,
+        warnings: &mut Vec<String>,
+    ) -> Result<(), String> {
+        if EACH {
+            let mut value = T::default();
+            P::parse_with_warnings(&mut value, v, warnings)?;
+            if N > 0 {
+                opt.rotate_left(1);
+                opt[N - 1] = value;
+            }
+            return Ok(());
+        }
+        let parts: Vec<&str> = v.split(SEP).collect();
+        if parts.len() != N {
+            return Err(format!(
+                "Expected {} values separated by '{}' in '{}'",
+                N, SEP, v
+            ));
+        }
+        let mut values: [T; N] = std::array::from_fn(|_| T::default());
+        for (value, part) in values.iter_mut().zip(parts) {
+            P::parse_with_warnings(value, part, warnings)?;
+        }
+        *opt = values;
+        Ok(())
+    }
+}
+
+impl OptionValueParser<Vec<i32>> for ExitCodeListParser {
+    fn parse(opt: &mut Vec<i32>, v: &str) -> Result<(), String> {
+        let mut codes = Vec::new();
+        for item in v.split(',') {
+            let mut range = 0..=0;
+            RangeParser::parse(&mut range, item)
+                .map_err(|_| format!("Invalid exit code '{}' in '{}'", item.trim(), v))?;
+            let (start, end) = (*range.start(), *range.end());
+            if i32::try_from(start).is_err() || i32::try_from(end).is_err() {
+                return Err(format!(
+                    "Exit code '{}' in '{}' is out of range",
+                    item.trim(),
+                    v
+                ));
+            }
+            if end - start >= MAX_EXIT_CODE_RANGE_LEN {
+                return Err(format!(
+                    "Exit code range '{}' in '{}' is too large",
+                    item.trim(),
+                    v
+                ));
+            }
+            codes.extend((start..=end).map(|c| c as i32));
+        }
+        opt.extend(codes);
+        opt.sort_unstable();
+        opt.dedup();
+        Ok(())
+    }
+}
+
+impl OptionValueParser<Vec<String>> for CommandLineParser {
+    fn parse(opt: &mut Vec<String>, v: &str) -> Result<(), String> {
+        let mut argv = Vec::new();
+        let mut arg: Option<String> = None;
+        let mut chars = v.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\'' => {
+                    let arg = arg.get_or_insert_with(String::new);
+                    loop {
+                        match chars.next() {
+                            Some('\'') => break,
+                            Some(c) => arg.push(c),
+                            None => return Err(format!("Unterminated quote in '{}'", v)),
+                        }
+                    }
+                }
+                '"' => {
+                    let arg = arg.get_or_insert_with(String::new);
+                    loop {
+                        match chars.next() {
+                            Some('"') => break,
+                            Some('\\') => match chars.next() {
+                                Some(c) if c == '"' || c == '\\' => arg.push(c),
+                                Some(c) => {
+                                    arg.push('\\');
+                                    arg.push(c);
+                                }
+                                None => return Err(format!("Unterminated quote in '{}'", v)),
+                            },
+                            Some(c) => arg.push(c),
+                            None => return Err(format!("Unterminated quote in '{}'", v)),
+                        }
+                    }
+                }
+                '\\' => match chars.next() {
+                    Some(c) => arg.get_or_insert_with(String::new).push(c),
+                    None => return Err(format!("Trailing backslash in '{}'", v)),
+                },
+                c if c.is_whitespace() => argv.extend(arg.take()),
+                c => arg.get_or_insert_with(String::new).push(c),
+            }
+        }
+        argv.extend(arg);
+        *opt = argv;
+        Ok(())
+    }
+}
+
+impl OptionValueParser<SocketAddr> for EndpointParser {
+    fn parse(opt: &mut SocketAddr, v: &str) -> Result<(), String> {
+        let (host, port) = match v.rfind(':') {
+            Some(pos) => (&v[..pos], &v[pos + 1..]),
+            None => return Err(format!("Missing port in '{}'", v)),
+        };
+        let port = port
+            .parse::<u16>()
+            .map_err(|_| format!("Invalid port '{}' in '{}'", port, v))?;
+        let ip = if host.is_empty() {
+            IpAddr::V4(Ipv4Addr::LOCALHOST)
+        } else if let Some(host) = host.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+            host.parse::<Ipv6Addr>()
+                .map(IpAddr::V6)
+                .map_err(|_| format!("Invalid IPv6 address '{}' in '{}'", host, v))?
+        } else {
+            host.parse::<Ipv4Addr>()
+                .map(IpAddr::V4)
+                .map_err(|_| format!("Invalid IP address '{}' in '{}'", host, v))?
+        };
+        *opt = SocketAddr::new(ip, port);
+        Ok(())
+    }
+}
+
+impl OptionValueParser<Option<SocketAddr>> for EndpointParser {
+    fn parse(opt: &mut Option<SocketAddr>, v: &str) -> Result<(), String> {
+        let mut addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
+        EndpointParser::parse(&mut addr, v)?;
+        *opt = Some(addr);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "glob")]
+impl<const ALLOW_NO_MATCHES: bool> OptionValueParser<Vec<PathBuf>>
+    for GlobParser<ALLOW_NO_MATCHES>
+{
+    fn parse(opt: &mut Vec<PathBuf>, v: &str) -> Result<(), String> {
+        let paths = glob::glob(v)
+            .map_err(|e| format!("Invalid pattern '{}': {}", v, e.msg))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Unable to read '{}': {}", e.path().display(), e.error()))?;
+        if paths.is_empty() && !ALLOW_NO_MATCHES {
+            return Err(format!("No files match '{}'", v));
+        }
+        opt.extend(paths);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "regex")]
+impl<P: Pattern> RegexParser<P> {
+    /// Returns the compiled pattern, or the error if it is invalid.
+    pub fn compile() -> Result<Regex, String> {
+        static COMPILED: OnceLock<Mutex<HashMap<&'static str, Result<Regex, String>>>> =
+            OnceLock::new();
+        COMPILED
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap()
+            .entry(P::PATTERN)
+            .or_insert_with(|| {
+                Regex::new(&format!("^(?:{})$", P::PATTERN))
+                    .map_err(|e| format!("Invalid pattern '{}': {}", P::PATTERN, e))
+            })
+            .clone()
+    }
+}
+
+#[cfg(feature = "regex")]
+impl<P: Pattern> OptionValueParser<String> for RegexParser<P> {
+    fn parse(opt: &mut String, v: &str) -> Result<(), String> {
+        if !Self::compile()?.is_match(v) {
+            return Err(format!(
+                "Invalid value '{}'. The value must match '{}'",
+                v,
+                P::PATTERN
+            ));
+        }
+        *opt = v.to_string();
+        Ok(())
+    }
+}
+
+macro_rules! impl_bounded_int_parser {
+    ($($t:ty),*) => {$(
+        impl<const MIN: i64, const MAX: i64> OptionValueParser<$t> for BoundedIntParser<MIN, MAX> {
+            fn parse(opt: &mut $t, v: &str) -> Result<(), String> {
+                let value = strip_digit_separators(v.trim())
+                    .and_then(|v| v.parse::<i128>().ok())
+                    .ok_or_else(|| format!("Invalid value '{}'", v))?;
+                if value < i128::from(MIN) || value > i128::from(MAX) {
+                    return Err(format!(
+                        "value {} out of range [{}, {}]",
+                        value,
+                        format_bound(MIN, i64::MIN, "-inf"),
+                        format_bound(MAX, i64::MAX, "inf")
+                    ));
+                }
+                *opt = <$t>::try_from(value).map_err(|_| format!("Invalid value '{}'", v))?;
+                Ok(())
+            }
+        }
+    )*};
+}
+
+impl_bounded_int_parser!(i32, i64, u32, u64, usize);
+
+/// Removes the underscores separating digit groups, e.g. `1_000_000`, so numbers can be
+/// parsed with `str::parse`. Returns `None` if an underscore is not surrounded by digits, as
+/// in `_100`, `100_` or `1__0`.
+pub fn strip_digit_separators(v: &str) -> Option<Cow<'_, str>> {
+    if !v.contains('_') {
+        return Some(Cow::Borrowed(v));
+    }
+    let bytes = v.as_bytes();
+    let separates_digits = |i: usize| {
+        i > 0
+            && i + 1 < bytes.len()
+            && bytes[i - 1].is_ascii_digit()
+            && bytes[i + 1].is_ascii_digit()
+    };
+    if (0..bytes.len()).any(|i| bytes[i] == b'_' && !separates_digits(i)) {
+        return None;
+    }
+    Some(Cow::Owned(v.replace('_', "")))
+}
+
+fn format_bound(bound: i64, open: i64, open_str: &str) -> String {
+    if bound == open {
+        open_str.to_string()
+    } else {
+        bound.to_string()
+    }
+}
+
+const MAX_EXIT_CODE_RANGE_LEN: i64 = 1 << 16;
+
+fn split_range(v: &str) -> Option<(&str, &str)> {
+    if let Some(pos) = v.find("..") {
+        return Some((&v[..pos], &v[pos + 2..]));
+    }
+    let bytes = v.as_bytes();
+    (1..bytes.len())
+        .find(|&i| bytes[i] == b'-' && bytes[i - 1].is_ascii_digit())
+        .map(|pos| (&v[..pos], &v[pos + 1..]))
+}
+
+fn parse_bound(bound: &str, v: &str) -> Result<i64, String> {
+    bound
+        .trim()
+        .parse::<i64>()
+        .map_err(|_| format!("Invalid range '{}'", v))
+}
@@ -3,12 +3,38 @@
 //! use `spawner_opts` library.
 //!
 //! # Container attributes
-//! `#[optcont(delimeters = "...", usage = "...", default_parser = "...")]`
+//! `#[optcont(delimeters = "...", usage = "...", default_parser = "...", validator = "...")]`
 //! - `delimeters` - This tells parser on what character the incoming string should be split
 //!   into the name\value pair.
 //! - `usage` - This attribute helps to build proper help message.
+//! - `epilog = "..."` - Text printed at the end of the help message, after the options.
 //! - `default_parser` - If some field doesn't have the `parser` attribute the parser specified
 //!   by `default_parser` will be used.
+//! - `validator = "check_fn"` - A function `fn(&Self) -> Result<(), String>` that is called
+//!   once all options are parsed, so it can check fields against each other. May be repeated,
+//!   validators run in declaration order.
+//! - `auto_version = "1.0"` - Makes `parse_argv` return `ParseError::VersionRequested` with
+//!   the given version once a version flag is met, even if other options are invalid. A bare
+//!   `auto_version` uses the `CARGO_PKG_VERSION` of the crate deriving the trait.
+//! - `version_flags("-V", "--version")` - The names of the version flags, `--version` by
+//!   default.
+//! - `auto_help` - Makes `parse_argv` return `ParseError::HelpRequested` once a help flag is
+//!   met, even if other options are invalid. Neither help nor version flags are recognized by
+//!   `parse_quiet`.
+//! - `help_flags("-h", "--help")` - The names of the help flags, `--help` by default.
+//! - `negatable_flags` - Makes every flag `negatable`.
+//! - `no_duplicates` - Makes `parse_argv` fail if an option is given more than once, instead of
+//!   the last value silently winning. Options accumulating values are exempted with `multiple`.
+//!   Flags and `rest` options are not affected.
+//! - `forward_unknown = "field"` - Appends unrecognized long options to the `Vec<String>` field
+//!   instead of stopping at them, see `Parser::forward_unknown` for how values are detected.
+//! - `raw_values = "field"` - Records the values of options as given on the command line,
+//!   before they are parsed, e.g. for audit logs. The field is a map such as
+//!   `HashMap<String, Vec<String>>` from field names to the values in the order they were
+//!   given. Values taken from env or a config and options capturing the rest of the command
+//!   line are not recorded.
+//! - `validation = "first"` - Either `"first"` (default) to stop at the first failed validator,
+//!   or `"all"` to run every validator and join their messages with newlines.
 //!
 //! # Field attributes
 //! There are two kinds of field attributes:
@@ -21,15 +47,57 @@
 //! # `#[flag(...)]` attributes
 //! - `name = "--some_flag"` - The name of the flag.
 //! - `names("-i", "--in")` - Multiple names of the same flag.
-//! - `desc = "..."` - The description of the flag.
+//!   Names without a leading dash, e.g. `sr`, are matched as whole tokens. Options with such
+//!   names only match with an attached value, e.g. `sr=file`, so a program named `sr` is not
+//!   taken for the option.
+//! - `negatable` - For every long name `--foo` of the flag also accept `--no-foo`, which
+//!   sets the field to `false`. The last occurrence wins.
+//! - `present = false` - The value the flag sets when given, `true` by default. Negated
+//!   names set the opposite value, and the field keeps its `Default` value if the flag is
+//!   not given at all:
+//!
+//!   | `present` | default | not given | `--foo` | `--no-foo` |
+//!   | --- | --- | --- | --- | --- |
+//!   | `true` | `false` | `false` | `true` | `false` |
+//!   | `true` | `true` | `true` | `true` | `false` |
+//!   | `false` | `false` | `false` | `false` | `true` |
+//!   | `false` | `true` | `true` | `false` | `true` |
+//! - `desc = "..."` - The description of the flag. If omitted, the field's doc comment is
+//!   used, with its lines joined by `\n`.
+//! - `conflicts_with = "field"` - Makes `parse_argv` fail if both this flag and any option of
+//!   `field` are given. The conflict applies both ways, so declaring it on one side is enough.
+//!   May be repeated.
+//! - `requires = "field"` - Makes `parse_argv` fail if this flag is given without any option
+//!   of `field`. May be repeated.
+//! - `hidden` - Parses the flag as usual, but leaves it out of `help` and `option_names`,
+//!   e.g. for internal or debugging flags.
+//! - `meta(widget = "checkbox", ...)` - Arbitrary string hints for external help renderers,
+//!   stored in `OptionHelp::metadata`. May be repeated, but each key may be given once.
 //!
 //! # `#[opt(...)]` attributes
 //! Shares the same attributes with the `#[flag(...)]` macro, including a few others:
 //! - `parser = "IntValueParser"` - This attribute tells what parser should be used on the value.
 //! The parser must implement `OptionValueParser` trait.
+//! - `context_parser = "LimitParser"` - Same as `parser`, but the parser must implement
+//!   `ContextualOptionValueParser` and can read other fields of the struct. Such options are set
+//!   after all other options.
 //! - `value_desc = "<int>"` - The description of the option's value.
+//! - `optional_value = "always"` - Allows the option to be given without a value, in which
+//!   case the specified value is parsed instead. The value must then be attached with a
+//!   delimeter, the next token is never taken as the value.
+//! - `reject_empty` - Rejects empty values, e.g. `--name=`, before they reach the parser.
+//!   Otherwise an empty value is passed to the parser as is.
 //! - `env = "..."` - Corresponding environment variable for this option.
-#![recursion_limit = "128"]
+//! - `rest` - Makes the option take every token after it, e.g. `--command prog -a b`, and
+//!   end the parsing. The tokens are appended to the field as is, so it must be a
+//!   `Vec<String>`. At most one option may be declared so, and it can't be read from `env`
+//!   or a config.
+//! - `multiple` - Allows the option to be given more than once under `no_duplicates`, e.g. for
+//!   `Vec` fields.
+//! - `occurrences = 2` - Makes `parse_argv` fail unless the option is given exactly that many
+//!   times, or not at all, e.g. for one value per occurrence with `ArrayParser`. Implies
+//!   `multiple`. Values taken from env or a config are not counted.
+#![recursion_limit = "256"]
 
 extern crate proc_macro;
 extern crate proc_macro2;
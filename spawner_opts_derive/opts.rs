@@ -1,12 +1,21 @@
 use proc_macro2::{Literal, TokenStream};
 use quote::{quote, ToTokens};
 use syn::{
-    Attribute, Data, DeriveInput, Error, Field, Lit, Meta, MetaList, MetaNameValue, NestedMeta,
+    Attribute, Data, DeriveInput, Error, Field, Ident, Lit, Meta, MetaList, MetaNameValue,
+    NestedMeta,
 };
 
 struct OptKindOpt {
     value_desc: Option<String>,
     parser: Option<TokenStream>,
+    contextual: bool,
+    optional_value: Option<String>,
+    reject_empty: bool,
+    rest: bool,
+    /// Exempts the option from `no_duplicates`.
+    multiple: bool,
+    /// The number of times the option must be given, if it is given at all.
+    occurrences: Option<u64>,
 }
 
 enum OptKind {
@@ -20,6 +29,14 @@ struct Opt<'a> {
     names: Vec<String>,
     desc: Option<String>,
     env: Option<String>,
+    negatable: bool,
+    /// The value a flag sets when given, negated names set the opposite.
+    present: bool,
+    /// Hidden options are parsed as usual but left out of `help` and `option_names`.
+    hidden: bool,
+    conflicts_with: Vec<String>,
+    requires: Vec<String>,
+    metadata: Vec<(String, String)>,
     field: &'a Field,
 }
 
@@ -29,21 +46,55 @@ enum OptAttribute<'a> {
     Desc(&'a MetaNameValue, String),
     ValueDesc(&'a MetaNameValue, String),
     Parser(&'a MetaNameValue, String),
+    ContextParser(&'a MetaNameValue, String),
+    OptionalValue(&'a MetaNameValue, String),
+    Negatable(&'a Meta),
+    Present(&'a MetaNameValue, bool),
+    RejectEmpty(&'a Meta),
+    Rest(&'a Meta),
+    Multiple(&'a Meta),
+    Occurrences(&'a MetaNameValue, u64),
+    Hidden,
+    ConflictsWith(String),
+    Requires(String),
     Env(&'a MetaNameValue, String),
+    Metadata(Vec<(&'a MetaNameValue, String)>),
 }
 
 enum OptContainerAttribute {
     Overview(String),
+    Epilog(String),
     Delimeters(String),
     Usage(String),
     DefaultParser(String),
+    Validator(String),
+    Validation(bool),
+    AutoVersion(Option<String>),
+    VersionFlags(Vec<String>),
+    AutoHelp,
+    HelpFlags(Vec<String>),
+    NegatableFlags,
+    NoDuplicates,
+    ForwardUnknown(String),
+    RawValues(String),
 }
 
 struct OptContainer<'a> {
     delimeters: Option<String>,
     usage: Option<String>,
     overview: Option<String>,
+    epilog: Option<String>,
     default_parser: Option<TokenStream>,
+    validators: Vec<TokenStream>,
+    validate_all: bool,
+    version: Option<TokenStream>,
+    version_flags: Vec<String>,
+    auto_help: bool,
+    help_flags: Vec<String>,
+    negatable_flags: bool,
+    no_duplicates: bool,
+    forward_unknown: Option<TokenStream>,
+    raw_values: Option<TokenStream>,
     opts: Vec<Opt<'a>>,
     ast: &'a DeriveInput,
 }
@@ -53,6 +104,12 @@ impl Default for OptKindOpt {
         Self {
             value_desc: None,
             parser: None,
+            contextual: false,
+            optional_value: None,
+            reject_empty: false,
+            rest: false,
+            multiple: false,
+            occurrences: None,
         }
     }
 }
@@ -71,11 +128,27 @@ impl<'a> OptAttribute<'a> {
         Ok(OptAttribute::Names(list, names))
     }
 
+    fn metadata_from_meta_list(list: &'a MetaList) -> Result<Self, Error> {
+        let mut metadata = Vec::new();
+        for item in list.nested.iter() {
+            match item {
+                NestedMeta::Meta(Meta::NameValue(nameval)) => {
+                    metadata.push((nameval, expect_str(&nameval.lit)?));
+                }
+                _ => return Err(Error::new_spanned(item, "Expected key = \"value\"")),
+            }
+        }
+        Ok(OptAttribute::Metadata(metadata))
+    }
+
     fn expected_one_of_err<T: ToTokens>(v: &T) -> Error {
         Error::new_spanned(
             v,
             "Expected one of: name = \"...\", names(...), desc = \"...\", \
-             value_desc = \"...\" parser = \"...\" env = \"...\"",
+             value_desc = \"...\" parser = \"...\" context_parser = \"...\" optional_value = \"...\" \
+             env = \"...\" conflicts_with = \"...\" requires = \"...\" present = false occurrences = 2 \
+             meta(...) \
+             negatable reject_empty rest multiple hidden",
         )
     }
 
@@ -86,33 +159,52 @@ impl<'a> OptAttribute<'a> {
             "desc" => Ok(OptAttribute::Desc(nameval, expect_str(lit)?)),
             "value_desc" => Ok(OptAttribute::ValueDesc(nameval, expect_str(lit)?)),
             "parser" => Ok(OptAttribute::Parser(nameval, expect_str(lit)?)),
+            "context_parser" => Ok(OptAttribute::ContextParser(nameval, expect_str(lit)?)),
+            "optional_value" => Ok(OptAttribute::OptionalValue(nameval, expect_str(lit)?)),
             "env" => Ok(OptAttribute::Env(nameval, expect_str(lit)?)),
+            "conflicts_with" => Ok(OptAttribute::ConflictsWith(expect_str(lit)?)),
+            "requires" => Ok(OptAttribute::Requires(expect_str(lit)?)),
+            "present" => Ok(OptAttribute::Present(nameval, expect_bool(lit)?)),
+            "occurrences" => Ok(OptAttribute::Occurrences(nameval, expect_int(lit)?)),
             _ => Err(OptAttribute::expected_one_of_err(nameval)),
         }
     }
 
     fn from_meta(meta: &'a Meta) -> Result<Self, Error> {
         match meta {
-            Meta::List(list) => {
-                if list.ident != "names" {
-                    Err(OptAttribute::expected_one_of_err(meta))
-                } else {
-                    OptAttribute::names_from_meta_list(&list)
-                }
-            }
+            Meta::List(list) if list.ident == "names" => OptAttribute::names_from_meta_list(&list),
+            Meta::List(list) if list.ident == "meta" => OptAttribute::metadata_from_meta_list(list),
             Meta::NameValue(nameval) => OptAttribute::from_name_value(&nameval),
+            Meta::Word(ident) if ident == "negatable" => Ok(OptAttribute::Negatable(meta)),
+            Meta::Word(ident) if ident == "reject_empty" => Ok(OptAttribute::RejectEmpty(meta)),
+            Meta::Word(ident) if ident == "rest" => Ok(OptAttribute::Rest(meta)),
+            Meta::Word(ident) if ident == "multiple" => Ok(OptAttribute::Multiple(meta)),
+            Meta::Word(ident) if ident == "hidden" => Ok(OptAttribute::Hidden),
             _ => Err(OptAttribute::expected_one_of_err(meta)),
         }
     }
 }
 
 impl<'a> Opt<'a> {
+    fn is_rest(&self) -> bool {
+        match self.kind {
+            OptKind::Opt(ref v) => v.rest,
+            _ => false,
+        }
+    }
+
     fn new(kind: OptKind, field: &'a Field) -> Self {
         Opt {
             kind,
             names: Vec::new(),
             desc: None,
             env: None,
+            negatable: false,
+            present: true,
+            hidden: false,
+            conflicts_with: Vec::new(),
+            requires: Vec::new(),
+            metadata: Vec::new(),
             field,
         }
     }
@@ -156,13 +248,103 @@ impl<'a> Opt<'a> {
                         ));
                     }
                 },
+                OptAttribute::ContextParser(nameval, s) => match opt.kind {
+                    OptKind::Opt(ref mut v) => {
+                        v.parser = Some(s.parse().unwrap());
+                        v.contextual = true;
+                    }
+                    _ => {
+                        return Err(Error::new_spanned(
+                            nameval,
+                            "Parser allowed on options only",
+                        ));
+                    }
+                },
+                OptAttribute::OptionalValue(nameval, s) => match opt.kind {
+                    OptKind::Opt(ref mut v) => v.optional_value = Some(s),
+                    _ => {
+                        return Err(Error::new_spanned(
+                            nameval,
+                            "Optional value allowed on options only",
+                        ));
+                    }
+                },
                 OptAttribute::Env(_, s) => opt.env = Some(s),
+                OptAttribute::ConflictsWith(s) => opt.conflicts_with.push(s),
+                OptAttribute::Requires(s) => opt.requires.push(s),
+                OptAttribute::Metadata(metadata) => {
+                    for (nameval, value) in metadata {
+                        let key = nameval.ident.to_string();
+                        if opt.metadata.iter().any(|(k, _)| *k == key) {
+                            return Err(Error::new_spanned(nameval, "Duplicate metadata key"));
+                        }
+                        opt.metadata.push((key, value));
+                    }
+                }
+                OptAttribute::Hidden => opt.hidden = true,
+                OptAttribute::RejectEmpty(meta) => match opt.kind {
+                    OptKind::Opt(ref mut v) => v.reject_empty = true,
+                    _ => {
+                        return Err(Error::new_spanned(
+                            meta,
+                            "Empty values rejected on options only",
+                        ));
+                    }
+                },
+                OptAttribute::Rest(meta) => match opt.kind {
+                    OptKind::Opt(ref mut v) => v.rest = true,
+                    _ => {
+                        return Err(Error::new_spanned(
+                            meta,
+                            "Capturing the rest of the command line allowed on options only",
+                        ));
+                    }
+                },
+                OptAttribute::Multiple(meta) => match opt.kind {
+                    OptKind::Opt(ref mut v) => v.multiple = true,
+                    _ => {
+                        return Err(Error::new_spanned(
+                            meta,
+                            "Multiple occurrences allowed on options only",
+                        ));
+                    }
+                },
+                OptAttribute::Occurrences(nameval, n) => match opt.kind {
+                    OptKind::Opt(ref mut v) => v.occurrences = Some(n),
+                    _ => {
+                        return Err(Error::new_spanned(
+                            nameval,
+                            "Number of occurrences allowed on options only",
+                        ));
+                    }
+                },
+                OptAttribute::Negatable(meta) => match opt.kind {
+                    OptKind::Flag => opt.negatable = true,
+                    _ => {
+                        return Err(Error::new_spanned(meta, "Negation allowed on flags only"));
+                    }
+                },
+                OptAttribute::Present(nameval, present) => match opt.kind {
+                    OptKind::Flag => opt.present = present,
+                    _ => {
+                        return Err(Error::new_spanned(
+                            nameval,
+                            "Presence value allowed on flags only",
+                        ));
+                    }
+                },
             }
         }
 
         if opt.names.is_empty() {
             return Err(Error::new_spanned(list, "Unnamed options are not allowed"));
         }
+        if opt.names.iter().any(String::is_empty) {
+            return Err(Error::new_spanned(
+                list,
+                "Empty option names are not allowed",
+            ));
+        }
 
         Ok(opt)
     }
@@ -190,8 +372,37 @@ impl<'a> Opt<'a> {
         if opts.is_empty() {
             opts.push(Opt::new(OptKind::Invalid, field));
         }
+        if let Some(doc) = Opt::doc_comment(field) {
+            for opt in opts.iter_mut().filter(|opt| opt.desc.is_none()) {
+                opt.desc = Some(doc.clone());
+            }
+        }
         Ok(opts)
     }
+
+    /// Joins the lines of the field's doc comment with `\n`.
+    fn doc_comment(field: &Field) -> Option<String> {
+        let lines: Vec<String> = field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path.is_ident("doc"))
+            .filter_map(|attr| match attr.interpret_meta() {
+                Some(Meta::NameValue(MetaNameValue {
+                    lit: Lit::Str(s), ..
+                })) => Some(s.value()),
+                _ => None,
+            })
+            .map(|line| match line.strip_prefix(' ') {
+                Some(stripped) => stripped.to_string(),
+                None => line,
+            })
+            .collect();
+        if lines.is_empty() {
+            None
+        } else {
+            Some(lines.join("\n"))
+        }
+    }
 }
 
 impl OptContainerAttribute {
@@ -199,19 +410,71 @@ impl OptContainerAttribute {
         Error::new_spanned(
             v,
             "Expected one of: delimeters = \"...\", usage = \"...\", overview = \"...\" \
-             default_parser = \"...\"",
+             epilog = \"...\" \
+             default_parser = \"...\" validator = \"...\" validation = \"...\" \
+             auto_version, auto_version = \"...\" version_flags(...) auto_help help_flags(...) \
+             negatable_flags no_duplicates \
+             forward_unknown = \"...\" raw_values = \"...\"",
         )
     }
 
     fn from_meta(meta: &Meta) -> Result<Self, Error> {
+        match meta {
+            Meta::Word(ident) if ident == "auto_version" => {
+                return Ok(OptContainerAttribute::AutoVersion(None));
+            }
+            Meta::Word(ident) if ident == "negatable_flags" => {
+                return Ok(OptContainerAttribute::NegatableFlags);
+            }
+            Meta::Word(ident) if ident == "auto_help" => {
+                return Ok(OptContainerAttribute::AutoHelp);
+            }
+            Meta::Word(ident) if ident == "no_duplicates" => {
+                return Ok(OptContainerAttribute::NoDuplicates);
+            }
+            Meta::List(list) if list.ident == "version_flags" || list.ident == "help_flags" => {
+                let mut flags = Vec::new();
+                for item in list.nested.iter() {
+                    match item {
+                        NestedMeta::Literal(l) => flags.push(expect_str(l)?),
+                        NestedMeta::Meta(m) => {
+                            return Err(Error::new_spanned(m, "Expected string literal"));
+                        }
+                    }
+                }
+                return Ok(if list.ident == "version_flags" {
+                    OptContainerAttribute::VersionFlags(flags)
+                } else {
+                    OptContainerAttribute::HelpFlags(flags)
+                });
+            }
+            _ => {}
+        }
         if let Meta::NameValue(nameval) = meta {
             match nameval.ident.to_string().as_ref() {
                 "overview" => Ok(OptContainerAttribute::Overview(expect_str(&nameval.lit)?)),
+                "epilog" => Ok(OptContainerAttribute::Epilog(expect_str(&nameval.lit)?)),
                 "delimeters" => Ok(OptContainerAttribute::Delimeters(expect_str(&nameval.lit)?)),
                 "usage" => Ok(OptContainerAttribute::Usage(expect_str(&nameval.lit)?)),
                 "default_parser" => Ok(OptContainerAttribute::DefaultParser(expect_str(
                     &nameval.lit,
                 )?)),
+                "validator" => Ok(OptContainerAttribute::Validator(expect_str(&nameval.lit)?)),
+                "forward_unknown" => Ok(OptContainerAttribute::ForwardUnknown(expect_str(
+                    &nameval.lit,
+                )?)),
+                "raw_values" => Ok(OptContainerAttribute::RawValues(expect_str(&nameval.lit)?)),
+                "auto_version" => Ok(OptContainerAttribute::AutoVersion(Some(expect_str(
+                    &nameval.lit,
+                )?))),
+                "validation" => match expect_str(&nameval.lit)?.as_str() {
+                    "all" => Ok(OptContainerAttribute::Validation(true)),
+                    "first" => Ok(OptContainerAttribute::Validation(false)),
+                    _ => Err(Error::new_spanned(
+                        nameval,
+                        "Expected validation = \"all\" or validation = \"first\"",
+                    )),
+                },
                 _ => Err(OptContainerAttribute::expected_one_of_err(meta)),
             }
         } else {
@@ -286,6 +549,18 @@ impl<'a> OptContainer<'a> {
                 Err(e) => errors.push(e),
             }
         }
+        errors.extend(
+            self.opts
+                .iter()
+                .filter(|opt| opt.is_rest())
+                .skip(1)
+                .map(|opt| {
+                    Error::new_spanned(
+                        opt.field,
+                        "Only one option may capture the rest of the command line",
+                    )
+                }),
+        );
         match errors.len() {
             0 => Ok(()),
             _ => Err(errors),
@@ -296,11 +571,31 @@ impl<'a> OptContainer<'a> {
         for att in OptContainer::parse_attrs(&self.ast.attrs)?.into_iter() {
             match att {
                 OptContainerAttribute::Overview(s) => self.overview = Some(s),
+                OptContainerAttribute::Epilog(s) => self.epilog = Some(s),
                 OptContainerAttribute::Delimeters(d) => self.delimeters = Some(d),
                 OptContainerAttribute::Usage(u) => self.usage = Some(u),
                 OptContainerAttribute::DefaultParser(p) => {
                     self.default_parser = Some(p.parse().unwrap())
                 }
+                OptContainerAttribute::Validator(v) => self.validators.push(v.parse().unwrap()),
+                OptContainerAttribute::Validation(all) => self.validate_all = all,
+                OptContainerAttribute::AutoVersion(v) => {
+                    self.version = Some(match v {
+                        Some(v) => quote!(#v),
+                        None => quote!(env!("CARGO_PKG_VERSION")),
+                    })
+                }
+                OptContainerAttribute::VersionFlags(flags) => self.version_flags = flags,
+                OptContainerAttribute::AutoHelp => self.auto_help = true,
+                OptContainerAttribute::HelpFlags(flags) => self.help_flags = flags,
+                OptContainerAttribute::NegatableFlags => self.negatable_flags = true,
+                OptContainerAttribute::NoDuplicates => self.no_duplicates = true,
+                OptContainerAttribute::ForwardUnknown(field) => {
+                    self.forward_unknown = Some(field.parse().unwrap())
+                }
+                OptContainerAttribute::RawValues(field) => {
+                    self.raw_values = Some(field.parse().unwrap())
+                }
             }
         }
         Ok(())
@@ -310,8 +605,19 @@ impl<'a> OptContainer<'a> {
         let mut cont = Self {
             delimeters: None,
             overview: None,
+            epilog: None,
             usage: None,
             default_parser: None,
+            validators: Vec::new(),
+            validate_all: false,
+            version: None,
+            version_flags: vec![String::from("--version")],
+            auto_help: false,
+            help_flags: vec![String::from("--help")],
+            negatable_flags: false,
+            no_duplicates: false,
+            forward_unknown: None,
+            raw_values: None,
             opts: Vec::new(),
             ast,
         };
@@ -327,18 +633,51 @@ impl<'a> OptContainer<'a> {
         }
     }
 
+    /// Returns `--no-foo` for every `--foo` name of a negatable flag.
+    fn negated_names(&self, opt: &Opt) -> Vec<String> {
+        match opt.kind {
+            OptKind::Flag if opt.negatable || self.negatable_flags => opt
+                .names
+                .iter()
+                .filter_map(|name| name.strip_prefix("--"))
+                .map(|name| format!("--no-{}", name))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The names of the option followed by its negated names.
+    fn all_names(&self, opt: &Opt) -> Vec<String> {
+        let mut names = opt.names.clone();
+        names.extend(self.negated_names(opt));
+        names
+    }
+
     fn build_help_fn(&self) -> TokenStream {
         let overview = self.build_str_opt(&self.overview);
         let usage = self.build_str_opt(&self.usage);
+        let epilog = self.build_str_opt(&self.epilog);
         let delimeters = self.build_str_opt(&self.delimeters);
         let options: Vec<TokenStream> = self
             .opts
             .iter()
+            .filter(|opt| !opt.hidden)
             .filter_map(|opt| {
-                let names: Vec<TokenStream> =
-                    opt.names.iter().map(|s| quote!(#s.to_string())).collect();
+                let names: Vec<TokenStream> = self
+                    .all_names(opt)
+                    .iter()
+                    .map(|s| quote!(#s.to_string()))
+                    .collect();
                 let desc = self.build_str_opt(&opt.desc);
                 let env = self.build_str_opt(&opt.env);
+                let (keys, values): (Vec<&String>, Vec<&String>) =
+                    opt.metadata.iter().map(|(k, v)| (k, v)).unzip();
+                let metadata = quote! {{
+                    #[allow(unused_mut)]
+                    let mut metadata = ::std::collections::HashMap::new();
+                    #(metadata.insert(#keys.to_string(), #values.to_string());)*
+                    metadata
+                }};
                 match opt.kind {
                     OptKind::Invalid => None,
                     OptKind::Flag => Some(quote! {
@@ -347,6 +686,7 @@ impl<'a> OptContainer<'a> {
                             desc: #desc,
                             value_desc: None,
                             env: #env,
+                            metadata: #metadata,
                         }
                     }),
                     OptKind::Opt(ref v) => {
@@ -357,6 +697,7 @@ impl<'a> OptContainer<'a> {
                                 desc: #desc,
                                 value_desc: #vd,
                                 env: #env,
+                                metadata: #metadata,
                             }
                         })
                     }
@@ -370,28 +711,50 @@ impl<'a> OptContainer<'a> {
                     usage: #usage,
                     delimeters: #delimeters,
                     options: vec![#(#options),*],
+                    name_columns: false,
+                    width: spawner_opts::DEFAULT_HELP_WIDTH,
+                    color: spawner_opts::HelpColor::Never,
+                    epilog: #epilog,
                 }
             }
         }
     }
 
+    fn build_option_names_fn(&self) -> TokenStream {
+        let names: Vec<String> = self
+            .opts
+            .iter()
+            .filter(|opt| !opt.hidden && !matches!(opt.kind, OptKind::Invalid))
+            .flat_map(|opt| self.all_names(opt))
+            .collect();
+        quote! {
+            fn option_names() -> Vec<&'static str> {
+                vec![#(#names),*]
+            }
+        }
+    }
+
     fn build_register_opts(&self) -> Vec<TokenStream> {
         self.opts
             .iter()
             .filter_map(|opt| {
-                let member_func = match &opt.kind {
-                    OptKind::Flag => quote!(flag),
-                    OptKind::Opt(_) => quote!(opt),
-                    _ => return None,
-                };
-                let names: Vec<Lit> = opt
-                    .names
+                let names: Vec<Lit> = self
+                    .all_names(opt)
                     .iter()
                     .map(|name| Lit::new(Literal::string(name)))
                     .collect();
-                Some(quote! {
-                    parser.#member_func(&[#(#names),*]);
-                })
+                match &opt.kind {
+                    OptKind::Flag => Some(quote!(parser.flag(&[#(#names),*]);)),
+                    OptKind::Opt(OptKindOpt { rest: true, .. }) => {
+                        Some(quote!(parser.rest_opt(&[#(#names),*]);))
+                    }
+                    OptKind::Opt(OptKindOpt {
+                        optional_value: Some(default),
+                        ..
+                    }) => Some(quote!(parser.optional_opt(&[#(#names),*], #default);)),
+                    OptKind::Opt(_) => Some(quote!(parser.opt(&[#(#names),*]);)),
+                    _ => None,
+                }
             })
             .collect()
     }
@@ -420,30 +783,135 @@ impl<'a> OptContainer<'a> {
         ))
     }
 
+    fn build_parse_value(&self, opt: &Opt, parser: &TokenStream, val: TokenStream) -> TokenStream {
+        let field = &opt.field.ident;
+        match opt.kind {
+            OptKind::Opt(ref v) if v.contextual => quote! {
+                let mut value = self.#field.clone();
+                <#parser as spawner_opts::ContextualOptionValueParser<Self, _>>::parse(
+                    self, &mut value, #val,
+                )?;
+                self.#field = value;
+            },
+            _ => quote! {
+                <#parser>::parse_with_warnings(&mut self.#field, #val, warnings)?;
+            },
+        }
+    }
+
+    /// Contextual options are set last, so their parsers can see every other option.
+    fn opts_in_parse_order(&self) -> impl Iterator<Item = &Opt<'a>> {
+        let is_contextual = |opt: &&Opt<'a>| match opt.kind {
+            OptKind::Opt(ref v) => v.contextual,
+            _ => false,
+        };
+        let simple = self.opts.iter().filter(move |opt| !is_contextual(opt));
+        let contextual = self.opts.iter().filter(is_contextual);
+        simple.chain(contextual)
+    }
+
     fn build_set_opts(&self) -> Result<Vec<TokenStream>, Vec<Error>> {
         let mut set_opts: Vec<TokenStream> = Vec::new();
         let mut errors: Vec<Error> = Vec::new();
 
-        for opt in &self.opts {
+        for opt in self.opts_in_parse_order() {
             let field = &opt.field.ident;
             let name = Lit::new(Literal::string(
                 opt.names.get(0).unwrap_or(&String::from("")),
             ));
             match opt.kind {
-                OptKind::Flag => set_opts.push(quote! {
-                    if parser.has_flag(#name) {
-                        assert_flag_type_is_bool(&self.#field);
-                        self.#field = true;
+                OptKind::Flag => {
+                    let negated = self.negated_names(opt);
+                    let present = opt.present;
+                    set_opts.push(quote! {
+                        if let Some(used) = parser.last_flag(#name) {
+                            assert_flag_type_is_bool(&self.#field);
+                            self.#field = #present != [#(#negated),*].contains(&used);
+                        }
+                    })
+                }
+                // The tokens are taken as is, the field must be a `Vec<String>`.
+                OptKind::Opt(ref v) if v.rest => set_opts.push(quote! {
+                    if let Some(entries) = parser.get_opt(#name) {
+                        let field: &mut Vec<String> = &mut self.#field;
+                        field.extend(entries.iter().cloned());
                     }
                 }),
-                OptKind::Opt(_) => match self.opt_parser(opt) {
-                    Ok(parser) => set_opts.push(quote! {
-                        if let Some(entries) = parser.get_opt(#name) {
-                            for e in entries {
-                                #parser::parse(&mut self.#field, e)?;
+                OptKind::Opt(ref v) => match self.opt_parser(opt) {
+                    Ok(parser) => {
+                        let parse_value = self.build_parse_value(opt, parser, quote!(e));
+                        let store_raw = match self.raw_values {
+                            Some(ref raw) => {
+                                let key = field.as_ref().unwrap().to_string();
+                                quote! {
+                                    self.#raw.entry(#key.to_string()).or_default().push(e.clone());
+                                }
                             }
-                        }
-                    }),
+                            None => quote!(),
+                        };
+                        let check_empty = if v.reject_empty {
+                            quote! {
+                                if e.is_empty() {
+                                    return Err(spawner_opts::ParseError::InvalidValue(format!(
+                                        "Option '{}' requires a non-empty value",
+                                        #name
+                                    )));
+                                }
+                            }
+                        } else {
+                            quote!()
+                        };
+                        let check_occurrences = match v.occurrences {
+                            Some(n) => quote! {
+                                if !entries.is_empty() && entries.len() as u64 != #n {
+                                    return Err(spawner_opts::ParseError::InvalidValue(format!(
+                                        "Option '{}' must be given {} times, got {}",
+                                        #name,
+                                        #n,
+                                        entries.len()
+                                    )));
+                                }
+                            },
+                            None => quote!(),
+                        };
+                        let check_duplicates =
+                            if self.no_duplicates && !v.multiple && v.occurrences.is_none() {
+                                quote! {
+                                    if entries.len() > 1 {
+                                        return Err(spawner_opts::ParseError::InvalidValue(format!(
+                                            "Option '{}' may be given only once",
+                                            #name
+                                        )));
+                                    }
+                                }
+                            } else {
+                                quote!()
+                            };
+                        set_opts.push(quote! {
+                            if let Some(entries) = parser.get_opt(#name) {
+                                #check_duplicates
+                                #check_occurrences
+                                for (n, e) in entries.iter().enumerate() {
+                                    let parsed = (|| -> std::result::Result<(), spawner_opts::ParseError> {
+                                        #check_empty
+                                        #parse_value
+                                        Ok(())
+                                    })();
+                                    match (parsed, settings.start_index) {
+                                        (Err(spawner_opts::ParseError::InvalidValue(msg)), Some(start)) => {
+                                            let token = parser.value_token(#name, n).unwrap_or(0);
+                                            return Err(spawner_opts::ParseError::InvalidToken(
+                                                start + token,
+                                                msg,
+                                            ));
+                                        }
+                                        (parsed, _) => parsed?,
+                                    }
+                                    #store_raw
+                                }
+                            }
+                        })
+                    }
                     Err(e) => errors.push(e),
                 },
                 _ => {}
@@ -456,28 +924,172 @@ impl<'a> OptContainer<'a> {
         }
     }
 
+    fn build_validate(&self) -> TokenStream {
+        let validators = &self.validators;
+        if validators.is_empty() {
+            quote!()
+        } else if self.validate_all {
+            quote! {
+                let errors: Vec<String> = vec![#(#validators(self)),*]
+                    .into_iter()
+                    .filter_map(std::result::Result::err)
+                    .collect();
+                if !errors.is_empty() {
+                    return Err(errors.join("\n").into());
+                }
+            }
+        } else {
+            quote! {
+                #(#validators(self)?;)*
+            }
+        }
+    }
+
+    /// Whether any option of the field was given on the command line.
+    fn build_is_set(&self, field: &Ident) -> TokenStream {
+        let checks = self
+            .opts
+            .iter()
+            .filter(|opt| opt.field.ident.as_ref() == Some(field))
+            .map(|opt| {
+                let name = &opt.names[0];
+                match opt.kind {
+                    OptKind::Flag => quote!(parser.last_flag(#name).is_some()),
+                    _ => quote!(parser.get_opt(#name).map_or(false, |e| !e.is_empty())),
+                }
+            });
+        quote!((false #(|| #checks)*))
+    }
+
+    fn find_field_opt(&self, opt: &Opt, field: &str, attr: &str) -> Result<&Opt<'_>, Error> {
+        self.opts
+            .iter()
+            .find(|o| o.field.ident.as_ref().is_some_and(|i| i == field))
+            .ok_or_else(|| {
+                Error::new_spanned(opt.field, format!("Unknown field '{}' in {}", field, attr))
+            })
+    }
+
+    /// Checks the `conflicts_with` and `requires` relations between options.
+    fn build_check_relations(&self) -> Result<Vec<TokenStream>, Vec<Error>> {
+        let mut checks = Vec::new();
+        let mut errors = Vec::new();
+        for opt in self.opts.iter() {
+            if opt.conflicts_with.is_empty() && opt.requires.is_empty() {
+                continue;
+            }
+            let is_set = self.build_is_set(opt.field.ident.as_ref().unwrap());
+            for other in opt.conflicts_with.iter() {
+                let other_opt = match self.find_field_opt(opt, other, "conflicts_with") {
+                    Ok(o) => o,
+                    Err(e) => {
+                        errors.push(e);
+                        continue;
+                    }
+                };
+                let other_is_set = self.build_is_set(other_opt.field.ident.as_ref().unwrap());
+                let msg = format!(
+                    "Options '{}' and '{}' cannot be used together",
+                    opt.names[0], other_opt.names[0]
+                );
+                checks.push(quote! {
+                    if #is_set && #other_is_set {
+                        return Err(spawner_opts::ParseError::InvalidValue(#msg.to_string()));
+                    }
+                });
+            }
+            for other in opt.requires.iter() {
+                let other_opt = match self.find_field_opt(opt, other, "requires") {
+                    Ok(o) => o,
+                    Err(e) => {
+                        errors.push(e);
+                        continue;
+                    }
+                };
+                let other_is_set = self.build_is_set(other_opt.field.ident.as_ref().unwrap());
+                let msg = format!(
+                    "Option '{}' requires '{}'",
+                    opt.names[0], other_opt.names[0]
+                );
+                checks.push(quote! {
+                    if #is_set && !#other_is_set {
+                        return Err(spawner_opts::ParseError::InvalidValue(#msg.to_string()));
+                    }
+                });
+            }
+        }
+        match errors.len() {
+            0 => Ok(checks),
+            _ => Err(errors),
+        }
+    }
+
+    /// The version flags are checked before any value is parsed, so `--version` wins over
+    /// invalid options.
+    fn build_help_flags(&self) -> (TokenStream, TokenStream) {
+        if !self.auto_help {
+            return (quote!(), quote!());
+        }
+        let flags = &self.help_flags;
+        (
+            quote!(parser.flag(&[#(#flags),*]);),
+            quote! {
+                if [#(#flags),*].iter().any(|f| parser.has_flag(f)) {
+                    return Err(spawner_opts::ParseError::HelpRequested);
+                }
+            },
+        )
+    }
+
+    fn build_version(&self) -> (TokenStream, TokenStream) {
+        let version = match self.version {
+            Some(ref v) => v,
+            None => return (quote!(), quote!()),
+        };
+        let flags = &self.version_flags;
+        (
+            quote!(parser.flag(&[#(#flags),*]);),
+            quote! {
+                if [#(#flags),*].iter().any(|f| parser.has_flag(f)) {
+                    return Err(spawner_opts::ParseError::VersionRequested(
+                        #version.to_string(),
+                    ));
+                }
+            },
+        )
+    }
+
     fn build_parse_env(&self) -> Result<Vec<TokenStream>, Vec<Error>> {
         let mut result = Vec::new();
         let mut errors = Vec::new();
 
-        for opt in &self.opts {
+        for opt in self.opts_in_parse_order() {
             let env = match opt.env {
                 Some(ref env) => env,
                 _ => continue,
             };
+            if opt.is_rest() {
+                errors.push(Error::new_spanned(
+                    opt.field,
+                    "Options capturing the rest of the command line can't be read from env",
+                ));
+                continue;
+            }
             let parser = match opt.kind {
                 OptKind::Flag => self.env_flag_parser(opt),
                 OptKind::Opt(_) => self.opt_parser(opt),
                 _ => continue,
             };
 
-            let field = &opt.field.ident;
             match parser {
-                Ok(parser) => result.push(quote! {
-                    if let Some(val) = std::env::var(#env).ok() {
-                        #parser::parse(&mut self.#field, val.as_str())?;
-                    }
-                }),
+                Ok(parser) => {
+                    let parse_value = self.build_parse_value(opt, parser, quote!(val.as_str()));
+                    result.push(quote! {
+                        if let Some(val) = std::env::var(#env).ok() {
+                            #parse_value
+                        }
+                    })
+                }
                 Err(e) => errors.push(e),
             }
         }
@@ -490,20 +1102,125 @@ impl<'a> OptContainer<'a> {
     fn build_parse_env_fn(&self) -> Result<TokenStream, Vec<Error>> {
         let parse_env = self.build_parse_env()?;
         Ok(quote! {
-            fn parse_env(&mut self) -> std::result::Result<(), String> {
+            #[allow(unused_variables)]
+            fn parse_env_with_warnings(
+                &mut self,
+                warnings: &mut Vec<String>,
+            ) -> std::result::Result<(), spawner_opts::ParseError> {
                 #(#parse_env)*
                 Ok(())
             }
         })
     }
 
+    /// Config keys are field names, values go through the same parsers as on the command line.
+    fn build_parse_config_fn(&self) -> Result<TokenStream, Vec<Error>> {
+        let mut arms = Vec::new();
+        let mut errors = Vec::new();
+        let mut keys = Vec::new();
+        for opt in self.opts_in_parse_order() {
+            let field = &opt.field.ident;
+            let key = field.as_ref().unwrap().to_string();
+            // A field may be declared by several `opt` attributes. Options capturing the rest
+            // of the command line have no single value to read from a config.
+            if keys.contains(&key) || opt.is_rest() {
+                continue;
+            }
+            keys.push(key.clone());
+            match opt.kind {
+                OptKind::Flag => arms.push(quote! {
+                    #key => {
+                        assert_flag_type_is_bool(&self.#field);
+                        self.#field = match val {
+                            "true" | "1" => true,
+                            "false" | "0" => false,
+                            _ => {
+                                return Err(spawner_opts::ParseError::InvalidValue(format!(
+                                    "Invalid value '{}' for config key '{}', expected a boolean",
+                                    val, key
+                                )))
+                            }
+                        };
+                    }
+                }),
+                OptKind::Opt(_) => match self.opt_parser(opt) {
+                    Ok(parser) => {
+                        let parse_value = self.build_parse_value(opt, parser, quote!(val));
+                        arms.push(quote! {
+                            #key => { #parse_value }
+                        });
+                    }
+                    Err(e) => errors.push(e),
+                },
+                _ => {}
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Ok(quote! {
+            #[allow(unused_variables)]
+            fn parse_config_with_warnings<I, K, V>(
+                &mut self,
+                entries: I,
+                warnings: &mut Vec<String>,
+            ) -> std::result::Result<(), spawner_opts::ParseError>
+            where
+                I: IntoIterator<Item = (K, V)>,
+                K: AsRef<str>,
+                V: AsRef<str>,
+            {
+                fn assert_flag_type_is_bool(v: &bool) {}
+
+                for (key, val) in entries {
+                    let (key, val) = (key.as_ref(), val.as_ref());
+                    match key {
+                        #(#arms)*
+                        _ => {
+                            return Err(spawner_opts::ParseError::InvalidValue(format!(
+                                "Unknown config key '{}'",
+                                key
+                            )))
+                        }
+                    }
+                }
+                Ok(())
+            }
+        })
+    }
+
     fn build_parse_argv_fn(&self) -> Result<TokenStream, Vec<Error>> {
         let delimeters = &self.delimeters;
         let register_opts = self.build_register_opts();
         let set_opts = self.build_set_opts()?;
+        let validate = self.build_validate();
+        let check_relations = self.build_check_relations()?;
+        // Skipped for a prefix of the command line, see `ParseSettings::partial`.
+        let check_complete = quote! {
+            if !settings.partial {
+                #(#check_relations)*
+                #validate
+            }
+        };
+        let (register_version, check_version) = self.build_version();
+        let (register_help, check_help) = self.build_help_flags();
+        let (enable_forwarding, store_forwarded) = match self.forward_unknown {
+            Some(ref field) => (
+                quote!(parser.forward_unknown();),
+                quote!(self.#field.extend(parser.forwarded().iter().cloned());),
+            ),
+            None => (quote!(), quote!()),
+        };
 
         Ok(quote! {
-            fn parse_argv<T, U>(&mut self, argv: T) -> std::result::Result<usize, String>
+            #[allow(unused_variables)]
+            fn parse_tokens<T, U>(
+                &mut self,
+                argv: T,
+                settings: spawner_opts::ParseSettings,
+                warnings: &mut Vec<String>,
+            ) -> std::result::Result<spawner_opts::Parsed, spawner_opts::ParseError>
             where
                 T: IntoIterator<Item = U>,
                 U: AsRef<str>
@@ -511,11 +1228,36 @@ impl<'a> OptContainer<'a> {
                 use spawner_opts::parser::Parser;
                 fn assert_flag_type_is_bool(v: &bool) {}
 
+                let max_occurrences = settings.max_occurrences;
                 let mut parser = Parser::new(argv, #delimeters);
                 #(#register_opts)*
-                let parsed_opts = parser.parse();
+                if !settings.quiet {
+                    #register_version
+                    #register_help
+                }
+                parser.max_occurrences(max_occurrences);
+                if settings.partial {
+                    parser.partial();
+                }
+                #enable_forwarding
+                let parsed_opts = parser.parse_split().args_start;
+                if let Some(name) = parser.exceeded_opt() {
+                    return Err(spawner_opts::ParseError::InvalidValue(format!(
+                        "Option '{}' is given more than {} times",
+                        name, max_occurrences
+                    )));
+                }
+                if !settings.quiet {
+                    #check_help
+                    #check_version
+                }
                 #(#set_opts)*
-                Ok(parsed_opts)
+                #store_forwarded
+                #check_complete
+                Ok(spawner_opts::Parsed {
+                    args_start: parsed_opts,
+                    pending: parser.pending().map(str::to_string),
+                })
             }
         })
     }
@@ -528,18 +1270,36 @@ fn expect_str(lit: &Lit) -> Result<String, Error> {
     }
 }
 
+fn expect_int(lit: &Lit) -> Result<u64, Error> {
+    match lit {
+        Lit::Int(i) => Ok(i.value()),
+        _ => Err(Error::new_spanned(lit, "Expected integer literal")),
+    }
+}
+
+fn expect_bool(lit: &Lit) -> Result<bool, Error> {
+    match lit {
+        Lit::Bool(b) => Ok(b.value),
+        _ => Err(Error::new_spanned(lit, "Expected boolean literal")),
+    }
+}
+
 pub fn expand_derive_cmd_line_options(ast: &DeriveInput) -> Result<TokenStream, Vec<Error>> {
     let cont = OptContainer::from_ast(ast)?;
     if let Data::Struct(_) = ast.data {
         let struct_name = &ast.ident;
         let help_fn = cont.build_help_fn();
+        let option_names_fn = cont.build_option_names_fn();
         let parse_argv_fn = cont.build_parse_argv_fn()?;
         let parse_env_fn = cont.build_parse_env_fn()?;
+        let parse_config_fn = cont.build_parse_config_fn()?;
         Ok(quote! {
             impl CmdLineOptions for #struct_name {
                 #help_fn
+                #option_names_fn
                 #parse_argv_fn
                 #parse_env_fn
+                #parse_config_fn
             }
         })
     } else {
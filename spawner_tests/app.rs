@@ -129,6 +129,10 @@ fn main() {
                 let s = p.next();
                 (0..p.parse::<usize>()).for_each(|_| print!("{}", s));
             }
+            "eprint_n" => {
+                let s = p.next();
+                (0..p.parse::<usize>()).for_each(|_| eprint!("{}", s));
+            }
             "wake_controller" => wake_controller(),
             "try_write" => try_write(p.next(), p.next()),
             "exec_rest" => {
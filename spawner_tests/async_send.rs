@@ -0,0 +1,56 @@
+use spawner::async_send::{self, AsyncSender};
+use spawner::pipe;
+use spawner::ProgramMessage;
+
+use std::future::Future;
+use std::io::Read;
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn block_on<F: Future>(f: F) -> F::Output {
+    let mut f = Box::pin(f);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match f.as_mut().poll(&mut cx) {
+            Poll::Ready(v) => return v,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[test]
+fn send_message() {
+    let (sender, receiver) = channel();
+    let sender = AsyncSender::new(sender);
+    block_on(sender.send(ProgramMessage::Terminate)).unwrap();
+    assert!(matches!(receiver.try_recv(), Ok(ProgramMessage::Terminate)));
+
+    drop(receiver);
+    assert!(block_on(sender.send(ProgramMessage::Resume)).is_err());
+}
+
+#[test]
+fn write_to_pipe() {
+    let (mut r, w) = pipe::create().unwrap();
+    // More than a pipe buffer can hold, so the write completes only while being read.
+    let data = vec![b'1'; 1 << 20];
+    let reader = thread::spawn(move || {
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf).unwrap();
+        buf
+    });
+    let w = block_on(async_send::write(w, data.clone())).unwrap();
+    drop(w);
+    assert_eq!(reader.join().unwrap(), data);
+}
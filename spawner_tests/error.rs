@@ -52,7 +52,10 @@ fn run_single_controller_cmd(cmd: &str) -> Vec<Report> {
 #[test]
 fn invalid_agent_index() {
     let r = run_single_controller_cmd("10W#\n");
-    ensure_error(&r[0], "Agent index '10' is out of range");
+    ensure_error(
+        &r[0],
+        "Agent index '10' is out of range (controller message 0, payload crc32 00000000)",
+    );
 }
 
 #[test]
@@ -64,5 +67,5 @@ fn invalid_controller_command() {
 #[test]
 fn invalid_controller_command_2() {
     let r = run_single_controller_cmd("A\n");
-    ensure_error(&r[0], "Missing '#' in controller message");
+    ensure_error(&r[0], "Missing '#' in controller message at byte 1");
 }
@@ -11,11 +11,14 @@ cfg_if! {
         extern crate spawner;
         extern crate spawner_driver;
 
+        #[cfg(feature = "async")]
+        mod async_send;
         mod term_reason;
         mod redirect;
         mod env;
         mod protocol;
         mod other;
+        mod pipe;
         mod error;
         mod resource_usage;
     }
@@ -0,0 +1,148 @@
+use spawner::pipe::{self, BufReadPipe, PipeMetrics, ReadResult, ReadUntil, StallWatch};
+
+use std::io::{BufRead, Read, Write};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn read_to_buf_eof() {
+    let (mut r, mut w) = pipe::create().unwrap();
+    let mut buf = [0u8; 16];
+
+    w.write_all(b"abc").unwrap();
+    drop(w);
+    assert_eq!(r.read_to_buf(&mut buf).unwrap(), ReadResult::Data(3));
+    assert_eq!(&buf[..3], b"abc");
+    assert_eq!(r.read_to_buf(&mut buf).unwrap(), ReadResult::Eof);
+    assert_eq!(r.read_to_buf(&mut buf).unwrap(), ReadResult::Eof);
+}
+
+#[test]
+fn read_to_buf_empty_buffer() {
+    let (mut r, _w) = pipe::create().unwrap();
+    assert_eq!(r.read_to_buf(&mut []).unwrap(), ReadResult::Data(0));
+}
+
+#[test]
+fn pipe_metrics() {
+    let metrics = Arc::new(PipeMetrics::new());
+    let (r, w) = pipe::create().unwrap();
+    let mut r = r.with_metrics(metrics.clone());
+    let mut w = w.with_metrics(metrics.clone());
+
+    w.write_all(b"abc").unwrap();
+    w.write_all(b"defgh").unwrap();
+    assert_eq!(metrics.write_calls(), 2);
+    assert_eq!(metrics.bytes_written(), 8);
+    assert_eq!(metrics.bytes_read(), 0);
+
+    drop(w);
+    let mut buf = Vec::new();
+    r.read_to_end(&mut buf).unwrap();
+    assert_eq!(metrics.bytes_read(), 8);
+}
+
+#[test]
+fn write_all_msg_large_message() {
+    // Larger than any pipe buffer, so the writer has to wait for the reader.
+    let msg: Vec<u8> = (0..4 << 20).map(|i| (i % 251) as u8).collect();
+    let metrics = Arc::new(PipeMetrics::new());
+    let (mut r, w) = pipe::create().unwrap();
+    let mut w = w.with_metrics(metrics.clone());
+    let expected = msg.clone();
+    let writer = thread::spawn(move || w.write_all_msg(&msg).unwrap());
+
+    let mut received = Vec::new();
+    r.read_to_end(&mut received).unwrap();
+    writer.join().unwrap();
+    assert_eq!(received, expected);
+    assert_eq!(metrics.bytes_written(), expected.len() as u64);
+}
+
+#[test]
+fn buf_read_pipe_lines() {
+    let (r, mut w) = pipe::create().unwrap();
+    w.write_all(b"first\nsecond\nthird\n").unwrap();
+    drop(w);
+
+    let lines = BufReadPipe::with_capacity(4, r)
+        .lines()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(lines, ["first", "second", "third"]);
+}
+
+#[test]
+fn read_until_deadline_newline() {
+    let (mut r, mut w) = pipe::create().unwrap();
+    let writer = thread::spawn(move || {
+        w.write_all(b"e2").unwrap();
+        thread::sleep(Duration::from_millis(50));
+        w.write_all(b"e4\nnext\n").unwrap();
+        w
+    });
+    let line = r.read_until_deadline(Duration::from_secs(5)).unwrap();
+    assert_eq!(line.data, b"e2e4\n");
+    assert!(!line.timed_out);
+    let _w = writer.join().unwrap();
+    assert_eq!(
+        r.read_until_deadline(Duration::from_secs(5)).unwrap(),
+        ReadUntil {
+            data: b"next\n".to_vec(),
+            timed_out: false,
+        }
+    );
+}
+
+#[test]
+fn read_until_deadline_keeps_rest() {
+    let (mut r, mut w) = pipe::create().unwrap();
+    w.write_all(b"a\nb\nc").unwrap();
+    drop(w);
+    let line = r.read_until_deadline(Duration::from_secs(5)).unwrap();
+    assert_eq!(line.data, b"a\n");
+    let mut rest = Vec::new();
+    r.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, b"b\nc");
+}
+
+#[test]
+fn read_until_deadline_timeout() {
+    let (mut r, mut w) = pipe::create().unwrap();
+    w.write_all(b"e2").unwrap();
+    let start = Instant::now();
+    let line = r.read_until_deadline(Duration::from_millis(100)).unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    assert_eq!(line.data, b"e2");
+    assert!(line.timed_out);
+
+    drop(w);
+    assert_eq!(
+        r.read_until_deadline(Duration::from_secs(5)).unwrap(),
+        ReadUntil {
+            data: Vec::new(),
+            timed_out: false,
+        }
+    );
+}
+
+#[test]
+fn stall_watch_reports_full_pipe() {
+    let (tx, rx) = mpsc::channel();
+    let tx = Mutex::new(tx);
+    let (mut r, mut w) = pipe::create().unwrap();
+    w.set_stall_watch(StallWatch::new(Duration::from_millis(20), move |d| {
+        let _ = tx.lock().unwrap().send(d);
+    }));
+    let writer = thread::spawn(move || w.write_all(&[0u8; 1 << 20]).unwrap());
+
+    let stalled_for = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(stalled_for >= Duration::from_millis(20));
+
+    let mut received = Vec::new();
+    r.read_to_end(&mut received).unwrap();
+    writer.join().unwrap();
+    assert_eq!(received.len(), 1 << 20);
+}
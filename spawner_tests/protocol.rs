@@ -1,5 +1,5 @@
 use crate::assert_approx_eq;
-use crate::common::{read_all, TmpDir, APP, TIME_ERR};
+use crate::common::{read_all, write_all, TmpDir, APP, TIME_ERR};
 use crate::term_reason::{
     check_tr, ensure_idle_time_limit_exceeded, ensure_ok, ensure_user_time_limit_exceeded,
     ensure_wall_clock_time_limit_exceeded,
@@ -84,6 +84,61 @@ fn message_to_agent() {
     assert_eq!("message\n", read_all(stderr2));
 }
 
+#[test]
+fn switch_agent_stdin_encoding() {
+    let tmp = TmpDir::new();
+    let stderr = tmp.file("stderr.txt");
+
+    run(&[
+        "--separator=@",
+        "-d=1",
+        "--@",
+        "--controller",
+        APP,
+        "1W#\n1#a\n1N=utf16be#\n1#b\n",
+        "--@",
+        format!("--err={}", stderr).as_str(),
+        "--in=*0.stdout",
+        APP,
+        "pipe_loop",
+    ])
+    .unwrap();
+
+    assert_eq!("a\n\0b\0\n", read_all(stderr));
+}
+
+#[test]
+fn agent_stdin_from_file() {
+    let tmp = TmpDir::new();
+    let input = tmp.file("input.txt");
+    let stderr1 = tmp.file("stderr1.txt");
+    let stderr2 = tmp.file("stderr2.txt");
+    write_all(&input, "input\n");
+
+    run(&[
+        "--separator=@",
+        "-d=1",
+        "--@",
+        "--controller",
+        APP,
+        "1W#\n2W#\n1#dropped\n2#message\n",
+        "--@",
+        format!("--in={}", input).as_str(),
+        format!("--err={}", stderr1).as_str(),
+        APP,
+        "pipe_loop",
+        "--@",
+        "--in=*0.stdout",
+        format!("--err={}", stderr2).as_str(),
+        APP,
+        "pipe_loop",
+    ])
+    .unwrap();
+
+    assert_eq!("input\n", read_all(stderr1));
+    assert_eq!("message\n", read_all(stderr2));
+}
+
 #[test]
 fn message_from_agent() {
     let tmp = TmpDir::new();
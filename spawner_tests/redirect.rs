@@ -1,7 +1,11 @@
 use crate::common::{read_all, write_all, TmpDir, APP, SP};
 
+use spawner::process::ProcessInfo;
+use spawner::{InputRedirect, OutputRedirect, Program, Session, StdioMappingBuilder};
 use spawner_driver::run;
 
+use std::path::PathBuf;
+
 #[test]
 fn stdin_from_file() {
     let tmp = TmpDir::new();
@@ -107,6 +111,39 @@ fn stdout_to_file() {
     assert_eq!("AAA".repeat(20), read_all(output));
 }
 
+#[test]
+fn stdout_and_stderr_byte_counts() {
+    let tmp = TmpDir::new();
+    let out = |name: &str| tmp.file(name);
+    let reports = run(&[
+        format!("--out={}", out("out1.txt")).as_str(),
+        format!("--out={}", out("out2.txt")).as_str(),
+        format!("--err={}", out("err1.txt")).as_str(),
+        format!("--err={}", out("err2.txt")).as_str(),
+        APP,
+        "print_n",
+        "AAA",
+        "20",
+        "eprint_n",
+        "B",
+        "7",
+    ])
+    .unwrap();
+    assert_eq!(reports[0].stdout_bytes, Some(60));
+    assert_eq!(reports[0].stderr_bytes, Some(7));
+
+    // A stream going to a single file is written by the program directly.
+    let reports = run(&[
+        format!("--out={}", out("out3.txt")).as_str(),
+        APP,
+        "print_n",
+        "AAA",
+        "20",
+    ])
+    .unwrap();
+    assert_eq!(reports[0].stdout_bytes, None);
+}
+
 #[test]
 fn stdout_to_2_files() {
     let tmp = TmpDir::new();
@@ -263,3 +300,59 @@ fn multiple_stdouts_to_sp_stdout() {
     .unwrap();
     assert_eq!("aaaaaa", read_all(stdout).trim_end());
 }
+
+#[test]
+fn stdout_to_null() {
+    // 4MB is far beyond any pipe buffer, so this hangs unless output is discarded.
+    run(&["--out=*null", "--err=*null", APP, "print_n", "A", "4194304"]).unwrap();
+}
+
+#[test]
+fn stdout_to_null_and_file() {
+    let tmp = TmpDir::new();
+    let output = tmp.file("out.txt");
+    run(&[
+        "--out=*null",
+        format!("--out={}", output).as_str(),
+        APP,
+        "print_n",
+        "AAA",
+        "20",
+    ])
+    .unwrap();
+    assert_eq!("AAA".repeat(20), read_all(output));
+}
+
+#[test]
+fn stdio_mapping_builder() {
+    let tmp = TmpDir::new();
+    let input = PathBuf::from(tmp.file("in.txt"));
+    let output = PathBuf::from(tmp.file("out.txt"));
+    let mut sess = Session::new();
+    let program = || Program::new(ProcessInfo::new(APP));
+
+    let mapping = StdioMappingBuilder::new()
+        .stdin_from(InputRedirect::File(input))
+        .stdout_to(OutputRedirect::File(output))
+        .merge_stderr_into_stdout()
+        .build(&mut sess, program())
+        .unwrap();
+    let graph = sess.graph();
+    let stdin_srcs = graph.destination(mapping.stdin).unwrap().edges();
+    assert_eq!(stdin_srcs.len(), 1);
+    assert_ne!(stdin_srcs[0], mapping.stdout);
+    let stdout_dsts = graph.source(mapping.stdout).unwrap().edges();
+    assert_eq!(stdout_dsts.len(), 1);
+    assert_eq!(graph.source(mapping.stderr).unwrap().edges(), stdout_dsts);
+
+    assert!(StdioMappingBuilder::new()
+        .stderr_to(OutputRedirect::Null)
+        .stdout_to(OutputRedirect::Null)
+        .merge_stderr_into_stdout()
+        .build(&mut sess, program())
+        .is_err());
+    assert!(StdioMappingBuilder::new()
+        .merge_stderr_into_stdout()
+        .build(&mut sess, program())
+        .is_err());
+}